@@ -4,6 +4,56 @@ import { tableFromIPC } from "@apache-arrow/ts";
 async function doPlot() {
   doPlotGitHubPRDuration();
   doPlotGitHubClosedPRCount();
+  doPlotGitHubIssuesOpenedClosed();
+  doPlotGitHubDORA('/data/github/deployment_frequency.arrow', "#github_deployment_frequency", "deployments", "Successful deployments by week");
+  doPlotGitHubDORA('/data/github/lead_time_for_changes.arrow', "#github_lead_time_for_changes", "lead_time", "Median hours from commit to deployment");
+  doPlotGitHubDORA('/data/github/change_failure_rate.arrow', "#github_change_failure_rate", "change_failure_rate", "Percentage of deployments that failed", 100);
+  doPlotGitHubDORA('/data/github/time_to_restore.arrow', "#github_time_to_restore", "time_to_restore", "Median hours to restore service");
+}
+
+async function doPlotGitHubIssuesOpenedClosed() {
+  ({ range, startDate, endDate } = getDateRange());
+  const url = new URL('/data/github/issues_opened_closed.arrow', window.location.origin);
+  url.searchParams.append('start_date', startDate.toISOString());
+  url.searchParams.append('end_date', endDate.toISOString());
+
+  const data = await tableFromIPC(fetch(url))
+  const plot = Plot.plot({
+      style: "overflow: visible;",
+      y: { grid: true },
+      color: { legend: true },
+      marks: [
+        Plot.axisX({ label: "Week", ticks: 6 }),
+        Plot.ruleY([0]),
+        Plot.axisY({ label: "Issues opened (line) and closed (dashed)" }),
+        Plot.lineY(data, { x: "week", y: (d: any) => Number(d.opened), stroke: "repo", marker: true, tip: "x" }),
+        Plot.lineY(data, { x: "week", y: (d: any) => Number(d.closed), stroke: "repo", strokeDasharray: "4,2" }),
+      ],
+    })
+  const div = document.querySelector("#github_issues_opened_closed")
+  if (div) div.replaceChildren(plot)
+}
+
+async function doPlotGitHubDORA(path: string, selector: string, column: string, label: string, scale: number = 1) {
+  ({ range, startDate, endDate } = getDateRange());
+  const url = new URL(path, window.location.origin);
+  url.searchParams.append('start_date', startDate.toISOString());
+  url.searchParams.append('end_date', endDate.toISOString());
+
+  const data = await tableFromIPC(fetch(url))
+  const plot = Plot.plot({
+      style: "overflow: visible;",
+      y: { grid: true },
+      color: { legend: true },
+      marks: [
+        Plot.axisX({ label: "Week", ticks: 6 }),
+        Plot.ruleY([0]),
+        Plot.axisY({ label }),
+        Plot.lineY(data, { x: "week", y: (d: any) => Number(d[column]) * scale, stroke: "repo", marker: true, tip: "x" }),
+      ],
+    })
+  const div = document.querySelector(selector)
+  if (div) div.replaceChildren(plot)
 }
 
 async function doPlotGitHubPRDuration() {
@@ -5,7 +5,12 @@ async function doPlot() {
   ({ range, startDate, endDate } = getDateRange());
   const repos = getRepos();
 
-  const url = new URL('/data/github/closed_prs.arrow', window.location.origin);
+  // The data URL and axis label can be set on the chart element so that other sources can reuse
+  // this chart
+  const vis = document.querySelector<HTMLElement>("#vis");
+  const dataUrl = vis?.dataset.url ?? '/data/github/closed_prs.arrow';
+  const yLabel = vis?.dataset.yLabel ?? 'Count of closed PRs';
+  const url = new URL(dataUrl, window.location.origin);
   url.searchParams.append('start_date', startDate.toISOString());
   url.searchParams.append('end_date', endDate.toISOString());
   if (repos.excludedRepos.length > 0) {
@@ -27,7 +32,7 @@ async function doPlot() {
       marks: [
         Plot.axisX({ label: "Date", interval: xInterval, ticks: 6 }),
         Plot.ruleY([0]),
-        Plot.axisY({ label: `Count of closed PRs by ${xInterval}` }),
+        Plot.axisY({ label: `${yLabel} by ${xInterval}` }),
         // @ts-ignore
         Plot.rectY(data, Plot.binX({ y: "count" }, { x: "closed_at", interval: xInterval, fill: "repo", fx: "repo", tip: true })),
       ],
@@ -5,7 +5,10 @@ async function doPlot() {
   ({ range, startDate, endDate } = getDateRange());
   const repos = getRepos();
 
-  const url = new URL('/data/github/merged_pr_duration_rolling_daily_average.arrow', window.location.origin);
+  // The data URL can be set on the chart element so that other sources can reuse this chart
+  const dataUrl = document.querySelector<HTMLElement>("#vis")?.dataset.url
+    ?? '/data/github/merged_pr_duration_rolling_daily_average.arrow';
+  const url = new URL(dataUrl, window.location.origin);
   url.searchParams.append('start_date', startDate.toISOString());
   url.searchParams.append('end_date', endDate.toISOString());
   if (repos.excludedRepos.length > 0) {
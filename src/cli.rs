@@ -1,3 +1,6 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
@@ -37,8 +40,39 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// Maintain the database
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+
     /// Fetch the latest data from configured sources
-    Fetch {},
+    ///
+    /// Use `--since` to backfill the history of the sources between two dates instead of
+    /// fetching the latest data. Backfills that are interrupted resume where they left off
+    /// when run again with the same arguments.
+    Fetch {
+        /// Backfill data from this date (YYYY-MM-DD) onward, ignoring data already fetched
+        #[arg(long, value_name = "DATE")]
+        since: Option<NaiveDate>,
+
+        /// Backfill data up to and including this date (YYYY-MM-DD). Defaults to now.
+        #[arg(long, value_name = "DATE", requires = "since")]
+        until: Option<NaiveDate>,
+
+        /// Only backfill this repo (of the form `owner/name`) instead of all configured repos
+        #[arg(long, value_name = "REPO", requires = "since")]
+        repo: Option<String>,
+    },
+
+    /// Import incidents from a JSON or CSV file
+    ///
+    /// Each incident needs an `id`, `service`, and `created_at`, and can have a `severity`,
+    /// `acknowledged_at`, and `resolved_at`. Re-importing an incident replaces it.
+    ImportIncidents {
+        /// The path of the `.json` or `.csv` file to import
+        path: String,
+    },
 
     /// Create a new project in an new directory
     New {
@@ -46,10 +80,44 @@ pub enum Commands {
         path: String,
     },
 
+    /// Rebuild the normalized GitHub tables from the raw data
+    ///
+    /// The queries read from tables that are loaded from the raw data after each fetch.
+    /// Rebuilding them is only needed if they've been changed by hand or gotten out of sync.
+    Rebuild {},
+
     /// Serve the web app
-    /// 
+    ///
     /// The server should not be exposed directly to the Internet since it has not been
     /// hardened for that environment. Run a proxy in front of the server if you choose
     /// to expose it to the Internet.
     Serve {},
 }
+
+#[derive(Subcommand)]
+pub enum DbCommands {
+    /// Compact the raw data by removing the older versions of items that were fetched again
+    ///
+    /// Only the newest version of each item is kept. Rows without any newest items are
+    /// deleted and the other rows are rewritten with only their newest items.
+    Compact {
+        /// Archive the original rows to a Parquet file in this directory first. Defaults to
+        /// `compact.archive_dir` when it's configured.
+        #[arg(long, value_name = "DIR")]
+        archive_dir: Option<PathBuf>,
+    },
+
+    /// Run the database migrations that haven't been run yet
+    ///
+    /// Migrations are also run whenever the database is opened by the other commands. Use
+    /// `--dry-run` to check which migrations would run first, such as before upgrading a
+    /// shared database.
+    Migrate {
+        /// Print the migrations that would run without running them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Show the status of each database migration
+    Status {},
+}
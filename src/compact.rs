@@ -0,0 +1,298 @@
+//! Compact `wallowa_raw_data` by removing the older versions of items that were fetched again.
+//!
+//! Pages of items are stored whole each time any item on them changes, so the same items are
+//! stored many times over. Compaction keeps only the newest version of each item, the same
+//! version that is loaded into the normalized GitHub tables, so the queries are unaffected.
+//! Rows left without any newest items are deleted and the other rows are rewritten with only
+//! their newest items. The original rows can be archived to a Parquet file first.
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::Utc;
+use tracing::{debug, info};
+use wallowa_duckdb::Pool;
+
+use crate::github::load::load_raw_data;
+
+/// How the items of one kind of `wallowa_raw_data` row are compacted
+struct Compaction {
+    data_source: &'static str,
+    data_type: &'static str,
+    /// The key of the items array in each row, or `None` when the row is the array itself
+    items_key: Option<&'static str>,
+    /// The SQL expressions for the key that identifies each `item`
+    keys: &'static [&'static str],
+    /// The SQL expression for when each `item` was last updated, if items have one. The
+    /// newest row wins otherwise.
+    updated_at: Option<&'static str>,
+}
+
+/// The compacted rows, which match the rows loaded by [`crate::github::load`]
+const COMPACTIONS: [Compaction; 10] = [
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "pulls",
+        items_key: None,
+        keys: &["json_extract_string(item, '$.url')"],
+        updated_at: Some(UPDATED_AT),
+    },
+    Compaction {
+        data_source: "gitea_rest_api",
+        data_type: "pulls",
+        items_key: None,
+        keys: &["json_extract_string(item, '$.url')"],
+        updated_at: Some(UPDATED_AT),
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "pull_reviews",
+        items_key: None,
+        keys: &[HOST, "json_extract_string(item, '$.id')"],
+        updated_at: None,
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "pull_review_comments",
+        items_key: None,
+        keys: &[HOST, "json_extract_string(item, '$.id')"],
+        updated_at: None,
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "workflow_runs",
+        items_key: Some("workflow_runs"),
+        keys: &[
+            "json_extract_string(item, '$.url')",
+            "json_extract_string(item, '$.run_attempt')",
+        ],
+        updated_at: Some(UPDATED_AT),
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "deployments",
+        items_key: None,
+        keys: &["json_extract_string(item, '$.url')"],
+        updated_at: Some(UPDATED_AT),
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "deployment_statuses",
+        items_key: None,
+        keys: &[HOST, "json_extract_string(item, '$.id')"],
+        updated_at: None,
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "releases",
+        items_key: None,
+        keys: &[HOST, "json_extract_string(item, '$.id')"],
+        updated_at: None,
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "commits",
+        items_key: None,
+        keys: &[
+            "github_repo_name(metadata, (metadata->>'$.owner') || '/' || (metadata->>'$.repo'))",
+            "json_extract_string(item, '$.sha')",
+        ],
+        updated_at: None,
+    },
+    Compaction {
+        data_source: "github_rest_api",
+        data_type: "issues",
+        items_key: None,
+        keys: &["json_extract_string(item, '$.url')"],
+        updated_at: Some(UPDATED_AT),
+    },
+];
+
+/// The API host of a row, since items that are only identified by an `id` are keyed by the
+/// host as well
+const HOST: &str = "coalesce(metadata->>'$.host', 'api.github.com')";
+
+/// The `updated_at` of an item, parsed the same way as when it's loaded
+const UPDATED_AT: &str = r#"json_transform(item, '{"updated_at": "TIMESTAMP"}').updated_at"#;
+
+/// The results of a compaction
+#[derive(Debug, Default)]
+pub struct CompactStats {
+    /// The rows that were deleted since none of their items were the newest version
+    pub rows_deleted: usize,
+    /// The rows that were rewritten with only their newest items
+    pub rows_rewritten: usize,
+    /// The Parquet file that the original rows were archived to, if any
+    pub archive: Option<PathBuf>,
+}
+
+/// Compact `wallowa_raw_data`, archiving the original versions of the compacted rows to a
+/// Parquet file in `archive_dir` when given. The database is checkpointed afterwards so
+/// that the freed space can be reused.
+///
+/// Raw data that hasn't been loaded yet is loaded first so that the normalized tables are
+/// built from the same versions of the items that are kept.
+pub fn compact(pool: &Pool, archive_dir: Option<&Path>) -> Result<CompactStats> {
+    load_raw_data(pool)?;
+
+    let mut stats = CompactStats::default();
+    {
+        let mut conn = pool.get()?;
+        let tx = conn.transaction()?;
+
+        tx.execute_batch(
+            r#"
+CREATE OR REPLACE TEMPORARY TABLE wallowa_compacted (
+    raw_id INTEGER PRIMARY KEY,
+    "data" VARCHAR
+);"#,
+        )?;
+        for compaction in &COMPACTIONS {
+            let compacted = tx.execute(&compaction.statement(), [])?;
+            debug!(
+                "Compacting {compacted} {} rows of {}",
+                compaction.data_type, compaction.data_source
+            );
+        }
+
+        let compacted: usize =
+            tx.query_row("SELECT COUNT(*) FROM wallowa_compacted", [], |row| {
+                row.get(0)
+            })?;
+        if compacted > 0 {
+            if let Some(archive_dir) = archive_dir {
+                std::fs::create_dir_all(archive_dir)?;
+                let path = archive_dir.join(format!(
+                    "wallowa_raw_data_{}.parquet",
+                    Utc::now().format("%Y%m%dT%H%M%SZ")
+                ));
+                tx.execute(
+                    &format!(
+                        r#"
+COPY (
+    SELECT *
+    FROM wallowa_raw_data
+    WHERE id IN (SELECT raw_id FROM wallowa_compacted)
+    ORDER BY id
+) TO '{}' (FORMAT PARQUET)
+"#,
+                        path.to_string_lossy().replace('\'', "''")
+                    ),
+                    [],
+                )?;
+                stats.archive = Some(path);
+            }
+
+            stats.rows_deleted = tx.execute(
+                r#"
+DELETE FROM wallowa_raw_data
+WHERE id IN (SELECT raw_id FROM wallowa_compacted WHERE "data" IS NULL)
+"#,
+                [],
+            )?;
+            stats.rows_rewritten = tx.execute(
+                r#"
+UPDATE wallowa_raw_data
+SET "data" = wallowa_compacted."data"
+FROM wallowa_compacted
+WHERE wallowa_raw_data.id = wallowa_compacted.raw_id
+AND wallowa_compacted."data" NOT NULL
+"#,
+                [],
+            )?;
+        }
+
+        tx.execute_batch("DROP TABLE wallowa_compacted")?;
+        tx.commit()?;
+    }
+
+    // Checkpointing can't be done in a transaction
+    pool.get()?.execute_batch("CHECKPOINT")?;
+
+    if stats.rows_deleted > 0 || stats.rows_rewritten > 0 {
+        info!(
+            "Compacted the raw data by deleting {} rows and rewriting {} rows",
+            stats.rows_deleted, stats.rows_rewritten
+        );
+    } else {
+        info!("No raw data to compact");
+    }
+    Ok(stats)
+}
+
+impl Compaction {
+    /// The statement that inserts the compacted `data` of each of the rows with older versions
+    /// of items into `wallowa_compacted`. The `data` is `NULL` for rows without any newest
+    /// items.
+    fn statement(&self) -> String {
+        let (items, compacted) = match self.items_key {
+            Some(items_key) => (
+                format!(r#"json_extract("data", '$.{items_key}')"#),
+                format!(r#"json_merge_patch("data", json_object('{items_key}', newest_items))"#),
+            ),
+            None => (r#""data""#.to_string(), "newest_items".to_string()),
+        };
+        let order = match self.updated_at {
+            Some(updated_at) => format!("{updated_at} DESC, raw_id DESC"),
+            None => "raw_id DESC".to_string(),
+        };
+        // Items without a key can't be told apart, so they're all kept rather than ranked
+        // together as if they were versions of the same item
+        let key_is_null = self
+            .keys
+            .iter()
+            .map(|key| format!("{key} IS NULL"))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        format!(
+            r#"
+INSERT INTO wallowa_compacted
+WITH pages AS (
+    SELECT
+        id AS raw_id,
+        metadata,
+        "data",
+        from_json({items}, '["JSON"]') AS items,
+    FROM wallowa_raw_data
+    WHERE "data_source" = '{data_source}'
+    AND data_type = '{data_type}'
+    AND json_type({items}) = 'ARRAY'
+),
+items AS (
+    SELECT
+        raw_id,
+        metadata,
+        "data",
+        unnest(items) AS item,
+        unnest(generate_series(1, len(items))) AS position,
+    FROM pages
+),
+ranked AS (
+    SELECT
+        raw_id,
+        "data",
+        item,
+        position,
+        ({key_is_null}) OR row_number() OVER (PARTITION BY {keys} ORDER BY {order}) = 1 AS newest,
+    FROM items
+),
+compacted AS (
+    SELECT
+        raw_id,
+        any_value("data") AS "data",
+        to_json(list(item ORDER BY position) FILTER (WHERE newest)) AS newest_items,
+        bool_or(newest) AS has_newest,
+    FROM ranked
+    GROUP BY raw_id
+    HAVING NOT bool_and(newest)
+)
+SELECT
+    raw_id,
+    CASE WHEN has_newest THEN CAST({compacted} AS VARCHAR) END,
+FROM compacted
+"#,
+            data_source = self.data_source,
+            data_type = self.data_type,
+            keys = self.keys.join(", "),
+        )
+    }
+}
@@ -0,0 +1,195 @@
+//! Record the history of fetches from data sources in the `wallowa_fetch_run` table
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use wallowa_duckdb::{duckdb::params, Pool};
+
+tokio::task_local! {
+    /// The stats of the fetch run that the current task is part of, if any
+    static CURRENT_RUN: Arc<FetchRunStats>;
+}
+
+/// The stats collected while a fetch run is in progress
+#[derive(Debug, Default)]
+struct FetchRunStats {
+    /// The count of successful (2xx) responses, excluding retries and `304 Not Modified`
+    pages_fetched: AtomicU64,
+    /// The count of items stored in `wallowa_raw_data`, such as pull requests or commits
+    rows_inserted: AtomicU64,
+    /// The count of responses for each HTTP status code
+    http_statuses: Mutex<BTreeMap<u16, u64>>,
+}
+
+/// Run the `fetch` for a single `repo` of a `data_source`, recording the run in the
+/// `wallowa_fetch_run` table along with the stats collected by [`record_response`] and
+/// [`record_rows_inserted`] while it runs.
+pub async fn record_fetch_run<F, T>(
+    pool: &Pool,
+    data_source: &str,
+    repo: &str,
+    fetch: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let run_id = {
+        let conn = pool.get()?;
+        conn.query_row(
+            r#"
+INSERT INTO wallowa_fetch_run ("data_source", repo, started_at)
+VALUES (?, ?, ?)
+RETURNING id
+"#,
+            params![data_source, repo, Utc::now()],
+            |row| row.get::<_, i64>(0),
+        )?
+    };
+
+    let stats = Arc::new(FetchRunStats::default());
+    let result = CURRENT_RUN.scope(stats.clone(), fetch).await;
+
+    let http_statuses = {
+        let statuses = stats.http_statuses.lock().unwrap();
+        let statuses: BTreeMap<String, u64> = statuses
+            .iter()
+            .map(|(status, count)| (status.to_string(), *count))
+            .collect();
+        serde_json::to_string(&statuses)?
+    };
+    let error = result.as_ref().err().map(|e| format!("{e:#}"));
+
+    let conn = pool.get()?;
+    conn.execute(
+        r#"
+UPDATE wallowa_fetch_run
+SET finished_at = ?,
+    pages_fetched = ?,
+    rows_inserted = ?,
+    http_statuses = ?,
+    error = ?
+WHERE id = ?
+"#,
+        params![
+            Utc::now(),
+            stats.pages_fetched.load(Ordering::Relaxed),
+            stats.rows_inserted.load(Ordering::Relaxed),
+            http_statuses,
+            error,
+            run_id
+        ],
+    )?;
+
+    result
+}
+
+/// Record a response with the HTTP `status` code for the current fetch run, if there is one.
+/// Only successful responses count as fetched pages.
+pub fn record_response(status: u16) {
+    let _ = CURRENT_RUN.try_with(|run| {
+        if (200..300).contains(&status) {
+            run.pages_fetched.fetch_add(1, Ordering::Relaxed);
+        }
+        *run.http_statuses.lock().unwrap().entry(status).or_default() += 1;
+    });
+}
+
+/// Record that `count` items (pull requests or commits, for example) were inserted into
+/// `wallowa_raw_data` for the current fetch run, if there is one
+pub fn record_rows_inserted(count: u64) {
+    let _ = CURRENT_RUN.try_with(|run| {
+        run.rows_inserted.fetch_add(count, Ordering::Relaxed);
+    });
+}
+
+/// The number of items in a page of JSON results: the length of the page when it's an array,
+/// or the total length of its array fields when it's an object (like the `workflow_runs` of
+/// a page of workflow runs)
+pub fn count_items(page: &str) -> u64 {
+    match serde_json::from_str::<serde_json::Value>(page) {
+        Ok(serde_json::Value::Array(items)) => items.len() as u64,
+        Ok(serde_json::Value::Object(fields)) => fields
+            .values()
+            .filter_map(|value| value.as_array())
+            .map(|items| items.len() as u64)
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Return the time the most recent successful fetch from the `data_source` finished, if any
+pub fn latest_fetch_run(pool: &Pool, data_source: &str) -> Result<Option<DateTime<Utc>>> {
+    let conn = pool.get()?;
+
+    let finished_at = conn.query_row(
+        r#"
+SELECT max(finished_at) AS finished_at
+FROM wallowa_fetch_run
+WHERE "data_source" = ?
+AND error IS NULL
+"#,
+        [data_source],
+        |row| row.get::<_, Option<DateTime<Utc>>>(0),
+    )?;
+    Ok(finished_at)
+}
+
+/// The latest fetch results for a repo of a data source
+#[derive(Debug, Serialize)]
+pub struct RepoFetchStatus {
+    pub repo: String,
+    pub last_success_at: Option<NaiveDateTime>,
+    pub last_failure_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub last_pages_fetched: Option<u64>,
+    pub last_rows_inserted: Option<u64>,
+    pub last_http_statuses: Option<String>,
+}
+
+/// Select the latest fetch results for each repo of the `data_source`
+pub fn select_repo_fetch_statuses(pool: &Pool, data_source: &str) -> Result<Vec<RepoFetchStatus>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(
+        r#"
+SELECT
+    repo,
+    max(finished_at) FILTER (WHERE error IS NULL) AS last_success_at,
+    max(finished_at) FILTER (WHERE error IS NOT NULL) AS last_failure_at,
+    arg_max(error, finished_at) FILTER (WHERE error IS NOT NULL) AS last_error,
+    arg_max(pages_fetched, finished_at) AS last_pages_fetched,
+    arg_max(rows_inserted, finished_at) AS last_rows_inserted,
+    arg_max(CAST(http_statuses AS VARCHAR), finished_at) AS last_http_statuses
+FROM wallowa_fetch_run
+WHERE "data_source" = ?
+AND finished_at IS NOT NULL
+GROUP BY repo
+ORDER BY repo
+"#,
+    )?;
+    let rows = stmt.query_map([data_source], |row| {
+        Ok(RepoFetchStatus {
+            repo: row.get(0)?,
+            last_success_at: row.get(1)?,
+            last_failure_at: row.get(2)?,
+            last_error: row.get(3)?,
+            last_pages_fetched: row.get(4)?,
+            last_rows_inserted: row.get(5)?,
+            last_http_statuses: row.get(6)?,
+        })
+    })?;
+
+    let mut statuses = vec![];
+    for row in rows {
+        statuses.push(row?);
+    }
+    Ok(statuses)
+}
@@ -0,0 +1,351 @@
+use crate::{
+    config_value,
+    fetch_run::{record_fetch_run, record_rows_inserted},
+};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::{collections::HashSet, path::Path, process::Command};
+use tracing::{debug, error, info};
+use wallowa_duckdb::{duckdb::params, duckdb::OptionalExt, Pool};
+
+/// The `data_source` of the rows read from local git clones
+pub const DATA_SOURCE: &str = "local_git";
+
+/// The number of commits stored in each row of `wallowa_raw_data`
+const COMMITS_PER_ROW: usize = 1000;
+
+/// The `git log` format of each commit: a record separator followed by the fields of the
+/// commit separated by unit separators. The `--numstat` lines of the commit follow it.
+const LOG_FORMAT: &str = "--format=%x1e%H%x1f%P%x1f%an%x1f%ae%x1f%aI%x1f%cn%x1f%ce%x1f%cI%x1f%s";
+
+/// A commit read from `git log`
+#[derive(Debug, Serialize)]
+struct GitCommit {
+    sha: String,
+    parents: Vec<String>,
+    author_name: String,
+    author_email: String,
+    authored_at: String,
+    committer_name: String,
+    committer_email: String,
+    committed_at: String,
+    subject: String,
+    /// The total lines added across `files`
+    additions: u64,
+    /// The total lines deleted across `files`
+    deletions: u64,
+    files: Vec<GitFileChange>,
+}
+
+/// The lines changed in a single file by a commit. The line counts are `None` for binary files.
+#[derive(Debug, Serialize)]
+struct GitFileChange {
+    path: String,
+    additions: Option<u64>,
+    deletions: Option<u64>,
+}
+
+/// Read the commits of a local clone at `repo` that are new since the last fetch and store
+/// them in `wallowa_raw_data`.
+///
+/// Only the commits reachable from `HEAD` but not from the `HEAD` of the last fetch are read.
+/// When the last `HEAD` is no longer an ancestor of `HEAD` (after a force push, for example),
+/// the whole history is read and the commits that are already stored are skipped.
+pub async fn fetch_commits(pool: &Pool, repo: &str) -> Result<()> {
+    let head = git(repo, &["rev-parse", "--verify", "HEAD"]).await?;
+    let head = head.trim().to_string();
+
+    let last_head = select_last_head(pool, repo)?;
+    if last_head.as_deref() == Some(head.as_str()) {
+        debug!("No new commits in {repo}");
+        return Ok(());
+    }
+
+    let is_ancestor = match &last_head {
+        Some(last_head) => git(repo, &["merge-base", "--is-ancestor", last_head, &head])
+            .await
+            .is_ok(),
+        None => false,
+    };
+    let range = match &last_head {
+        Some(last_head) if is_ancestor => format!("{last_head}..{head}"),
+        _ => head.clone(),
+    };
+
+    info!("Reading commits {range} from {repo}");
+    let log = git(
+        repo,
+        &["log", "--no-renames", "--numstat", LOG_FORMAT, &range],
+    )
+    .await?;
+    let mut commits = parse_log(&log)?;
+
+    if last_head.is_some() && !is_ancestor {
+        let stored = select_stored_shas(pool, repo)?;
+        commits.retain(|commit| !stored.contains(&commit.sha));
+    }
+    debug!("Read {} new commits from {repo}", commits.len());
+
+    // The chunks are inserted in one transaction since each of them records the new `HEAD`, so
+    // storing only some of them would skip the rest of the commits on the next fetch
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    for chunk in commits.chunks(COMMITS_PER_ROW) {
+        tx.execute(
+            r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    'commits',
+    to_json({repo: ?, head: ?}),
+    ?
+)
+"#,
+            params![DATA_SOURCE, repo, head, serde_json::to_string(chunk)?],
+        )?;
+    }
+
+    if commits.is_empty() {
+        // Record the new `HEAD` so that the same range isn't read again
+        tx.execute(
+            r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    'commits',
+    to_json({repo: ?, head: ?}),
+    '[]'
+)
+"#,
+            params![DATA_SOURCE, repo, head],
+        )?;
+    }
+    tx.commit()?;
+    record_rows_inserted(commits.len() as u64);
+
+    Ok(())
+}
+
+/// Select the `HEAD` of a repo as of the last fetch, if it has been fetched
+fn select_last_head(pool: &Pool, repo: &str) -> Result<Option<String>> {
+    let conn = pool.get()?;
+
+    let head = conn
+        .query_row(
+            r#"
+SELECT (metadata->>'$.head') AS head
+FROM wallowa_raw_data
+WHERE "data_source" = ?
+AND data_type = 'commits'
+AND (metadata->>'$.repo') = ?
+ORDER BY created_at DESC, id DESC
+LIMIT 1
+"#,
+            params![DATA_SOURCE, repo],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(head)
+}
+
+/// Select the sha of every commit already stored for a repo
+fn select_stored_shas(pool: &Pool, repo: &str) -> Result<HashSet<String>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(
+        r#"
+SELECT DISTINCT unnest(json_transform("data", '[{"sha": "VARCHAR"}]')).sha AS sha
+FROM wallowa_raw_data
+WHERE "data_source" = ?
+AND data_type = 'commits'
+AND (metadata->>'$.repo') = ?
+"#,
+    )?;
+    let rows = stmt.query_map(params![DATA_SOURCE, repo], |row| row.get(0))?;
+    let mut shas = HashSet::new();
+    for row in rows {
+        shas.insert(row?);
+    }
+    Ok(shas)
+}
+
+/// Run `git` with the given `args` in the `repo` directory, returning its standard output.
+///
+/// `git` is run on a blocking thread since reading a large history can take a while.
+async fn git(repo: &str, args: &[&str]) -> Result<String> {
+    let repo = repo.to_string();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+
+    tokio::task::spawn_blocking(move || {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(&args)
+            .output()
+            .context("Error running git; is it installed?")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "`git {}` failed in {repo}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    })
+    .await?
+}
+
+/// Parse the output of `git log --numstat` in the [`LOG_FORMAT`] into commits
+fn parse_log(log: &str) -> Result<Vec<GitCommit>> {
+    let mut commits = vec![];
+    for record in log.split('\x1e').skip(1) {
+        let (header, numstat) = record.split_once('\n').unwrap_or((record, ""));
+        let fields: Vec<&str> = header.split('\x1f').collect();
+        let &[sha, parents, author_name, author_email, authored_at, committer_name, committer_email, committed_at, subject] =
+            fields.as_slice()
+        else {
+            return Err(anyhow!("Unexpected git log output: {header}"));
+        };
+
+        let mut files = vec![];
+        for line in numstat.lines().filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(3, '\t');
+            let (Some(additions), Some(deletions), Some(path)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(anyhow!("Unexpected git log --numstat output: {line}"));
+            };
+            files.push(GitFileChange {
+                path: path.to_string(),
+                // Binary files are listed with `-` instead of line counts
+                additions: additions.parse().ok(),
+                deletions: deletions.parse().ok(),
+            });
+        }
+
+        commits.push(GitCommit {
+            sha: sha.to_string(),
+            parents: parents.split_whitespace().map(String::from).collect(),
+            author_name: author_name.to_string(),
+            author_email: author_email.to_string(),
+            authored_at: authored_at.to_string(),
+            committer_name: committer_name.to_string(),
+            committer_email: committer_email.to_string(),
+            committed_at: committed_at.to_string(),
+            subject: subject.to_string(),
+            additions: files.iter().filter_map(|file| file.additions).sum(),
+            deletions: files.iter().filter_map(|file| file.deletions).sum(),
+            files,
+        });
+    }
+    Ok(commits)
+}
+
+/// Read the latest commits from all of the configured local git clones.
+///
+/// A failure to read one repo doesn't stop the other repos from being read; the first error
+/// is returned once all of the repos have been read.
+pub async fn fetch_all(pool: &Pool) -> Result<()> {
+    info!("Fetching from local git repos");
+
+    let repos: Vec<String> = config_value("git.repos").await?;
+
+    let mut first_error = None;
+    for repo in &repos {
+        if let Err(e) = fetch_repo(pool, repo).await {
+            error!("{e:#}");
+            first_error.get_or_insert(e);
+        }
+    }
+    info!("Fetching from local git repos complete");
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Read the commits of a single repo, recording the run in `wallowa_fetch_run`
+async fn fetch_repo(pool: &Pool, repo: &str) -> Result<()> {
+    let fetched = record_fetch_run(pool, DATA_SOURCE, repo, fetch_commits(pool, repo)).await;
+
+    fetched.with_context(|| format!("Error reading commits from {repo}"))
+}
+
+/// Check the local git configuration, returning whether any repos are configured to be read.
+/// Returns an error for repos that aren't directories.
+pub async fn validate_config() -> Result<bool> {
+    let repos: Vec<String> = config_value("git.repos").await?;
+    if repos.is_empty() {
+        return Ok(false);
+    }
+
+    for repo in &repos {
+        if !Path::new(repo).is_dir() {
+            return Err(anyhow!(
+                "Invalid repo {repo} in `git.repos`: not a directory"
+            ));
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_log_reads_commits_and_numstat() {
+        let log = "\x1eaaa\x1fppp\x1fAda\x1fada@example.com\x1f2023-10-01T00:00:00+00:00\x1fBob\x1fbob@example.com\x1f2023-10-02T00:00:00+00:00\x1fAdd a thing\n\n\
+                   3\t1\tsrc/lib.rs\n\
+                   -\t-\tlogo.png\n\
+                   \x1ebbb\x1fccc ddd\x1fAda\x1fada@example.com\x1f2023-10-03T00:00:00+00:00\x1fAda\x1fada@example.com\x1f2023-10-03T00:00:00+00:00\x1fMerge branch 'tab\there'\n";
+
+        let commits = parse_log(log).unwrap();
+
+        assert_eq!(commits.len(), 2);
+        let commit = &commits[0];
+        assert_eq!(commit.sha, "aaa");
+        assert_eq!(commit.parents, vec!["ppp"]);
+        assert_eq!(commit.author_email, "ada@example.com");
+        assert_eq!(commit.committer_name, "Bob");
+        assert_eq!(commit.committed_at, "2023-10-02T00:00:00+00:00");
+        assert_eq!(commit.subject, "Add a thing");
+        assert_eq!(commit.additions, 3);
+        assert_eq!(commit.deletions, 1);
+        assert_eq!(commit.files.len(), 2);
+        assert_eq!(commit.files[1].path, "logo.png");
+        assert_eq!(commit.files[1].additions, None);
+        assert_eq!(commit.files[1].deletions, None);
+
+        let merge = &commits[1];
+        assert_eq!(merge.parents, vec!["ccc", "ddd"]);
+        assert_eq!(merge.subject, "Merge branch 'tab\there'");
+        assert!(merge.files.is_empty());
+        assert_eq!(merge.additions, 0);
+    }
+
+    #[test]
+    fn parse_log_reads_empty_output() {
+        assert!(parse_log("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_log_rejects_unexpected_output() {
+        assert!(parse_log("\x1eaaa\x1fppp\n").is_err());
+        assert!(parse_log(
+            "\x1eaaa\x1f\x1fAda\x1fa\x1f2023\x1fAda\x1fa\x1f2023\x1fRoot\n\n3\tsrc/lib.rs\n"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,62 @@
+/// All functionality for reading commits from local git clones
+pub mod fetch;
+pub mod queries;
+pub mod web;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::{future::BoxFuture, FutureExt};
+use wallowa_duckdb::Pool;
+
+use crate::{
+    source::{NavEntry, Source},
+    web::AppState,
+};
+
+/// Commit history read directly from local git clones with the `git` CLI
+pub struct GitSource;
+
+impl Source for GitSource {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn label(&self) -> &'static str {
+        "Local git repos"
+    }
+
+    fn description(&self) -> &'static str {
+        r#"Collect commit history directly from local
+<a href="https://git-scm.com/" target="_blank" class="link" referrerpolicy="same-origin">git</a>
+clones, without any API tokens."#
+    }
+
+    fn data_source(&self) -> &'static str {
+        fetch::DATA_SOURCE
+    }
+
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>> {
+        fetch::validate_config().boxed()
+    }
+
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>> {
+        fetch::fetch_all(pool).boxed()
+    }
+
+    fn page_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::page_routes()
+    }
+
+    fn data_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::data_routes()
+    }
+
+    fn nav_entries(&self) -> Vec<NavEntry> {
+        vec![NavEntry {
+            children: vec![NavEntry::new("/git/commits", "Commits")],
+            ..NavEntry::new("/git", "Git")
+        }]
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, FixedOffset};
+use tracing::{debug, error};
+use wallowa_duckdb::duckdb::{params_from_iter, ToSql};
+use wallowa_duckdb::Pool;
+
+use super::fetch::DATA_SOURCE;
+
+/// Get the list of distinct local git repos (by their configured path) in the database
+pub fn select_distinct_repos(pool: &Pool) -> Result<Vec<String>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(
+        r#"
+SELECT DISTINCT (metadata->>'$.repo') AS repo
+FROM wallowa_raw_data
+WHERE "data_source" = ?
+AND data_type = 'commits'
+ORDER BY repo
+"#,
+    )?;
+    let rows = stmt.query_map([DATA_SOURCE], |row| row.get(0))?;
+    let mut repo_names = vec![];
+    for row in rows {
+        match row {
+            Ok(repo_name) => repo_names.push(repo_name),
+            Err(e) => error!("Error querying distinct git repos: {:?}", e),
+        }
+    }
+    Ok(repo_names)
+}
+
+/// The CTEs shared by the commit queries: `commits` (every stored commit), `repos` (the
+/// selected repos), and `deduped_commits` (each commit of the selected repos once)
+fn commits_ctes(repo_placeholders: &str) -> String {
+    format!(
+        r#"
+commits AS (
+    SELECT
+        (metadata->>'$.repo') AS repo,
+        unnest(json_transform("data",
+            '[{{
+                "sha": "VARCHAR",
+                "parents": ["VARCHAR"],
+                "author_email": "VARCHAR",
+                "committed_at": "TIMESTAMP",
+                "additions": "UBIGINT",
+                "deletions": "UBIGINT",
+                "files": [{{"path": "VARCHAR"}}]
+            }}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = '{DATA_SOURCE}'
+    AND data_type = 'commits'
+),
+repos AS (
+    {repo_placeholders}
+),
+deduped_commits AS (
+    SELECT
+        repo,
+        row.sha AS sha,
+        len(row.parents) > 1 AS is_merge,
+        row.author_email AS author_email,
+        row.committed_at AS committed_at,
+        row.additions AS additions,
+        row.deletions AS deletions,
+        len(row.files) AS files_changed,
+    FROM commits
+    WHERE repo IN (SELECT repo FROM repos)
+    QUALIFY row_number() OVER (PARTITION BY repo, row.sha) = 1
+)"#,
+        DATA_SOURCE = DATA_SOURCE,
+        repo_placeholders = repo_placeholders
+    )
+}
+
+/// Query the count of commits and of distinct commit authors by week
+pub fn commit_frequency(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `commit_frequency`");
+
+    query_commits(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+SELECT
+    CAST(date_trunc('week', committed_at) AS DATE) AS week,
+    repo,
+    COUNT(*) AS commits,
+    COUNT(DISTINCT author_email) AS authors
+FROM deduped_commits
+WHERE committed_at >= ?
+AND committed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the churn (lines added and deleted) and the number of files changed by week.
+/// Merge commits are not included since their changes are already counted in the commits
+/// being merged.
+pub fn code_churn(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `code_churn`");
+
+    query_commits(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+SELECT
+    CAST(date_trunc('week', committed_at) AS DATE) AS week,
+    repo,
+    CAST(SUM(additions) AS BIGINT) AS additions,
+    CAST(SUM(deletions) AS BIGINT) AS deletions,
+    CAST(SUM(files_changed) AS BIGINT) AS files_changed
+FROM deduped_commits
+WHERE NOT is_merge
+AND committed_at >= ?
+AND committed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the median lines changed (added plus deleted) per commit by week, not including
+/// merge commits
+pub fn lines_changed_per_commit(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `lines_changed_per_commit`");
+
+    query_commits(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+SELECT
+    CAST(date_trunc('week', committed_at) AS DATE) AS week,
+    repo,
+    CAST(median(additions + deletions) AS DOUBLE) AS lines_changed
+FROM deduped_commits
+WHERE NOT is_merge
+AND committed_at >= ?
+AND committed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Run the `select` statement after the commit common table expressions for the repos and
+/// date range
+fn query_commits(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    select: &str,
+) -> Result<Vec<RecordBatch>> {
+    let conn = pool.get()?;
+
+    let repo_placeholders = if repos.is_empty() {
+        "SELECT DISTINCT repo FROM commits".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    };
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH {ctes}\n{select}",
+        ctes = commits_ctes(&repo_placeholders),
+        select = select
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
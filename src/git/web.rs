@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use axum::{body::Body, extract::State, response::Html, routing::get, Router};
+use axum_extra::extract::Query;
+use minijinja::context;
+
+use crate::{
+    web::{date_range, render, to_arrow_ipc, AppState, ChartParams},
+    AppResult,
+};
+
+use super::queries::{
+    code_churn, commit_frequency, lines_changed_per_commit, select_distinct_repos,
+};
+
+/// All page-related routes for local git repos
+pub fn page_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/commits", get(git_commits))
+        .route("/", get(git_dashboard))
+}
+
+/// All data-related routes for local git repos
+pub fn data_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/commit_frequency.arrow", get(commit_frequency_arrow))
+        .route("/code_churn.arrow", get(code_churn_arrow))
+        .route(
+            "/lines_changed_per_commit.arrow",
+            get(lines_changed_per_commit_arrow),
+        )
+}
+
+async fn commit_frequency_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = commit_frequency(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn code_churn_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = code_churn(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn lines_changed_per_commit_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = lines_changed_per_commit(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn git_commits(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool)?;
+    let html = render(
+        state,
+        "git/commits.html",
+        context! {
+            current_nav => "/git/commits",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn git_dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let html = render(
+        state,
+        "git/index.html",
+        context! {
+            current_nav => "/git",
+        },
+    )?;
+    Ok(Html(html))
+}
@@ -0,0 +1,146 @@
+use crate::{
+    config_value,
+    fetch_run::record_fetch_run,
+    github::fetch::{fetch_pages, parse_repo_str, Api, GitHubRepo, PULLS_WATERMARK},
+};
+use anyhow::{anyhow, Context, Result};
+use futures::{stream, StreamExt};
+use tracing::{error, info};
+use wallowa_duckdb::Pool;
+
+/// Fetch pull requests for a specific repo.
+///
+/// The pulls API of Gitea pages through results the same way as GitHub's, so the pages are
+/// fetched and stored with the same watermark logic as GitHub pull requests.
+pub async fn fetch_pulls(pool: &Pool, repo: &GitHubRepo) -> Result<()> {
+    let per_page: String = config_value("gitea.per_page").await?;
+    let url = format!(
+        "{api_url}/repos/{owner}/{repo}/pulls?state=all&sort=recentupdate&limit={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        per_page = per_page,
+    );
+
+    fetch_pages(pool, repo, "pulls", &PULLS_WATERMARK, None, url).await
+}
+
+/// Fetch the latest data from Gitea
+///
+/// Repos are fetched concurrently, up to `gitea.max_concurrency` at a time. A failure to
+/// fetch one repo doesn't stop the other repos from being fetched; the first error is
+/// returned once all of the repos have been fetched.
+pub async fn fetch_all(pool: &Pool) -> Result<()> {
+    info!("Fetching from Gitea");
+
+    let api_url: Option<String> = config_value("gitea.api_url").await?;
+    let max_concurrency: usize = config_value("gitea.max_concurrency").await?;
+    let repo_strings: Vec<String> = config_value("gitea.repos").await?;
+
+    let mut first_error = None;
+    let mut repos = vec![];
+    for repo_string in &repo_strings {
+        match parse_gitea_repo_str(repo_string, api_url.as_deref()) {
+            Ok(repo) => repos.push(repo),
+            Err(e) => {
+                let e = e.context(format!("Error fetching {repo_string} from Gitea"));
+                error!("{e:#}");
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    // Each fetch owns its repo and a handle to the pool so that the futures don't borrow
+    // from `repos`, which keeps the resulting future `Send` for `tokio::spawn`
+    let repo_fetches = repos.into_iter().map(|repo| {
+        let pool = pool.clone();
+        async move { fetch_repo(&pool, &repo).await }
+    });
+    let results: Vec<Result<()>> = stream::iter(repo_fetches)
+        .buffer_unordered(max_concurrency.max(1))
+        .collect()
+        .await;
+
+    for result in results {
+        if let Err(e) = result {
+            error!("{e:#}");
+            first_error.get_or_insert(e);
+        }
+    }
+    info!("Fetching from Gitea complete");
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Fetch all of the data types for a single repo, recording the run in `wallowa_fetch_run`
+async fn fetch_repo(pool: &Pool, repo: &GitHubRepo) -> Result<()> {
+    let fetched = record_fetch_run(pool, repo.api.data_source(), &repo.to_string(), async {
+        fetch_pulls(pool, repo).await
+    })
+    .await;
+
+    fetched.with_context(|| format!("Error fetching {repo} from Gitea"))
+}
+
+/// Check the Gitea configuration, returning whether any repos are configured to be fetched.
+/// Returns an error for repos that can't be parsed, including repos without an API URL when
+/// `gitea.api_url` isn't set.
+pub async fn validate_config() -> Result<bool> {
+    let api_url: Option<String> = config_value("gitea.api_url").await?;
+    let repo_strings: Vec<String> = config_value("gitea.repos").await?;
+    if repo_strings.is_empty() {
+        return Ok(false);
+    }
+
+    for repo_string in &repo_strings {
+        parse_gitea_repo_str(repo_string, api_url.as_deref())
+            .with_context(|| format!("Invalid repo {repo_string} in `gitea.repos`"))?;
+    }
+
+    Ok(true)
+}
+
+/// Parse a repo string of the form `{owner}/{repo}` (or `{api_url}/{owner}/{repo}`) into a
+/// [`GitHubRepo`] fetched from the Gitea REST API at `api_url`
+fn parse_gitea_repo_str(repo: &str, api_url: Option<&str>) -> Result<GitHubRepo> {
+    match api_url {
+        Some(api_url) => parse_repo_str(repo, api_url, Api::Gitea),
+        None if repo.starts_with("https://") || repo.starts_with("http://") => {
+            parse_repo_str(repo, "", Api::Gitea)
+        }
+        None => Err(anyhow!(
+            "`gitea.api_url` is required for repos without an API URL"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gitea_repo_str_uses_the_configured_api_url() {
+        let repo =
+            parse_gitea_repo_str("owner/repo", Some("https://gitea.example.com/api/v1")).unwrap();
+        assert_eq!(repo.api, Api::Gitea);
+        assert_eq!(repo.api_url, "https://gitea.example.com/api/v1");
+        assert_eq!(repo.to_string(), "gitea.example.com/owner/repo");
+    }
+
+    #[test]
+    fn parse_gitea_repo_str_reads_the_api_url_of_a_repo() {
+        let repo = parse_gitea_repo_str("https://codeberg.org/api/v1/owner/repo", None).unwrap();
+        assert_eq!(repo.api_url, "https://codeberg.org/api/v1");
+        assert_eq!(repo.host, "codeberg.org");
+        assert_eq!(repo.api.data_source(), "gitea_rest_api");
+    }
+
+    #[test]
+    fn parse_gitea_repo_str_requires_an_api_url() {
+        assert!(parse_gitea_repo_str("owner/repo", None).is_err());
+        assert!(parse_gitea_repo_str("repo", Some("https://gitea.example.com/api/v1")).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+/// All Gitea-related functionality, including Forgejo (a fork of Gitea)
+pub mod fetch;
+pub mod web;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::{future::BoxFuture, FutureExt};
+use wallowa_duckdb::Pool;
+
+use crate::{
+    github::fetch::Api,
+    source::{NavEntry, Source},
+    web::AppState,
+};
+
+/// Pull Request activity from the REST API of a Gitea or Forgejo instance
+pub struct GiteaSource;
+
+impl Source for GiteaSource {
+    fn name(&self) -> &'static str {
+        "gitea"
+    }
+
+    fn label(&self) -> &'static str {
+        "Gitea"
+    }
+
+    fn description(&self) -> &'static str {
+        r#"Collect Pull Request activity data from a
+<a href="https://about.gitea.com/" target="_blank" class="link" referrerpolicy="same-origin">Gitea</a>
+or <a href="https://forgejo.org/" target="_blank" class="link" referrerpolicy="same-origin">Forgejo</a>
+instance."#
+    }
+
+    fn data_source(&self) -> &'static str {
+        Api::Gitea.data_source()
+    }
+
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>> {
+        fetch::validate_config().boxed()
+    }
+
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>> {
+        fetch::fetch_all(pool).boxed()
+    }
+
+    fn page_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::page_routes()
+    }
+
+    fn data_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::data_routes()
+    }
+
+    fn nav_entries(&self) -> Vec<NavEntry> {
+        vec![NavEntry {
+            children: vec![
+                NavEntry::new("/gitea/closed_pr_count", "Closed PR count"),
+                NavEntry::new("/gitea/pr_duration", "PR duration"),
+            ],
+            ..NavEntry::new("/gitea", "Gitea")
+        }]
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::{body::Body, extract::State, response::Html, routing::get, Router};
+use axum_extra::extract::Query;
+use minijinja::context;
+
+use crate::{
+    github::{
+        fetch::Api,
+        queries::{closed_prs, merged_pr_duration_rolling_daily_average, select_distinct_repos},
+    },
+    web::{date_range, render, to_arrow_ipc, AppState, ChartParams},
+    AppResult,
+};
+
+/// All page-related routes for Gitea
+pub fn page_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/pr_duration", get(gitea_pr_duration))
+        .route("/closed_pr_count", get(gitea_closed_pr_count))
+        .route("/", get(gitea_dashboard))
+}
+
+/// All data-related routes for Gitea
+pub fn data_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route(
+            "/merged_pr_duration_rolling_daily_average.arrow",
+            get(merged_pr_duration_rolling_daily_average_arrow),
+        )
+        .route("/closed_prs.arrow", get(closed_prs_arrow))
+}
+
+async fn merged_pr_duration_rolling_daily_average_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = merged_pr_duration_rolling_daily_average(
+        &state.read_pool,
+        Api::Gitea.data_source(),
+        start_date,
+        end_date,
+        &params.repo,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn closed_prs_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = closed_prs(
+        &state.read_pool,
+        Api::Gitea.data_source(),
+        start_date,
+        end_date,
+        &params.repo,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn gitea_pr_duration(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool, Api::Gitea.data_source())?;
+    let html = render(
+        state,
+        "gitea/pr_duration.html",
+        context! {
+            current_nav => "/gitea/pr_duration",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn gitea_closed_pr_count(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool, Api::Gitea.data_source())?;
+    let html = render(
+        state,
+        "gitea/pr_count.html",
+        context! {
+            current_nav => "/gitea/closed_pr_count",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn gitea_dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let html = render(
+        state,
+        "gitea/index.html",
+        context! {
+            current_nav => "/gitea",
+        },
+    )?;
+    Ok(Html(html))
+}
@@ -0,0 +1,157 @@
+use crate::config_value;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{
+    header::{HeaderValue, ACCEPT, AUTHORIZATION},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+/// The most recent GitHub App installation token, shared by every client
+static INSTALLATION_TOKEN: Mutex<Option<InstallationToken>> = Mutex::const_new(None);
+
+/// Installation tokens are refreshed once they are this close to expiring so that a token
+/// never expires partway through paging through results
+const REFRESH_BEFORE_EXPIRY_MINUTES: i64 = 10;
+
+/// An installation access token for a GitHub App, as returned by the REST API
+#[derive(Clone, Debug, Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// The claims of the JWT used to authenticate as a GitHub App
+#[derive(Debug, Serialize)]
+struct AppClaims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+/// Build the `Authorization` header value for a request to `url` on the GitHub REST API.
+///
+/// When a GitHub App is configured (`github.auth.app_id`), an installation token is minted
+/// and reused until it is close to expiring. The installation token is only sent to the host
+/// of `github.api_url` that it was issued by. Requests to other hosts use the
+/// `github.auth.token` if there is one, and aren't authenticated otherwise. Without an app,
+/// the `github.auth.token` is used for every host.
+pub async fn authorization_header(url: &str) -> Result<Option<HeaderValue>> {
+    let app_id: Option<String> = config_value("github.auth.app_id").await?;
+
+    let token = match app_id {
+        Some(app_id) => {
+            let api_url: String = config_value("github.api_url").await?;
+            if same_host(url, &api_url)? {
+                Some(installation_token(&app_id).await?)
+            } else {
+                config_value::<String>("github.auth.token").await.ok()
+            }
+        }
+        None => Some(config_value("github.auth.token").await?),
+    };
+
+    let Some(token) = token else {
+        return Ok(None);
+    };
+    let mut authz_value = HeaderValue::from_str(format!("Bearer {}", token).as_str())?;
+    authz_value.set_sensitive(true);
+    Ok(Some(authz_value))
+}
+
+/// Whether the URLs `a` and `b` are on the same host and port
+fn same_host(a: &str, b: &str) -> Result<bool> {
+    let (a, b) = (Url::parse(a)?, Url::parse(b)?);
+    Ok(a.host_str() == b.host_str() && a.port_or_known_default() == b.port_or_known_default())
+}
+
+/// Return a valid installation token for the GitHub App with the given `app_id`, exchanging
+/// a new JWT for a fresh token when there isn't one or the current one is about to expire
+async fn installation_token(app_id: &str) -> Result<String> {
+    // The lock is held while refreshing so that concurrent fetches share a single new token
+    let mut current = INSTALLATION_TOKEN.lock().await;
+    if let Some(token) = current.as_ref() {
+        if token.expires_at - Utc::now() > Duration::minutes(REFRESH_BEFORE_EXPIRY_MINUTES) {
+            return Ok(token.token.clone());
+        }
+    }
+
+    let token = fetch_installation_token(app_id).await?;
+    info!(
+        "Refreshed GitHub App installation token, expires at {}",
+        token.expires_at
+    );
+    *current = Some(token.clone());
+    Ok(token.token)
+}
+
+/// Exchange a JWT signed with the app's private key for a new installation token
+async fn fetch_installation_token(app_id: &str) -> Result<InstallationToken> {
+    let api_url: String = config_value("github.api_url").await?;
+    let installation_id: Option<String> = config_value("github.auth.installation_id").await?;
+    let installation_id = installation_id.ok_or_else(|| {
+        anyhow!("`github.auth.installation_id` is required when `github.auth.app_id` is set")
+    })?;
+
+    let jwt = app_jwt(app_id).await?;
+    let mut authz_value = HeaderValue::from_str(format!("Bearer {}", jwt).as_str())?;
+    authz_value.set_sensitive(true);
+
+    let url = format!(
+        "{api_url}/app/installations/{installation_id}/access_tokens",
+        api_url = api_url.trim_end_matches('/'),
+        installation_id = installation_id,
+    );
+    debug!("Requesting a GitHub App installation token from {url}");
+
+    let resp = reqwest::Client::new()
+        .post(&url)
+        .header(ACCEPT, "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header(AUTHORIZATION, authz_value)
+        .header(reqwest::header::USER_AGENT, "wallowa/0.2.0")
+        .send()
+        .await?;
+
+    let resp_status = resp.status();
+    if !resp_status.is_success() {
+        let text = resp.text().await?;
+        return Err(anyhow!(
+            "HTTP {resp_status}: '{text}' from request to {url}"
+        ));
+    }
+
+    Ok(resp.json::<InstallationToken>().await?)
+}
+
+/// Build a JWT that authenticates as the GitHub App, signed with the private key at
+/// `github.auth.private_key_path`
+async fn app_jwt(app_id: &str) -> Result<String> {
+    let private_key_path: Option<String> = config_value("github.auth.private_key_path").await?;
+    let private_key_path = private_key_path.ok_or_else(|| {
+        anyhow!("`github.auth.private_key_path` is required when `github.auth.app_id` is set")
+    })?;
+    let private_key = tokio::fs::read(&private_key_path)
+        .await
+        .with_context(|| format!("Unable to read GitHub App private key {private_key_path}"))?;
+    let key = EncodingKey::from_rsa_pem(&private_key)
+        .with_context(|| format!("Invalid GitHub App private key {private_key_path}"))?;
+
+    // Issue the JWT a minute in the past to allow for clock drift. GitHub rejects JWTs that
+    // expire more than 10 minutes in the future.
+    let now = Utc::now();
+    let claims = AppClaims {
+        iat: (now - Duration::seconds(60)).timestamp(),
+        exp: (now + Duration::minutes(9)).timestamp(),
+        iss: app_id.to_string(),
+    };
+
+    Ok(jsonwebtoken::encode(
+        &Header::new(Algorithm::RS256),
+        &claims,
+        &key,
+    )?)
+}
@@ -0,0 +1,185 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use super::{auth::authorization_header, fetch::Api};
+use crate::{config_value, fetch_run::record_response};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, RETRY_AFTER},
+    Client, Response, StatusCode, Url,
+};
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+/// The time at which the rate limit budget resets for each API host whose budget has been
+/// exhausted. Shared by every client since the budget belongs to the auth token rather than
+/// a client.
+static RATE_LIMIT_RESETS: Mutex<BTreeMap<String, DateTime<Utc>>> = Mutex::new(BTreeMap::new());
+
+/// The longest time to wait between retries, not including jitter
+const MAX_BACKOFF_SECS: u64 = 60;
+
+/// How long to wait after a secondary rate limit response that doesn't say how long to wait
+const SECONDARY_RATE_LIMIT_WAIT_SECS: u64 = 60;
+
+/// A client for the GitHub (or Gitea) REST API that waits out the rate limit and retries
+/// transient errors
+pub struct GitHubClient {
+    client: Client,
+    api: Api,
+    max_retries: u32,
+}
+
+impl GitHubClient {
+    /// Build a client for the `api` using the configured `github.max_retries` (or
+    /// `gitea.max_retries`)
+    pub async fn new(api: Api) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        let max_retries: u32 = match api {
+            Api::GitHub => {
+                headers.insert(
+                    ACCEPT,
+                    HeaderValue::from_str("application/vnd.github+json")?,
+                );
+                headers.insert("X-GitHub-Api-Version", HeaderValue::from_str("2022-11-28")?);
+                config_value("github.max_retries").await?
+            }
+            Api::Gitea => {
+                headers.insert(ACCEPT, HeaderValue::from_str("application/json")?);
+                // Public repos can be fetched without a token
+                let token: Option<String> = config_value("gitea.auth.token").await?;
+                if let Some(token) = token {
+                    let mut auth_value = HeaderValue::from_str(&format!("token {token}"))?;
+                    auth_value.set_sensitive(true);
+                    headers.insert(AUTHORIZATION, auth_value);
+                }
+                config_value("gitea.max_retries").await?
+            }
+        };
+
+        let client = reqwest::ClientBuilder::new()
+            .user_agent("wallowa/0.2.0")
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            api,
+            max_retries,
+        })
+    }
+
+    /// Make a GET request to `url` with the given additional `headers`.
+    ///
+    /// Waits until the rate limit resets when the budget is exhausted. Rate limited responses,
+    /// server errors, and network errors are retried up to `max_retries` times with exponential
+    /// backoff and jitter. The last response is returned once the retries run out so that the
+    /// caller can decide how to handle it.
+    pub async fn get(&self, url: &str, headers: HeaderMap) -> Result<Response> {
+        let host = Url::parse(url)?.host_str().unwrap_or_default().to_string();
+        let mut attempt = 0;
+        loop {
+            wait_for_rate_limit_reset(&host).await;
+
+            // The GitHub auth header is built for each attempt since GitHub App installation
+            // tokens expire and are refreshed while paging through results
+            let mut request = self.client.get(url).headers(headers.clone());
+            if self.api == Api::GitHub {
+                if let Some(authz_value) = authorization_header(url).await? {
+                    request = request.header(AUTHORIZATION, authz_value);
+                }
+            }
+            let result = request.send().await;
+            let retries_left = attempt < self.max_retries;
+
+            let resp = match result {
+                Ok(resp) => resp,
+                Err(e) if retries_left && (e.is_timeout() || e.is_connect() || e.is_request()) => {
+                    let wait = backoff(attempt);
+                    warn!("Error requesting {url}: {e}. Retrying in {wait:?}");
+                    sleep(wait).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            record_response(resp.status().as_u16());
+
+            let remaining = header_u64(resp.headers(), "x-ratelimit-remaining");
+            let reset = header_u64(resp.headers(), "x-ratelimit-reset")
+                .and_then(|reset| Utc.timestamp_opt(reset as i64, 0).single());
+            if let Some(remaining) = remaining {
+                debug!("GitHub API rate limit remaining: {remaining}, resets at {reset:?}");
+            }
+            if let (Some(0), Some(reset)) = (remaining, reset) {
+                // Hold off every request until the budget resets
+                RATE_LIMIT_RESETS
+                    .lock()
+                    .unwrap()
+                    .insert(host.clone(), reset);
+            }
+
+            let status = resp.status();
+            let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+                || (status == StatusCode::FORBIDDEN
+                    && (remaining == Some(0) || resp.headers().contains_key(RETRY_AFTER)));
+
+            if rate_limited && retries_left {
+                if let Some(retry_after) = header_u64(resp.headers(), RETRY_AFTER.as_str()) {
+                    info!("Rate limited by GitHub; retrying {url} in {retry_after} seconds");
+                    sleep(Duration::from_secs(retry_after)).await;
+                } else if remaining != Some(0) || reset.is_none() {
+                    // A rate limit without any guidance on how long to wait
+                    let wait =
+                        Duration::from_secs(SECONDARY_RATE_LIMIT_WAIT_SECS) + backoff(attempt);
+                    info!("Rate limited by GitHub; retrying {url} in {wait:?}");
+                    sleep(wait).await;
+                }
+                // Otherwise the next loop waits until the primary rate limit resets
+                attempt += 1;
+                continue;
+            }
+
+            if status.is_server_error() && retries_left {
+                let wait = backoff(attempt);
+                warn!("HTTP {status} from request to {url}. Retrying in {wait:?}");
+                sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+
+            return Ok(resp);
+        }
+    }
+}
+
+/// Sleep until the rate limit for `host` resets if its budget has been exhausted
+async fn wait_for_rate_limit_reset(host: &str) {
+    let reset = RATE_LIMIT_RESETS.lock().unwrap().get(host).copied();
+    if let Some(reset) = reset {
+        if let Ok(wait) = (reset - Utc::now()).to_std() {
+            info!("GitHub API rate limit exhausted; waiting until {reset} ({wait:?})");
+            // Wait an extra second since the reset time is rounded to the second
+            sleep(wait + Duration::from_secs(1)).await;
+        }
+        let mut resets = RATE_LIMIT_RESETS.lock().unwrap();
+        if resets.get(host) == Some(&reset) {
+            resets.remove(host);
+        }
+    }
+}
+
+/// The exponential backoff for a retry `attempt` (starting at 0) with up to 1 second of jitter
+fn backoff(attempt: u32) -> Duration {
+    let secs = 2u64.saturating_pow(attempt).min(MAX_BACKOFF_SECS);
+    // Random jitter spreads out the retries of concurrent fetches that failed together
+    let jitter_millis = rand::thread_rng().gen_range(0..1000);
+    Duration::from_secs(secs) + Duration::from_millis(jitter_millis)
+}
+
+/// Parse the header with the given `name` as a `u64`, if it exists
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
@@ -1,103 +1,494 @@
-use crate::config_value;
-use anyhow::{anyhow, Result};
+use super::client::GitHubClient;
+use crate::{
+    config_value,
+    fetch_run::{count_items, record_fetch_run, record_rows_inserted},
+};
+use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, LocalResult, TimeZone, Utc};
+use futures::{stream, StreamExt};
+use glob::Pattern;
 use reqwest::{
-    header::{
-        HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LINK,
-    },
-    StatusCode,
+    header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LINK},
+    StatusCode, Url,
 };
-use tracing::{debug, info};
+use serde::Deserialize;
+use std::fmt;
+use tracing::{debug, error, info};
 use wallowa_duckdb::{duckdb::params, duckdb::OptionalExt, Pool};
 
-/// Fetch pull requests for a specific owner+repo
-pub async fn fetch_pulls(pool: &Pool, owner: &str, repo: &str) -> Result<()> {
-    let github_api_token: String = config_value("github.auth.token").await?;
+/// The JSON structure and SQL expression used to find the timestamp that each
+/// item in a page of results was last changed at. This timestamp is used as the
+/// watermark for incremental fetching.
+pub(crate) struct Watermark {
+    /// The `json_transform_strict` structure for a page of results
+    structure: &'static str,
+    /// The key of the list of items when a page of results is an object instead of a list
+    items_key: Option<&'static str>,
+    /// The expression for the timestamp relative to the unnested `row`
+    expr: &'static str,
+}
+
+impl Watermark {
+    /// The SQL expression to unnest each item in a page of results into a `row`
+    fn unnest(&self) -> String {
+        match self.items_key {
+            Some(key) => format!(
+                r#"unnest(struct_extract(json_transform_strict("data", '{}'), '{}'))"#,
+                self.structure, key
+            ),
+            None => format!(
+                r#"unnest(json_transform_strict("data", '{}'))"#,
+                self.structure
+            ),
+        }
+    }
+}
+
+pub(crate) const PULLS_WATERMARK: Watermark = Watermark {
+    structure: r#"[{"updated_at": "TIMESTAMP"}]"#,
+    items_key: None,
+    expr: "row.updated_at",
+};
+
+const COMMITS_WATERMARK: Watermark = Watermark {
+    structure: r#"[{"commit": {"committer": {"date": "TIMESTAMP"}}}]"#,
+    items_key: None,
+    expr: "row.commit.committer.date",
+};
+
+const DEPLOYMENTS_WATERMARK: Watermark = Watermark {
+    structure: r#"[{"updated_at": "TIMESTAMP"}]"#,
+    items_key: None,
+    expr: "row.updated_at",
+};
+
+const RELEASES_WATERMARK: Watermark = Watermark {
+    structure: r#"[{"created_at": "TIMESTAMP"}]"#,
+    items_key: None,
+    expr: "row.created_at",
+};
+
+const ISSUES_WATERMARK: Watermark = Watermark {
+    structure: r#"[{"updated_at": "TIMESTAMP"}]"#,
+    items_key: None,
+    expr: "row.updated_at",
+};
+
+const WORKFLOW_RUNS_WATERMARK: Watermark = Watermark {
+    structure: r#"{"workflow_runs": [{"updated_at": "TIMESTAMP"}]}"#,
+    items_key: Some("workflow_runs"),
+    expr: "row.updated_at",
+};
+
+/// Workflow runs are backfilled by the time they were created to match the `created` filter
+/// of the workflow runs API
+const WORKFLOW_RUNS_CREATED_WATERMARK: Watermark = Watermark {
+    structure: r#"{"workflow_runs": [{"created_at": "TIMESTAMP"}]}"#,
+    items_key: Some("workflow_runs"),
+    expr: "row.created_at",
+};
+
+/// The host of the public GitHub REST API. Data fetched before the host was stored in the
+/// `metadata` of each row is from this host.
+pub const DEFAULT_HOST: &str = "api.github.com";
+
+/// The flavor of REST API that a repo is fetched from. Gitea (and Forgejo, a fork of Gitea)
+/// serves pull requests in nearly the same shape as GitHub so they share the fetching logic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Api {
+    GitHub,
+    Gitea,
+}
+
+impl Api {
+    /// The `data_source` of the rows fetched from this API
+    pub fn data_source(&self) -> &'static str {
+        match self {
+            Api::GitHub => "github_rest_api",
+            Api::Gitea => "gitea_rest_api",
+        }
+    }
+}
+
+/// A GitHub repo and the REST API (github.com, a GitHub Enterprise Server, or a Gitea
+/// instance) it's fetched from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitHubRepo {
+    pub api: Api,
+    /// The base URL of the REST API, like `https://api.github.com`
+    pub api_url: String,
+    /// The host (and port, if any) of the `api_url`
+    pub host: String,
+    pub owner: String,
+    pub name: String,
+}
+
+impl fmt::Display for GitHubRepo {
+    /// Format the repo the same way that repos are named in queries: `{owner}/{repo}` for
+    /// github.com and `{host}/{owner}/{repo}` for other hosts
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host == DEFAULT_HOST {
+            write!(f, "{}/{}", self.owner, self.name)
+        } else {
+            write!(f, "{}/{}/{}", self.host, self.owner, self.name)
+        }
+    }
+}
+
+/// A window of time to backfill. Backfills ignore the watermark of the data that has already
+/// been fetched and store every page of results with items in the window.
+#[derive(Debug, Clone, Copy)]
+pub struct Backfill {
+    /// The start of the window (inclusive)
+    pub since: DateTime<Utc>,
+    /// The end of the window (exclusive), or `None` for a window that ends now
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// The progress of backfilling one data type of a repo
+struct BackfillProgress {
+    /// The next page of results to fetch
+    next_url: Option<String>,
+    completed: bool,
+}
+
+/// Format a timestamp for the date filters of the GitHub REST API
+fn api_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Fetch pull requests for a specific repo, or backfill them over the `window`
+pub async fn fetch_pulls(pool: &Pool, repo: &GitHubRepo, window: Option<&Backfill>) -> Result<()> {
+    let per_page: String = config_value("github.per_page").await?;
+    let url = format!(
+        "{api_url}/repos/{owner}/{repo}/pulls?state=all&sort=updated&direction=desc&per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        per_page = per_page,
+    );
+
+    // Remember the watermark from before this fetch so that reviews can be fetched for
+    // every pull request that has been updated since then
+    let previous_watermark = match window {
+        Some(window) => window.since,
+        None => match select_watermark(pool, repo, "pulls", &PULLS_WATERMARK)? {
+            Some((_, watermark)) => watermark,
+            None => default_watermark(),
+        },
+    };
+
+    fetch_pages(pool, repo, "pulls", &PULLS_WATERMARK, window, url).await?;
+
+    if let Some(window) = window {
+        if backfill_completed(pool, repo, "pull_reviews", window)? {
+            return Ok(());
+        }
+    }
+
+    let pull_numbers = select_updated_since(
+        pool,
+        repo,
+        "pulls",
+        "number",
+        previous_watermark,
+        window.and_then(|window| window.until),
+    )?;
+    if !pull_numbers.is_empty() {
+        info!(
+            "Fetching reviews for {} updated pull requests in {repo}",
+            pull_numbers.len()
+        );
+    }
+    for pull_number in pull_numbers {
+        fetch_pull_reviews(pool, repo, pull_number).await?;
+    }
+
+    if let Some(window) = window {
+        update_backfill_progress(pool, repo, "pull_reviews", window, None)?;
+    }
+
+    Ok(())
+}
+
+/// Fetch the reviews and review comments for a specific pull request.
+///
+/// Reviews and review comments are not fetched incrementally. Every page is fetched
+/// each time the pull request is updated so that the latest state of each review is stored.
+pub async fn fetch_pull_reviews(pool: &Pool, repo: &GitHubRepo, pull_number: i64) -> Result<()> {
     let per_page: String = config_value("github.per_page").await?;
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        ACCEPT,
-        HeaderValue::from_str("application/vnd.github+json")?,
+    let reviews_url = format!(
+        "{api_url}/repos/{owner}/{repo}/pulls/{pull_number}/reviews?per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        pull_number = pull_number,
+        per_page = per_page,
     );
-    headers.insert("X-GitHub-Api-Version", HeaderValue::from_str("2022-11-28")?);
-    let mut authz_value = HeaderValue::from_str(format!("Bearer {}", github_api_token).as_str())?;
-    authz_value.set_sensitive(true);
-    headers.insert(AUTHORIZATION, authz_value);
+    fetch_all_pages(
+        pool,
+        repo,
+        "pull_number",
+        pull_number,
+        "pull_reviews",
+        reviews_url,
+    )
+    .await?;
 
-    let client = reqwest::ClientBuilder::new()
-        .user_agent("wallowa/0.2.0")
-        .default_headers(headers)
-        .build()?;
+    let comments_url = format!(
+        "{api_url}/repos/{owner}/{repo}/pulls/{pull_number}/comments?per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        pull_number = pull_number,
+        per_page = per_page,
+    );
+    fetch_all_pages(
+        pool,
+        repo,
+        "pull_number",
+        pull_number,
+        "pull_review_comments",
+        comments_url,
+    )
+    .await
+}
 
-    let mut url_opt = Some(format!(
-        "https://api.github.com/repos/{owner}/{repo}/pulls?state=all&sort=updated&direction=desc&per_page={per_page}",
-        owner = owner,
-        repo = repo,
+/// Fetch commits on the default branch for a specific repo, or backfill them over the `window`
+pub async fn fetch_commits(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    window: Option<&Backfill>,
+) -> Result<()> {
+    let per_page: String = config_value("github.per_page").await?;
+    let mut url = format!(
+        "{api_url}/repos/{owner}/{repo}/commits?per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
         per_page = per_page,
-    ));
+    );
 
-    let mut conn = pool.get()?;
+    if let Some(window) = window {
+        url.push_str(&format!("&since={}", api_timestamp(window.since)));
+        if let Some(until) = window.until {
+            url.push_str(&format!("&until={}", api_timestamp(until)));
+        }
+    }
 
-    // Select the most recent updated_at date and etag from raw_data
-    let watermark = conn
-        .query_row(
-            r#"
-WITH raw AS (
-    SELECT
-        metadata->>'$.etag' AS etag,
-        metadata->>'$.owner' AS "owner",
-        metadata->>'$.repo' AS repo,
-        unnest(json_transform_strict("data",
-            '[{
-                "updated_at": "TIMESTAMP",
-            }]')) AS row,
-    FROM wallowa_raw_data
-    WHERE "data_source" = 'github_rest_api'
-    AND data_type = 'pulls'
-    AND "owner" = ?
-    AND repo = ?
-    ORDER BY created_at DESC
-)
-SELECT etag, row.updated_at AS updated_at
-FROM raw
-ORDER BY updated_at DESC
-LIMIT 1
-"#,
-            params![owner, repo],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, DateTime<Utc>>(1)?)),
+    fetch_pages(pool, repo, "commits", &COMMITS_WATERMARK, window, url).await
+}
+
+/// Fetch GitHub Actions workflow runs for a specific repo, or backfill them over the `window`
+pub async fn fetch_workflow_runs(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    window: Option<&Backfill>,
+) -> Result<()> {
+    let per_page: String = config_value("github.per_page").await?;
+    let mut url = format!(
+        "{api_url}/repos/{owner}/{repo}/actions/runs?per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        per_page = per_page,
+    );
+
+    let watermark_def = match window {
+        Some(window) => {
+            let created = match window.until {
+                Some(until) => format!("{}..{}", api_timestamp(window.since), api_timestamp(until)),
+                None => format!(">={}", api_timestamp(window.since)),
+            };
+            url.push_str(&format!("&created={created}"));
+            &WORKFLOW_RUNS_CREATED_WATERMARK
+        }
+        None => &WORKFLOW_RUNS_WATERMARK,
+    };
+
+    fetch_pages(pool, repo, "workflow_runs", watermark_def, window, url).await
+}
+
+/// Fetch deployments for a specific repo, along with the statuses of each deployment
+/// that has been updated since the last fetch, or backfill them over the `window`
+pub async fn fetch_deployments(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    window: Option<&Backfill>,
+) -> Result<()> {
+    let per_page: String = config_value("github.per_page").await?;
+    let url = format!(
+        "{api_url}/repos/{owner}/{repo}/deployments?per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        per_page = per_page,
+    );
+
+    // Remember the watermark from before this fetch so that statuses can be fetched for
+    // every deployment that has been updated since then
+    let previous_watermark = match window {
+        Some(window) => window.since,
+        None => match select_watermark(pool, repo, "deployments", &DEPLOYMENTS_WATERMARK)? {
+            Some((_, watermark)) => watermark,
+            None => default_watermark(),
+        },
+    };
+
+    fetch_pages(
+        pool,
+        repo,
+        "deployments",
+        &DEPLOYMENTS_WATERMARK,
+        window,
+        url,
+    )
+    .await?;
+
+    if let Some(window) = window {
+        if backfill_completed(pool, repo, "deployment_statuses", window)? {
+            return Ok(());
+        }
+    }
+
+    let deployment_ids = select_updated_since(
+        pool,
+        repo,
+        "deployments",
+        "id",
+        previous_watermark,
+        window.and_then(|window| window.until),
+    )?;
+    for deployment_id in deployment_ids {
+        let statuses_url = format!(
+            "{api_url}/repos/{owner}/{repo}/deployments/{deployment_id}/statuses?per_page={per_page}",
+            api_url = repo.api_url,
+            owner = repo.owner,
+            repo = repo.name,
+            deployment_id = deployment_id,
+            per_page = per_page,
+        );
+        fetch_all_pages(
+            pool,
+            repo,
+            "deployment_id",
+            deployment_id,
+            "deployment_statuses",
+            statuses_url,
         )
-        .optional()?;
+        .await?;
+    }
+
+    if let Some(window) = window {
+        update_backfill_progress(pool, repo, "deployment_statuses", window, None)?;
+    }
+
+    Ok(())
+}
+
+/// Fetch releases for a specific repo, or backfill them over the `window`
+pub async fn fetch_releases(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    window: Option<&Backfill>,
+) -> Result<()> {
+    let per_page: String = config_value("github.per_page").await?;
+    let url = format!(
+        "{api_url}/repos/{owner}/{repo}/releases?per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        per_page = per_page,
+    );
+
+    fetch_pages(pool, repo, "releases", &RELEASES_WATERMARK, window, url).await
+}
+
+/// Fetch issues for a specific repo, or backfill them over the `window`. Pull requests are
+/// also returned by the issues endpoint. They're stored along with the issues so that the
+/// watermark covers them too, and are left out when the issues are loaded.
+pub async fn fetch_issues(pool: &Pool, repo: &GitHubRepo, window: Option<&Backfill>) -> Result<()> {
+    let per_page: String = config_value("github.per_page").await?;
+    let mut url = format!(
+        "{api_url}/repos/{owner}/{repo}/issues?state=all&sort=updated&direction=desc&per_page={per_page}",
+        api_url = repo.api_url,
+        owner = repo.owner,
+        repo = repo.name,
+        per_page = per_page,
+    );
+
+    if let Some(window) = window {
+        url.push_str(&format!("&since={}", api_timestamp(window.since)));
+    }
+
+    fetch_pages(pool, repo, "issues", &ISSUES_WATERMARK, window, url).await
+}
+
+/// Fetch every page of results starting at `url` for a specific repo and store
+/// each page in `wallowa_raw_data` with the given `data_type`.
+///
+/// Requests are made conditionally using the etag and latest timestamp (per `watermark`)
+/// already stored for the repo. Paging stops once a page has no items newer than
+/// the watermark.
+///
+/// When backfilling a `window`, the stored etag and watermark are ignored. Only pages with
+/// items in the window are stored and paging stops once a page has no items newer than the
+/// start of the window. The progress is recorded after each page so that an interrupted
+/// backfill resumes from the page it stopped at.
+///
+/// A 410 Gone response, which GitHub sends when a feature such as issues is disabled for the
+/// repo, is treated as having nothing to fetch.
+pub(crate) async fn fetch_pages(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    data_type: &str,
+    watermark_def: &Watermark,
+    window: Option<&Backfill>,
+    url: String,
+) -> Result<()> {
+    let client = GitHubClient::new(repo.api).await?;
+
+    let (watermark, mut url_opt) = match window {
+        Some(window) => match select_backfill_progress(pool, repo, data_type, window)? {
+            Some(progress) if progress.completed => {
+                info!("Backfill of {data_type} for {repo} already completed");
+                return Ok(());
+            }
+            Some(progress) => (None, Some(progress.next_url.unwrap_or(url))),
+            None => (None, Some(url)),
+        },
+        None => (
+            select_watermark(pool, repo, data_type, watermark_def)?,
+            Some(url),
+        ),
+    };
     let (etag, modified_since) = if let Some((inner_etag, inner_modified_since)) = watermark.clone()
     {
         (inner_etag, inner_modified_since)
     } else {
         (
             "".to_string(),
-            // This should never fail. If it does then default to about 10 years ago.
-            match Utc.timestamp_opt(0, 0) {
-                LocalResult::Single(default_watermark) => default_watermark,
-                LocalResult::Ambiguous(default_watermark, _) => default_watermark,
-                LocalResult::None => {
-                    debug!("Unexpected 'None' result from Utc.timestamp_opt(0, 0). Using 10 years ago as default watermark.");
-                    Utc::now() - chrono::Duration::days(3652)
-                }
-            },
+            window.map_or_else(default_watermark, |window| window.since),
         )
     };
+    let until = window.and_then(|window| window.until);
 
     while let Some(request_url) = url_opt {
-        let mut req_builder = client.get(&request_url);
+        let mut headers = HeaderMap::new();
         if watermark.is_some() {
             if !etag.clone().is_empty() {
-                req_builder = req_builder.header(IF_NONE_MATCH, etag.clone());
+                headers.insert(IF_NONE_MATCH, HeaderValue::from_str(&etag)?);
             }
-            req_builder = req_builder.header(IF_MODIFIED_SINCE, modified_since.to_string());
+            headers.insert(
+                IF_MODIFIED_SINCE,
+                HeaderValue::from_str(&modified_since.to_string())?,
+            );
         }
         info!("Making request to {request_url}");
-        debug!("Request for Github Pulls: {:?}", req_builder);
+        debug!("Request headers for Github {data_type}: {:?}", headers);
 
-        let resp = req_builder.send().await?;
+        let resp = client.get(&request_url, headers).await?;
 
         let resp_status = resp.status();
         let resp_headers = resp.headers().clone();
@@ -110,12 +501,20 @@ LIMIT 1
         let text = resp.text().await?;
 
         debug!(
-            "Response status code and etag from Github Pulls: {:?}, {:?}",
+            "Response status code and etag from Github {data_type}: {:?}, {:?}",
             resp_status, latest_etag
         );
         if resp_status == StatusCode::NOT_MODIFIED {
             // A 304, no need to further process the response
             return Ok(());
+        } else if resp_status == StatusCode::GONE {
+            // GitHub answers with a 410 when the feature is disabled for the repo, such as
+            // issues, so there's nothing to fetch
+            info!("GitHub {data_type} are disabled for {repo}");
+            if let Some(window) = window {
+                update_backfill_progress(pool, repo, data_type, window, None)?;
+            }
+            return Ok(());
         } else if resp_status.is_server_error() || resp_status.is_client_error() {
             // Error - stop making requests and bubble up the error
             return Err(anyhow!(
@@ -127,31 +526,42 @@ LIMIT 1
         // The data is inserted into the database to check whether any new data is in the response.
         // If new data is found, it is committed to the database.
         // If no new data is found, the insert is rolled back and the function completes.
+        let has_new_data = {
+            // The connection is only held while processing this page (and never across an `await`)
+            // so that other repos being fetched concurrently can use the pool in the meantime.
+            let mut conn = pool.get()?;
+            let tx = conn.transaction()?;
 
-        let tx = conn.transaction()?;
-
-        let mut insert_stmt = tx.prepare(
-            r#"
+            let row_id = tx.query_row(
+                r#"
 INSERT INTO wallowa_raw_data (
     "data_source",
     data_type,
     metadata,
     "data"
 ) VALUES (
-    'github_rest_api',
-    'pulls',
-    to_json({owner: ?, repo: ?, etag: ?}),
+    ?,
+    ?,
+    to_json({host: ?, owner: ?, repo: ?, etag: ?}),
     ?
 )
 RETURNING id
 "#,
-        )?;
-        let row_id = insert_stmt.query_row(params![owner, repo, latest_etag, text], |row| {
-            row.get::<_, i64>(0)
-        })?;
+                params![
+                    repo.api.data_source(),
+                    data_type,
+                    repo.host,
+                    repo.owner,
+                    repo.name,
+                    latest_etag,
+                    text
+                ],
+                |row| row.get::<_, i64>(0),
+            )?;
 
-        let mut query_stmt = tx.prepare(
-            r#"
+            let (has_new_data, in_window) = tx.query_row(
+                &format!(
+                    r#"
 -- Figure out if the newly inserted JSON object has any new updates in it.
 -- This approach doesn't require storing state external to the `wallowa_raw_data` table
 -- for tracking the latest data but causes increased query cost when fetching/inserting new data.
@@ -160,145 +570,888 @@ RETURNING id
 WITH raw AS (
     SELECT
         id,
-        metadata->>'$.owner' AS "owner",
-        metadata->>'$.repo' AS repo,
-        unnest(json_transform_strict("data",
-            '[{
-                "updated_at": "TIMESTAMP",
-                "base": {
-                    "repo": {
-                        "name": "VARCHAR",
-                        "owner": {
-                            "login": "VARCHAR"
-                        }
-                    }
-                },
-            }]')) AS row,
+        {unnest} AS row,
     FROM wallowa_raw_data
-    WHERE "data_source" = 'github_rest_api'
-    AND data_type = 'pulls'
-    AND "owner" = ?
-    AND repo = ?
-    ORDER BY created_at DESC
+    WHERE id = ?
 )
-SELECT COUNT(id) > 0
+SELECT
+    COUNT(id) > 0 AS has_new_data,
+    -- Backfills only store pages with items before the end of the window
+    COUNT(id) FILTER (WHERE {expr} < coalesce(CAST(? AS TIMESTAMP), 'infinity'::TIMESTAMP)) > 0 AS in_window
 FROM raw
-WHERE raw.row.updated_at >= ?
-AND id = ?
+WHERE {expr} >= ?
 "#,
-        )?;
+                    unnest = watermark_def.unnest(),
+                    expr = watermark_def.expr,
+                ),
+                params![row_id, until, modified_since],
+                |row| Ok((row.get::<_, bool>(0)?, row.get::<_, bool>(1)?)),
+            )?;
 
-        let save_new_data = query_stmt
-            .query_row(params![owner, repo, modified_since, row_id], |row| {
-                row.get::<_, bool>(0)
-            })?;
+            if in_window {
+                debug!("New data found for Github {data_type}; committing");
+                tx.commit()?;
+                record_rows_inserted(count_items(&text));
+            } else {
+                debug!("No new data found for Github {data_type}; rolling back");
+                tx.rollback()?;
+            }
 
-        if save_new_data {
-            debug!("New data found for Github Pulls; committing");
-            tx.commit()?;
+            has_new_data
+        };
 
+        if has_new_data {
             // Check for a `next` header in case of another page of results, but only when the
             // current page of results has new data
-            url_opt = match resp_headers.get(LINK) {
-                Some(link_header) => {
-                    let link_header_str = link_header.to_str()?;
-                    let res = parse_link_header::parse_with_rel(link_header_str);
-                    match res {
-                        Ok(links) => links.get("next").map(|next_link| next_link.raw_uri.clone()),
-                        Err(e) => {
-                            debug!("Error parsing link header: {}", e);
-                            None
-                        }
-                    }
-                }
-                None => None,
-            };
+            url_opt = next_page_url(&resp_headers)?;
         } else {
-            debug!("No new data found for Github Pulls; rolling back");
-            tx.rollback()?;
-
             // No need to fetch more pages since the latest data isn't new
             url_opt = None;
         }
+
+        if let Some(window) = window {
+            update_backfill_progress(pool, repo, data_type, window, url_opt.as_deref())?;
+        }
     }
 
     Ok(())
 }
 
-/// Fetch the latest data from Github
-pub async fn fetch_all(pool: &Pool) -> Result<DateTime<Utc>> {
-    let repos: Vec<String> = config_value("github.repos").await?;
-    info!("Fetching from GitHub");
-    for repo_string in repos {
-        let (owner, repo_name) = parse_repo_str(&repo_string)?;
-        fetch_pulls(pool, owner, repo_name).await?;
-    }
-
-    // TODO decide whether to work through the compiler error in order to add concurrency to these requests
-    // TODO make this configurable
-    /*
-    let github_request_max_concurrency = 2;
-    let repo_requests = repos.iter().map(|repo| {
-        let (owner, repo_name) = parse_repo_str(repo).expect(format!("Malformed repository string {}", repo).as_str());
-        fetch_pulls_2(pool, owner, repo_name)
-    });
-    let mut executed_requests =
-        futures::stream::iter(repo_requests)
-            .buffer_unordered(github_request_max_concurrency);
-    while executed_requests.next().await.is_some() {}
-    */
-    info!("Fetching from GitHub complete");
-    latest_fetch_all(pool)
+/// Fetch every page of results starting at `url` for a specific parent item (a pull request
+/// or deployment, for example) and store each non-empty page in `wallowa_raw_data` with the
+/// given `data_type`. The `parent_key` and `parent_id` are added to the `metadata` of each page.
+async fn fetch_all_pages(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    parent_key: &str,
+    parent_id: i64,
+    data_type: &str,
+    url: String,
+) -> Result<()> {
+    let client = GitHubClient::new(repo.api).await?;
+
+    let mut url_opt = Some(url);
+
+    while let Some(request_url) = url_opt {
+        info!("Making request to {request_url}");
+        let resp = client.get(&request_url, HeaderMap::new()).await?;
+
+        let resp_status = resp.status();
+        let resp_headers = resp.headers().clone();
+        let latest_etag = if let Some(response_etag) = resp_headers.get(ETAG) {
+            response_etag.to_str()?
+        } else {
+            ""
+        };
+
+        let text = resp.text().await?;
+
+        debug!(
+            "Response status code and etag from Github {data_type}: {:?}, {:?}",
+            resp_status, latest_etag
+        );
+        if resp_status.is_server_error() || resp_status.is_client_error() {
+            // Error - stop making requests and bubble up the error
+            return Err(anyhow!(
+                "HTTP {resp_status}: '{text}' from request to {request_url}"
+            ));
+        }
+
+        // Skip storing empty pages, such as for pull requests without reviews
+        if text.trim() != "[]" {
+            let conn = pool.get()?;
+            conn.execute(
+                &format!(
+                    r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    ?,
+    to_json({{host: ?, owner: ?, repo: ?, {parent_key}: ?, etag: ?}}),
+    ?
+)
+"#,
+                    parent_key = parent_key,
+                ),
+                params![
+                    repo.api.data_source(),
+                    data_type,
+                    repo.host,
+                    repo.owner,
+                    repo.name,
+                    parent_id,
+                    latest_etag,
+                    text
+                ],
+            )?;
+            record_rows_inserted(count_items(&text));
+        }
+
+        url_opt = next_page_url(&resp_headers)?;
+    }
+
+    Ok(())
 }
 
-/// Return the timestamp of the most recent Github API request that added data
-pub fn latest_fetch_all(pool: &Pool) -> Result<DateTime<Utc>> {
+/// Select the `id_key` (`number` or `id`, for example) of each item of `data_type` for a
+/// specific repo that has been updated after `since` (and before `until`, when given)
+fn select_updated_since(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    data_type: &str,
+    id_key: &str,
+    since: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Vec<i64>> {
     let conn = pool.get()?;
 
-    let watermark = conn
-        .query_row(
-            r#"
+    let mut stmt = conn.prepare(&format!(
+        r#"
 WITH raw AS (
     SELECT
+        coalesce(metadata->>'$.host', '{DEFAULT_HOST}') AS host,
+        metadata->>'$.owner' AS "owner",
+        metadata->>'$.repo' AS repo,
         unnest(json_transform_strict("data",
-            '[{
+            '[{{
+                "{id_key}": "BIGINT",
                 "updated_at": "TIMESTAMP",
-            }]')) AS row,
+            }}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = ?
+    AND data_type = ?
+    AND host = ?
+    AND "owner" = ?
+    AND repo = ?
+)
+SELECT DISTINCT row.{id_key} AS id
+FROM raw
+WHERE row.updated_at > ?
+AND row.updated_at < coalesce(CAST(? AS TIMESTAMP), 'infinity'::TIMESTAMP)
+ORDER BY id
+"#,
+        id_key = id_key,
+        DEFAULT_HOST = DEFAULT_HOST,
+    ))?;
+    let rows = stmt.query_map(
+        params![
+            repo.api.data_source(),
+            data_type,
+            repo.host,
+            repo.owner,
+            repo.name,
+            since,
+            until
+        ],
+        |row| row.get::<_, i64>(0),
+    )?;
+    let mut ids = vec![];
+    for row in rows {
+        ids.push(row?);
+    }
+    Ok(ids)
+}
+
+/// Select the progress of backfilling `data_type` for a specific repo over the `window`
+fn select_backfill_progress(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    data_type: &str,
+    window: &Backfill,
+) -> Result<Option<BackfillProgress>> {
+    let conn = pool.get()?;
+
+    let progress = conn
+        .query_row(
+            r#"
+SELECT next_url, completed_at IS NOT NULL AS completed
+FROM wallowa_backfill
+WHERE "data_source" = ?
+AND data_type = ?
+AND repo = ?
+AND since = ?
+AND "until" IS NOT DISTINCT FROM ?
+"#,
+            params![
+                repo.api.data_source(),
+                data_type,
+                repo.to_string(),
+                window.since,
+                window.until
+            ],
+            |row| {
+                Ok(BackfillProgress {
+                    next_url: row.get(0)?,
+                    completed: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+
+    Ok(progress)
+}
+
+/// Whether backfilling `data_type` for a specific repo over the `window` has been completed
+fn backfill_completed(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    data_type: &str,
+    window: &Backfill,
+) -> Result<bool> {
+    let progress = select_backfill_progress(pool, repo, data_type, window)?;
+    Ok(progress.is_some_and(|progress| progress.completed))
+}
+
+/// Record the `next_url` to fetch when backfilling `data_type` for a specific repo over the
+/// `window`. The backfill is recorded as completed when there is no `next_url`.
+fn update_backfill_progress(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    data_type: &str,
+    window: &Backfill,
+    next_url: Option<&str>,
+) -> Result<()> {
+    let completed_at = next_url.is_none().then(Utc::now);
+    if completed_at.is_some() {
+        info!("Backfill of {data_type} for {repo} completed");
+    }
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        r#"
+DELETE FROM wallowa_backfill
+WHERE "data_source" = ?
+AND data_type = ?
+AND repo = ?
+AND since = ?
+AND "until" IS NOT DISTINCT FROM ?
+"#,
+        params![
+            repo.api.data_source(),
+            data_type,
+            repo.to_string(),
+            window.since,
+            window.until
+        ],
+    )?;
+    tx.execute(
+        r#"
+INSERT INTO wallowa_backfill (
+    "data_source",
+    data_type,
+    repo,
+    since,
+    "until",
+    next_url,
+    completed_at
+) VALUES (?, ?, ?, ?, ?, ?, ?)
+"#,
+        params![
+            repo.api.data_source(),
+            data_type,
+            repo.to_string(),
+            window.since,
+            window.until,
+            next_url,
+            completed_at
+        ],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Select the etag and the most recent timestamp (per `watermark_def`) already stored
+/// for a specific repo and `data_type`
+fn select_watermark(
+    pool: &Pool,
+    repo: &GitHubRepo,
+    data_type: &str,
+    watermark_def: &Watermark,
+) -> Result<Option<(String, DateTime<Utc>)>> {
+    let conn = pool.get()?;
+
+    let watermark = conn
+        .query_row(
+            &format!(
+                r#"
+WITH raw AS (
+    SELECT
+        metadata->>'$.etag' AS etag,
+        coalesce(metadata->>'$.host', '{DEFAULT_HOST}') AS host,
+        metadata->>'$.owner' AS "owner",
+        metadata->>'$.repo' AS repo,
+        {unnest} AS row,
     FROM wallowa_raw_data
-    WHERE "data_source" = 'github_rest_api'
-    AND data_type = 'pulls'
+    WHERE "data_source" = ?
+    AND data_type = ?
+    AND host = ?
+    AND "owner" = ?
+    AND repo = ?
+    -- Rows stored from webhooks don't cover everything that changed before them. The
+    -- parentheses are needed since `->>` binds more loosely than `IS NULL`.
+    AND (metadata->>'$.webhook') IS NULL
     ORDER BY created_at DESC
 )
-SELECT row.updated_at AS updated_at
+SELECT etag, {expr} AS updated_at
 FROM raw
 ORDER BY updated_at DESC
 LIMIT 1
 "#,
-            params![],
-            |row| row.get::<_, DateTime<Utc>>(0),
+                unnest = watermark_def.unnest(),
+                expr = watermark_def.expr,
+                DEFAULT_HOST = DEFAULT_HOST,
+            ),
+            params![
+                repo.api.data_source(),
+                data_type,
+                repo.host,
+                repo.owner,
+                repo.name
+            ],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, DateTime<Utc>>(1)?)),
         )
         .optional()?;
-    if let Some(latest) = watermark {
-        Ok(latest)
-    } else {
-        let latest = match Utc.timestamp_opt(0, 0) {
-            LocalResult::Single(default_watermark) => default_watermark,
-            LocalResult::Ambiguous(default_watermark, _) => default_watermark,
-            LocalResult::None => {
-                debug!("Unexpected 'None' result from Utc.timestamp_opt(0, 0). Using 10 years ago as default watermark.");
-                Utc::now() - chrono::Duration::days(3652)
+
+    Ok(watermark)
+}
+
+/// Parse the `next` URL out of the `Link` header of a response, if there is one
+fn next_page_url(headers: &HeaderMap) -> Result<Option<String>> {
+    let next = match headers.get(LINK) {
+        Some(link_header) => {
+            let link_header_str = link_header.to_str()?;
+            let res = parse_link_header::parse_with_rel(link_header_str);
+            match res {
+                Ok(links) => links.get("next").map(|next_link| next_link.raw_uri.clone()),
+                Err(e) => {
+                    debug!("Error parsing link header: {}", e);
+                    None
+                }
             }
-        };
-        Ok(latest)
+        }
+        None => None,
+    };
+    Ok(next)
+}
+
+/// The watermark to use when nothing has been fetched yet
+fn default_watermark() -> DateTime<Utc> {
+    // This should never fail. If it does then default to about 10 years ago.
+    match Utc.timestamp_opt(0, 0) {
+        LocalResult::Single(default_watermark) => default_watermark,
+        LocalResult::Ambiguous(default_watermark, _) => default_watermark,
+        LocalResult::None => {
+            debug!("Unexpected 'None' result from Utc.timestamp_opt(0, 0). Using 10 years ago as default watermark.");
+            Utc::now() - chrono::Duration::days(3652)
+        }
+    }
+}
+
+/// Fetch the latest data from Github
+///
+/// Repos are fetched concurrently, up to `github.max_concurrency` at a time. A failure to
+/// fetch one repo doesn't stop the other repos from being fetched; the first error is
+/// returned once all of the repos have been fetched.
+pub async fn fetch_all(pool: &Pool) -> Result<()> {
+    info!("Fetching from GitHub");
+    fetch_repos(pool, None, None).await?;
+    info!("Fetching from GitHub complete");
+    Ok(())
+}
+
+/// Check the GitHub configuration, returning whether any repos or orgs are configured to be
+/// fetched. Returns an error for repos, orgs, or repo patterns that can't be parsed, or when
+/// no credentials are configured.
+pub async fn validate_config() -> Result<bool> {
+    let api_url: String = config_value("github.api_url").await?;
+    let repo_strings: Vec<String> = config_value("github.repos").await?;
+    let org_strings: Vec<String> = config_value("github.orgs").await?;
+    if repo_strings.is_empty() && org_strings.is_empty() {
+        return Ok(false);
+    }
+
+    for repo_string in &repo_strings {
+        parse_repo_str(repo_string, &api_url, Api::GitHub)
+            .with_context(|| format!("Invalid repo {repo_string} in `github.repos`"))?;
+    }
+    for org_string in &org_strings {
+        parse_org_str(org_string, &api_url)
+            .with_context(|| format!("Invalid org {org_string} in `github.orgs`"))?;
     }
+    let include_repos: Vec<String> = config_value("github.include_repos").await?;
+    let exclude_repos: Vec<String> = config_value("github.exclude_repos").await?;
+    glob_patterns(&include_repos)?;
+    glob_patterns(&exclude_repos)?;
+
+    let app_id: Option<String> = config_value("github.auth.app_id").await?;
+    if app_id.is_some() {
+        let installation_id: Option<String> = config_value("github.auth.installation_id").await?;
+        let private_key_path: Option<String> = config_value("github.auth.private_key_path").await?;
+        if installation_id.is_none() || private_key_path.is_none() {
+            return Err(anyhow!(
+                "`github.auth.installation_id` and `github.auth.private_key_path` are required when `github.auth.app_id` is set"
+            ));
+        }
+    } else if config_value::<String>("github.auth.token").await.is_err() {
+        return Err(anyhow!(
+            "Either `github.auth.token` or `github.auth.app_id` is required to fetch from GitHub"
+        ));
+    }
+
+    Ok(true)
 }
 
-/// Parse a repo string of the form `{owner}/{repo}` into a tuple of (owner, repo)
+/// Backfill the `window` from GitHub for all of the configured repos, or only for the
+/// `only_repo` string (of the form `{owner}/{repo}`) when given
+pub async fn backfill(pool: &Pool, window: Backfill, only_repo: Option<&str>) -> Result<()> {
+    info!(
+        "Backfilling from GitHub since {} until {}",
+        window.since,
+        window
+            .until
+            .map_or_else(|| "now".to_string(), |until| until.to_string())
+    );
+    fetch_repos(pool, Some(window), only_repo).await?;
+    info!("Backfilling from GitHub complete");
+
+    Ok(())
+}
+
+/// Fetch (or backfill the `window` for) all of the configured repos, or only the `only_repo`
+/// when given, up to `github.max_concurrency` at a time
+async fn fetch_repos(pool: &Pool, window: Option<Backfill>, only_repo: Option<&str>) -> Result<()> {
+    let api_url: String = config_value("github.api_url").await?;
+    let max_concurrency: usize = config_value("github.max_concurrency").await?;
+
+    if let Some(repo_string) = only_repo {
+        let repo = parse_repo_str(repo_string, &api_url, Api::GitHub)?;
+        return fetch_repo(pool, &repo, window.as_ref()).await;
+    }
+
+    let repo_strings: Vec<String> = config_value("github.repos").await?;
+    let mut first_error = None;
+
+    let mut repos = vec![];
+    for repo_string in &repo_strings {
+        match parse_repo_str(repo_string, &api_url, Api::GitHub) {
+            Ok(repo) => repos.push(repo),
+            Err(e) => {
+                let e = e.context(format!("Error fetching {repo_string} from GitHub"));
+                error!("{e:#}");
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    // Repos of the configured orgs are looked up at the start of each fetch so that new repos
+    // are picked up automatically. Each lookup is recorded as a run for the org so that
+    // failures show up on the Sources page even when no repos could be found.
+    let orgs: Vec<String> = config_value("github.orgs").await?;
+    for org_string in &orgs {
+        let discovered = record_fetch_run(
+            pool,
+            Api::GitHub.data_source(),
+            org_string,
+            discover_org_repos(org_string, &api_url),
+        )
+        .await
+        .with_context(|| format!("Error discovering the repos of {org_string} on GitHub"));
+        match discovered {
+            Ok(discovered) => {
+                for repo in discovered {
+                    if !repos.contains(&repo) {
+                        repos.push(repo);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{e:#}");
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    // Each fetch owns its repo and a handle to the pool so that the futures don't borrow
+    // from `repos`, which keeps the resulting future `Send` for `tokio::spawn`
+    let repo_fetches = repos.into_iter().map(|repo| {
+        let pool = pool.clone();
+        async move { fetch_repo(&pool, &repo, window.as_ref()).await }
+    });
+    let results: Vec<Result<()>> = stream::iter(repo_fetches)
+        .buffer_unordered(max_concurrency.max(1))
+        .collect()
+        .await;
+
+    for result in results {
+        if let Err(e) = result {
+            error!("{e:#}");
+            first_error.get_or_insert(e);
+        }
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Fetch (or backfill the `window` for) all of the data types for a single repo, recording
+/// the run in `wallowa_fetch_run`
+async fn fetch_repo(pool: &Pool, repo: &GitHubRepo, window: Option<&Backfill>) -> Result<()> {
+    let fetched = record_fetch_run(pool, repo.api.data_source(), &repo.to_string(), async {
+        fetch_pulls(pool, repo, window).await?;
+        fetch_commits(pool, repo, window).await?;
+        fetch_workflow_runs(pool, repo, window).await?;
+        fetch_deployments(pool, repo, window).await?;
+        fetch_releases(pool, repo, window).await?;
+        fetch_issues(pool, repo, window).await
+    })
+    .await;
+
+    fetched.with_context(|| format!("Error fetching {repo} from GitHub"))
+}
+
+/// The fields of a repo in the org repos API response used to filter the discovered repos
+#[derive(Debug, Deserialize)]
+struct OrgRepo {
+    name: String,
+    full_name: String,
+    archived: bool,
+    fork: bool,
+}
+
+/// List the repos of the org in `org_string` that pass the `github.include_repos`,
+/// `github.exclude_repos`, `github.exclude_archived`, and `github.exclude_forks` filters
+async fn discover_org_repos(org_string: &str, default_api_url: &str) -> Result<Vec<GitHubRepo>> {
+    let include_repos: Vec<String> = config_value("github.include_repos").await?;
+    let exclude_repos: Vec<String> = config_value("github.exclude_repos").await?;
+    let exclude_archived: bool = config_value("github.exclude_archived").await?;
+    let exclude_forks: bool = config_value("github.exclude_forks").await?;
+    let per_page: String = config_value("github.per_page").await?;
+
+    let include_patterns = glob_patterns(&include_repos)?;
+    let exclude_patterns = glob_patterns(&exclude_repos)?;
+
+    let client = GitHubClient::new(Api::GitHub).await?;
+    let mut repos = vec![];
+
+    let (api_url, org) = parse_org_str(org_string, default_api_url)?;
+    let mut url_opt = Some(format!(
+        "{api_url}/orgs/{org}/repos?type=all&per_page={per_page}",
+        api_url = api_url,
+        org = org,
+        per_page = per_page,
+    ));
+
+    while let Some(request_url) = url_opt {
+        info!("Making request to {request_url}");
+        let resp = client.get(&request_url, HeaderMap::new()).await?;
+
+        let resp_status = resp.status();
+        let resp_headers = resp.headers().clone();
+        let text = resp.text().await?;
+        if resp_status.is_server_error() || resp_status.is_client_error() {
+            return Err(anyhow!(
+                "HTTP {resp_status}: '{text}' from request to {request_url}"
+            ));
+        }
+
+        let org_repos: Vec<OrgRepo> = serde_json::from_str(&text)
+            .with_context(|| format!("Unexpected response from request to {request_url}"))?;
+        for org_repo in org_repos {
+            if is_repo_included(
+                &org_repo,
+                &include_patterns,
+                &exclude_patterns,
+                exclude_archived,
+                exclude_forks,
+            ) {
+                repos.push(github_repo(Api::GitHub, api_url, org, &org_repo.name)?);
+            }
+        }
+
+        url_opt = next_page_url(&resp_headers)?;
+    }
+
+    info!("Discovered {} repos in the {org_string} org", repos.len());
+    Ok(repos)
+}
+
+/// Whether the `org_repo` matches any of the `include_patterns` (or there are none) and none
+/// of the exclusions. An exclusion wins over a matching include pattern.
+fn is_repo_included(
+    org_repo: &OrgRepo,
+    include_patterns: &[Pattern],
+    exclude_patterns: &[Pattern],
+    exclude_archived: bool,
+    exclude_forks: bool,
+) -> bool {
+    let included = include_patterns.is_empty()
+        || include_patterns
+            .iter()
+            .any(|pattern| pattern.matches(&org_repo.full_name));
+    let excluded = exclude_patterns
+        .iter()
+        .any(|pattern| pattern.matches(&org_repo.full_name))
+        || (exclude_archived && org_repo.archived)
+        || (exclude_forks && org_repo.fork);
+    included && !excluded
+}
+
+/// Compile each of the glob `patterns`
+fn glob_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).with_context(|| format!("Invalid repo pattern {pattern}"))
+        })
+        .collect()
+}
+
+/// Parse a repo string of the form `{owner}/{repo}` into a [`GitHubRepo`] fetched from the
+/// `default_api_url` of the `api`. A repo on another host can be given with the API URL
+/// prepended, like `https://github.example.com/api/v3/{owner}/{repo}`.
+///
 /// Returns an error if the string is not in the correct format
-fn parse_repo_str(repo: &str) -> anyhow::Result<(&str, &str)> {
-    let parts: Vec<&str> = repo.split('/').collect();
-    if parts.len() != 2 {
-        anyhow::bail!("Repo string must be of the form {{owner}}/{{repo}}");
+pub(crate) fn parse_repo_str(
+    repo: &str,
+    default_api_url: &str,
+    api: Api,
+) -> anyhow::Result<GitHubRepo> {
+    let (api_url, owner_repo) = if repo.starts_with("https://") || repo.starts_with("http://") {
+        let mut parts = repo.trim_end_matches('/').rsplitn(3, '/');
+        let name = parts.next().unwrap_or_default();
+        let owner = parts.next().unwrap_or_default();
+        let api_url = parts.next().unwrap_or_default();
+        (api_url, format!("{owner}/{name}"))
+    } else {
+        (default_api_url, repo.to_string())
+    };
+
+    let parts: Vec<&str> = owner_repo.split('/').collect();
+    if parts.len() != 2 || parts.iter().any(|part| part.is_empty()) {
+        anyhow::bail!(
+            "Repo string must be of the form {{owner}}/{{repo}} or {{api_url}}/{{owner}}/{{repo}}"
+        );
+    }
+
+    github_repo(api, api_url, parts[0], parts[1])
+}
+
+/// Parse an org string of the form `{org}` (or `{api_url}/{org}` for an org on another GitHub
+/// host) into a tuple of (api_url, org)
+fn parse_org_str<'a>(org: &'a str, default_api_url: &'a str) -> anyhow::Result<(&'a str, &'a str)> {
+    let org = org.trim_end_matches('/');
+    let (api_url, org_name) = if org.starts_with("https://") || org.starts_with("http://") {
+        org.rsplit_once('/').unwrap_or_default()
+    } else {
+        (default_api_url, org)
+    };
+
+    if org_name.is_empty() || org_name.contains('/') {
+        anyhow::bail!("Org string must be of the form {{org}} or {{api_url}}/{{org}}");
+    }
+    Ok((api_url.trim_end_matches('/'), org_name))
+}
+
+/// Build a [`GitHubRepo`] for the `owner`/`name` repo fetched from the `api` at `api_url`
+fn github_repo(api: Api, api_url: &str, owner: &str, name: &str) -> anyhow::Result<GitHubRepo> {
+    let api_url = api_url.trim_end_matches('/');
+    let parsed_url = Url::parse(api_url).with_context(|| format!("Invalid API URL {api_url}"))?;
+    let host = match (parsed_url.host_str(), parsed_url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => anyhow::bail!("API URL {api_url} is missing a host"),
+    };
+
+    Ok(GitHubRepo {
+        api,
+        api_url: api_url.to_string(),
+        host,
+        owner: owner.to_string(),
+        name: name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn org_repo(full_name: &str, archived: bool, fork: bool) -> OrgRepo {
+        OrgRepo {
+            name: full_name.split_once('/').unwrap().1.to_string(),
+            full_name: full_name.to_string(),
+            archived,
+            fork,
+        }
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<Pattern> {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        glob_patterns(&patterns).unwrap()
+    }
+
+    #[test]
+    fn parse_repo_str_reads_a_github_com_repo() {
+        let repo = parse_repo_str("owner/repo", "https://api.github.com", Api::GitHub).unwrap();
+        assert_eq!(
+            repo,
+            GitHubRepo {
+                api: Api::GitHub,
+                api_url: "https://api.github.com".to_string(),
+                host: "api.github.com".to_string(),
+                owner: "owner".to_string(),
+                name: "repo".to_string(),
+            }
+        );
+        assert_eq!(repo.to_string(), "owner/repo");
+    }
+
+    #[test]
+    fn parse_repo_str_reads_a_repo_on_another_host() {
+        let repo = parse_repo_str(
+            "https://ghe.example.com/api/v3/owner/repo",
+            "https://api.github.com",
+            Api::GitHub,
+        )
+        .unwrap();
+        assert_eq!(repo.api_url, "https://ghe.example.com/api/v3");
+        assert_eq!(repo.host, "ghe.example.com");
+        assert_eq!(repo.owner, "owner");
+        assert_eq!(repo.name, "repo");
+        assert_eq!(repo.to_string(), "ghe.example.com/owner/repo");
+
+        let with_slash = parse_repo_str(
+            "https://ghe.example.com/api/v3/owner/repo/",
+            "https://api.github.com",
+            Api::GitHub,
+        )
+        .unwrap();
+        assert_eq!(with_slash, repo);
+    }
+
+    #[test]
+    fn parse_repo_str_keeps_the_port_in_the_host() {
+        let repo = parse_repo_str(
+            "owner/repo",
+            "http://ghe.example.com:8080/api/v3/",
+            Api::GitHub,
+        )
+        .unwrap();
+        assert_eq!(repo.api_url, "http://ghe.example.com:8080/api/v3");
+        assert_eq!(repo.host, "ghe.example.com:8080");
+        assert_eq!(repo.to_string(), "ghe.example.com:8080/owner/repo");
+    }
+
+    #[test]
+    fn parse_repo_str_rejects_invalid_repos() {
+        let api_url = "https://api.github.com";
+        assert!(parse_repo_str("repo", api_url, Api::GitHub).is_err());
+        assert!(parse_repo_str("owner/repo/extra", api_url, Api::GitHub).is_err());
+        assert!(parse_repo_str("owner/", api_url, Api::GitHub).is_err());
+        assert!(parse_repo_str("https://owner/repo", api_url, Api::GitHub).is_err());
+        assert!(parse_repo_str("owner/repo", "not a url", Api::GitHub).is_err());
+    }
+
+    #[test]
+    fn parse_org_str_reads_a_bare_org() {
+        assert_eq!(
+            parse_org_str("acme", "https://api.github.com").unwrap(),
+            ("https://api.github.com", "acme")
+        );
+        assert_eq!(
+            parse_org_str("acme/", "https://api.github.com/").unwrap(),
+            ("https://api.github.com", "acme")
+        );
+    }
+
+    #[test]
+    fn parse_org_str_reads_an_org_on_another_host() {
+        assert_eq!(
+            parse_org_str(
+                "https://ghe.example.com/api/v3/acme/",
+                "https://api.github.com"
+            )
+            .unwrap(),
+            ("https://ghe.example.com/api/v3", "acme")
+        );
+    }
+
+    #[test]
+    fn parse_org_str_rejects_invalid_orgs() {
+        let api_url = "https://api.github.com";
+        assert!(parse_org_str("", api_url).is_err());
+        assert!(parse_org_str("acme/repo", api_url).is_err());
+    }
+
+    #[test]
+    fn repo_filters_include_matching_repos() {
+        let include = patterns(&["acme/api-*"]);
+        assert!(is_repo_included(
+            &org_repo("acme/api-server", false, false),
+            &include,
+            &[],
+            false,
+            false
+        ));
+        assert!(!is_repo_included(
+            &org_repo("acme/website", false, false),
+            &include,
+            &[],
+            false,
+            false
+        ));
+        assert!(is_repo_included(
+            &org_repo("acme/website", false, false),
+            &[],
+            &[],
+            false,
+            false
+        ));
+    }
+
+    #[test]
+    fn repo_filters_exclude_overrides_include() {
+        let include = patterns(&["acme/*"]);
+        let exclude = patterns(&["acme/*-archive"]);
+        assert!(!is_repo_included(
+            &org_repo("acme/api-archive", false, false),
+            &include,
+            &exclude,
+            false,
+            false
+        ));
+        assert!(!is_repo_included(
+            &org_repo("acme/api", true, false),
+            &include,
+            &[],
+            true,
+            false
+        ));
+        assert!(!is_repo_included(
+            &org_repo("acme/api", false, true),
+            &include,
+            &[],
+            false,
+            true
+        ));
+        assert!(is_repo_included(
+            &org_repo("acme/api", true, true),
+            &include,
+            &exclude,
+            false,
+            false
+        ));
+    }
+
+    #[test]
+    fn glob_patterns_rejects_invalid_globs() {
+        assert!(glob_patterns(&["acme/[".to_string()]).is_err());
+        assert!(glob_patterns(&["acme/***".to_string()]).is_err());
     }
-    Ok((parts[0], parts[1]))
 }
@@ -0,0 +1,492 @@
+//! Load the GitHub and Gitea rows of `wallowa_raw_data` into the normalized `github_*` tables
+//! that the queries read from.
+//!
+//! The raw rows stay the source of truth. Each load upserts the rows that haven't been loaded
+//! yet (`loaded_at IS NULL`), keeping the latest version of each item, and then sets their
+//! `loaded_at`. The tables can be rebuilt from scratch with [`rebuild`].
+use anyhow::Result;
+use tracing::{debug, info};
+use wallowa_duckdb::Pool;
+
+/// The normalized tables, all of which are loaded by [`LOAD_STATEMENTS`]
+const TABLES: [&str; 9] = [
+    "github_pull_request",
+    "github_pull_review",
+    "github_pull_review_comment",
+    "github_workflow_run",
+    "github_deployment",
+    "github_deployment_status",
+    "github_release",
+    "github_commit",
+    "github_issue",
+];
+
+/// The `wallowa_raw_data` rows that are loaded into the normalized tables
+const LOADED_ROWS: &str = r#"(
+    ("data_source" = 'github_rest_api' AND data_type IN (
+        'pulls',
+        'pull_reviews',
+        'pull_review_comments',
+        'workflow_runs',
+        'deployments',
+        'deployment_statuses',
+        'releases',
+        'commits',
+        'issues'
+    ))
+    OR ("data_source" = 'gitea_rest_api' AND data_type = 'pulls')
+)"#;
+
+/// The statements that upsert the rows that haven't been loaded yet into each normalized
+/// table.
+///
+/// Items are deduped within the new rows first since a row can only be upserted once per
+/// statement. Items with an `updated_at` only replace an older version of themselves, while
+/// other items are replaced by the version in the newest row.
+const LOAD_STATEMENTS: [&str; 9] = [
+    r#"
+INSERT INTO github_pull_request
+WITH pulls AS (
+    SELECT
+        id AS raw_id,
+        "data_source",
+        metadata,
+        unnest(json_transform("data",
+            '[{
+                "url": "VARCHAR",
+                "user": {
+                    "login": "VARCHAR"
+                },
+                "base": {
+                    "repo": {
+                        "name": "VARCHAR",
+                        "owner": {
+                            "login": "VARCHAR"
+                        }
+                    }
+                },
+                "state": "VARCHAR",
+                "created_at": "TIMESTAMP",
+                "closed_at": "TIMESTAMP",
+                "merged_at": "TIMESTAMP",
+                "updated_at": "TIMESTAMP"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" IN ('github_rest_api', 'gitea_rest_api')
+    AND data_type = 'pulls'
+)
+SELECT
+    "data_source",
+    row.url,
+    github_repo_name(metadata, row.base.repo.owner.login || '/' || row.base.repo.name),
+    row.user.login,
+    row.state,
+    row.created_at,
+    row.closed_at,
+    row.merged_at,
+    row.updated_at,
+    raw_id
+FROM pulls
+WHERE row.url NOT NULL
+QUALIFY row_number() OVER (PARTITION BY "data_source", row.url ORDER BY row.updated_at DESC, raw_id DESC) = 1
+ON CONFLICT ("data_source", "url") DO UPDATE SET
+    repo = excluded.repo,
+    author = excluded.author,
+    state = excluded.state,
+    created_at = excluded.created_at,
+    closed_at = excluded.closed_at,
+    merged_at = excluded.merged_at,
+    updated_at = excluded.updated_at,
+    raw_id = excluded.raw_id
+WHERE excluded.updated_at >= github_pull_request.updated_at
+"#,
+    // Pending reviews don't have a `submitted_at` so the non-strict `json_transform` is used
+    r#"
+INSERT INTO github_pull_review
+WITH reviews AS (
+    SELECT
+        id AS raw_id,
+        coalesce(metadata->>'$.host', 'api.github.com') AS host,
+        unnest(json_transform("data",
+            '[{
+                "id": "BIGINT",
+                "pull_request_url": "VARCHAR",
+                "user": {
+                    "login": "VARCHAR"
+                },
+                "state": "VARCHAR",
+                "submitted_at": "TIMESTAMP"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'pull_reviews'
+)
+SELECT
+    host,
+    row.id,
+    row.pull_request_url,
+    row.user.login,
+    row.state,
+    row.submitted_at,
+    raw_id
+FROM reviews
+WHERE row.id NOT NULL
+QUALIFY row_number() OVER (PARTITION BY host, row.id ORDER BY raw_id DESC) = 1
+ON CONFLICT (host, id) DO UPDATE SET
+    pull_request_url = excluded.pull_request_url,
+    reviewer = excluded.reviewer,
+    state = excluded.state,
+    submitted_at = excluded.submitted_at,
+    raw_id = excluded.raw_id
+"#,
+    r#"
+INSERT INTO github_pull_review_comment
+WITH review_comments AS (
+    SELECT
+        id AS raw_id,
+        coalesce(metadata->>'$.host', 'api.github.com') AS host,
+        unnest(json_transform("data",
+            '[{
+                "id": "BIGINT",
+                "pull_request_url": "VARCHAR",
+                "user": {
+                    "login": "VARCHAR"
+                },
+                "created_at": "TIMESTAMP"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'pull_review_comments'
+)
+SELECT
+    host,
+    row.id,
+    row.pull_request_url,
+    row.user.login,
+    row.created_at,
+    raw_id
+FROM review_comments
+WHERE row.id NOT NULL
+QUALIFY row_number() OVER (PARTITION BY host, row.id ORDER BY raw_id DESC) = 1
+ON CONFLICT (host, id) DO UPDATE SET
+    pull_request_url = excluded.pull_request_url,
+    reviewer = excluded.reviewer,
+    created_at = excluded.created_at,
+    raw_id = excluded.raw_id
+"#,
+    r#"
+INSERT INTO github_workflow_run
+WITH workflow_runs AS (
+    SELECT
+        id AS raw_id,
+        metadata,
+        unnest(struct_extract(json_transform("data",
+            '{
+                "workflow_runs": [{
+                    "id": "BIGINT",
+                    "url": "VARCHAR",
+                    "name": "VARCHAR",
+                    "head_branch": "VARCHAR",
+                    "status": "VARCHAR",
+                    "conclusion": "VARCHAR",
+                    "run_attempt": "BIGINT",
+                    "created_at": "TIMESTAMP",
+                    "run_started_at": "TIMESTAMP",
+                    "updated_at": "TIMESTAMP",
+                    "repository": {
+                        "full_name": "VARCHAR"
+                    }
+                }]
+            }'), 'workflow_runs')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'workflow_runs'
+)
+SELECT
+    row.url,
+    row.run_attempt,
+    row.id,
+    github_repo_name(metadata, row.repository.full_name),
+    row.name,
+    row.head_branch,
+    row.status,
+    row.conclusion,
+    row.created_at,
+    row.run_started_at,
+    row.updated_at,
+    raw_id
+FROM workflow_runs
+WHERE row.url NOT NULL
+AND row.run_attempt NOT NULL
+QUALIFY row_number() OVER (PARTITION BY row.url, row.run_attempt ORDER BY row.updated_at DESC, raw_id DESC) = 1
+ON CONFLICT ("url", run_attempt) DO UPDATE SET
+    id = excluded.id,
+    repo = excluded.repo,
+    workflow = excluded.workflow,
+    branch = excluded.branch,
+    status = excluded.status,
+    conclusion = excluded.conclusion,
+    created_at = excluded.created_at,
+    run_started_at = excluded.run_started_at,
+    updated_at = excluded.updated_at,
+    raw_id = excluded.raw_id
+WHERE excluded.updated_at >= github_workflow_run.updated_at
+"#,
+    r#"
+INSERT INTO github_deployment
+WITH deployments AS (
+    SELECT
+        id AS raw_id,
+        github_repo_name(metadata, (metadata->>'$.owner') || '/' || (metadata->>'$.repo')) AS repo,
+        unnest(json_transform("data",
+            '[{
+                "url": "VARCHAR",
+                "sha": "VARCHAR",
+                "environment": "VARCHAR",
+                "created_at": "TIMESTAMP",
+                "updated_at": "TIMESTAMP"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'deployments'
+)
+SELECT
+    row.url,
+    repo,
+    row.sha,
+    row.environment,
+    row.created_at,
+    row.updated_at,
+    raw_id
+FROM deployments
+WHERE row.url NOT NULL
+QUALIFY row_number() OVER (PARTITION BY row.url ORDER BY row.updated_at DESC, raw_id DESC) = 1
+ON CONFLICT ("url") DO UPDATE SET
+    repo = excluded.repo,
+    sha = excluded.sha,
+    environment = excluded.environment,
+    created_at = excluded.created_at,
+    updated_at = excluded.updated_at,
+    raw_id = excluded.raw_id
+WHERE excluded.updated_at >= github_deployment.updated_at
+"#,
+    r#"
+INSERT INTO github_deployment_status
+WITH deployment_statuses AS (
+    SELECT
+        id AS raw_id,
+        coalesce(metadata->>'$.host', 'api.github.com') AS host,
+        unnest(json_transform("data",
+            '[{
+                "id": "BIGINT",
+                "deployment_url": "VARCHAR",
+                "state": "VARCHAR",
+                "created_at": "TIMESTAMP"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'deployment_statuses'
+)
+SELECT
+    host,
+    row.id,
+    row.deployment_url,
+    row.state,
+    row.created_at,
+    raw_id
+FROM deployment_statuses
+WHERE row.id NOT NULL
+QUALIFY row_number() OVER (PARTITION BY host, row.id ORDER BY raw_id DESC) = 1
+ON CONFLICT (host, id) DO UPDATE SET
+    deployment_url = excluded.deployment_url,
+    state = excluded.state,
+    created_at = excluded.created_at,
+    raw_id = excluded.raw_id
+"#,
+    r#"
+INSERT INTO github_release
+WITH releases AS (
+    SELECT
+        id AS raw_id,
+        coalesce(metadata->>'$.host', 'api.github.com') AS host,
+        github_repo_name(metadata, (metadata->>'$.owner') || '/' || (metadata->>'$.repo')) AS repo,
+        unnest(json_transform("data",
+            '[{
+                "id": "BIGINT",
+                "draft": "BOOLEAN",
+                "prerelease": "BOOLEAN",
+                "published_at": "TIMESTAMP"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'releases'
+)
+SELECT
+    host,
+    row.id,
+    repo,
+    row.draft,
+    row.prerelease,
+    row.published_at,
+    raw_id
+FROM releases
+WHERE row.id NOT NULL
+QUALIFY row_number() OVER (PARTITION BY host, row.id ORDER BY raw_id DESC) = 1
+ON CONFLICT (host, id) DO UPDATE SET
+    repo = excluded.repo,
+    draft = excluded.draft,
+    prerelease = excluded.prerelease,
+    published_at = excluded.published_at,
+    raw_id = excluded.raw_id
+"#,
+    r#"
+INSERT INTO github_commit
+WITH commits AS (
+    SELECT
+        id AS raw_id,
+        github_repo_name(metadata, (metadata->>'$.owner') || '/' || (metadata->>'$.repo')) AS repo,
+        unnest(json_transform("data",
+            '[{
+                "sha": "VARCHAR",
+                "commit": {
+                    "committer": {
+                        "date": "TIMESTAMP"
+                    }
+                },
+                "parents": [{
+                    "sha": "VARCHAR"
+                }]
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'commits'
+)
+SELECT
+    repo,
+    row.sha,
+    row.commit.committer.date,
+    raw_id,
+    coalesce(list_transform(row.parents, p -> p.sha), [])
+FROM commits
+WHERE repo NOT NULL
+AND row.sha NOT NULL
+QUALIFY row_number() OVER (PARTITION BY repo, row.sha ORDER BY raw_id DESC) = 1
+ON CONFLICT (repo, sha) DO UPDATE SET
+    committed_at = excluded.committed_at,
+    raw_id = excluded.raw_id,
+    parents = excluded.parents
+"#,
+    r#"
+INSERT INTO github_issue
+WITH issues AS (
+    SELECT
+        id AS raw_id,
+        github_repo_name(metadata, (metadata->>'$.owner') || '/' || (metadata->>'$.repo')) AS repo,
+        unnest(json_transform("data",
+            '[{
+                "url": "VARCHAR",
+                "labels": [{
+                    "name": "VARCHAR"
+                }],
+                "state": "VARCHAR",
+                "created_at": "TIMESTAMP",
+                "closed_at": "TIMESTAMP",
+                "updated_at": "TIMESTAMP",
+                "pull_request": "JSON"
+            }]')) AS row,
+    FROM wallowa_raw_data
+    WHERE loaded_at IS NULL
+    AND "data_source" = 'github_rest_api'
+    AND data_type = 'issues'
+)
+SELECT
+    row.url,
+    repo,
+    coalesce(list_transform(row.labels, l -> l.name), []),
+    row.state,
+    row.created_at,
+    row.closed_at,
+    row.updated_at,
+    raw_id
+FROM issues
+WHERE row.url NOT NULL
+-- The issues endpoint also returns pull requests
+AND row.pull_request IS NULL
+QUALIFY row_number() OVER (PARTITION BY row.url ORDER BY row.updated_at DESC, raw_id DESC) = 1
+ON CONFLICT ("url") DO UPDATE SET
+    repo = excluded.repo,
+    labels = excluded.labels,
+    state = excluded.state,
+    created_at = excluded.created_at,
+    closed_at = excluded.closed_at,
+    updated_at = excluded.updated_at,
+    raw_id = excluded.raw_id
+WHERE excluded.updated_at >= github_issue.updated_at
+"#,
+];
+
+/// Load the GitHub and Gitea rows of `wallowa_raw_data` that haven't been loaded yet into the
+/// normalized tables, returning the number of raw rows loaded
+pub fn load_raw_data(pool: &Pool) -> Result<usize> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    for statement in LOAD_STATEMENTS {
+        tx.execute(statement, [])?;
+    }
+    let loaded = tx.execute(
+        &format!(
+            r#"
+UPDATE wallowa_raw_data
+SET loaded_at = now()
+WHERE loaded_at IS NULL
+AND {LOADED_ROWS}
+"#
+        ),
+        [],
+    )?;
+    tx.commit()?;
+
+    if loaded > 0 {
+        info!("Loaded {loaded} rows of raw data into the normalized GitHub tables");
+    } else {
+        debug!("No new raw data to load into the normalized GitHub tables");
+    }
+    Ok(loaded)
+}
+
+/// Empty the normalized tables and load them again from all of the GitHub and Gitea rows of
+/// `wallowa_raw_data`, returning the number of raw rows loaded
+pub fn rebuild(pool: &Pool) -> Result<usize> {
+    // The tables are emptied in their own transaction since DuckDB can't upsert rows that were
+    // deleted earlier in the same transaction
+    {
+        let mut conn = pool.get()?;
+        let tx = conn.transaction()?;
+        for table in TABLES {
+            tx.execute(&format!("DELETE FROM {table}"), [])?;
+        }
+        tx.execute(
+            &format!(
+                r#"
+UPDATE wallowa_raw_data
+SET loaded_at = NULL
+WHERE {LOADED_ROWS}
+"#
+            ),
+            [],
+        )?;
+        tx.commit()?;
+    }
+    info!("Emptied the normalized GitHub tables to rebuild them");
+
+    load_raw_data(pool)
+}
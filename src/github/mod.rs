@@ -1,4 +1,75 @@
 /// All GitHub-related functionality
+pub mod auth;
+pub mod client;
 pub mod fetch;
+pub mod load;
 pub mod queries;
 pub mod web;
+pub mod webhook;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::{future::BoxFuture, FutureExt};
+use wallowa_duckdb::Pool;
+
+use crate::{
+    source::{NavEntry, Source},
+    web::AppState,
+};
+
+/// Pull Request, workflow, deployment, release, and issue activity from the GitHub REST API
+pub struct GitHubSource;
+
+impl Source for GitHubSource {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn label(&self) -> &'static str {
+        "GitHub"
+    }
+
+    fn description(&self) -> &'static str {
+        r#"Collect Pull Request activity data from
+<a href="https://github.com/" target="_blank" class="link" referrerpolicy="same-origin">GitHub</a>."#
+    }
+
+    fn logo(&self) -> Option<&'static str> {
+        Some("/static/github/github-mark.svg")
+    }
+
+    fn data_source(&self) -> &'static str {
+        "github_rest_api"
+    }
+
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>> {
+        fetch::validate_config().boxed()
+    }
+
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>> {
+        fetch::fetch_all(pool).boxed()
+    }
+
+    fn page_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::page_routes()
+    }
+
+    fn data_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::data_routes()
+    }
+
+    fn nav_entries(&self) -> Vec<NavEntry> {
+        vec![NavEntry {
+            logo: self.logo().map(String::from),
+            children: vec![
+                NavEntry::new("/github/closed_pr_count", "Closed PR count"),
+                NavEntry::new("/github/pr_duration", "PR duration"),
+                NavEntry::new("/github/workflow_runs", "Workflow runs"),
+                NavEntry::new("/github/issues", "Issues"),
+            ],
+            ..NavEntry::new("/github", "GitHub")
+        }]
+    }
+}
@@ -5,35 +5,21 @@ use tracing::{debug, error};
 use wallowa_duckdb::duckdb::{params_from_iter, ToSql};
 use wallowa_duckdb::Pool;
 
-/// Get the list of distinct GitHub repository names in the database.
-/// Repository names consist of `owner/repo`.
-pub fn select_distinct_repos(pool: &Pool) -> Result<Vec<String>> {
+/// Get the list of distinct repository names with pull requests from the `data_source`
+/// (`github_rest_api` or `gitea_rest_api`) in the database.
+/// Repository names consist of `owner/repo`, prefixed with the host for repos that aren't
+/// on github.com (`host/owner/repo`).
+pub fn select_distinct_repos(pool: &Pool, data_source: &str) -> Result<Vec<String>> {
     let conn = pool.get()?;
 
     let mut stmt = conn.prepare(
         r#"
-WITH pulls AS (
-    SELECT
-        unnest(json_transform_strict("data",
-            '[{
-                "base": {
-                    "repo": {
-                        "name": "VARCHAR",
-                        "owner": {
-                            "login": "VARCHAR"
-                        }
-                    }
-                },
-            }]')) AS row,
-    FROM wallowa_raw_data
-    WHERE "data_source" = 'github_rest_api'
-    AND data_type = 'pulls'
-)
-SELECT DISTINCT (row.base.repo.owner.login || '/' || row.base.repo.name) AS repo
-FROM pulls
+SELECT DISTINCT repo
+FROM github_pull_request
+WHERE "data_source" = ?
 "#,
     )?;
-    let rows = stmt.query_map([], |row| row.get(0))?;
+    let rows = stmt.query_map([data_source], |row| row.get(0))?;
     let mut repo_names = vec![];
     for row in rows {
         match row {
@@ -44,9 +30,32 @@ FROM pulls
     Ok(repo_names)
 }
 
-/// Query the rolling daily average time to merge GitHub Pull Requests
+/// Get the list of distinct repository names with GitHub Actions workflow runs in the
+/// database, named the same way as in [`select_distinct_repos`]
+pub fn select_distinct_workflow_run_repos(pool: &Pool) -> Result<Vec<String>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(
+        r#"
+SELECT DISTINCT repo
+FROM github_workflow_run
+"#,
+    )?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    let mut repo_names = vec![];
+    for row in rows {
+        match row {
+            Ok(repo_name) => repo_names.push(repo_name),
+            Err(e) => error!("Error querying distinct workflow run repos: {:?}", e),
+        }
+    }
+    Ok(repo_names)
+}
+
+/// Query the rolling daily average time to merge the Pull Requests from the `data_source`
 pub fn merged_pr_duration_rolling_daily_average(
     pool: &Pool,
+    data_source: &str,
     start_date: DateTime<FixedOffset>,
     end_date: DateTime<FixedOffset>,
     repos: &Vec<String>,
@@ -56,7 +65,7 @@ pub fn merged_pr_duration_rolling_daily_average(
     let conn = pool.get()?;
 
     let repo_placeholders = if repos.is_empty() {
-        "SELECT DISTINCT (row.base.repo.owner.login || '/' || row.base.repo.name) AS repo FROM pulls".to_string()
+        "SELECT DISTINCT repo FROM pulls".to_string()
     } else {
         let mut placeholders = "?,".repeat(repos.len());
         placeholders.pop(); // Remove the trailing comma (`,`)
@@ -72,30 +81,9 @@ WITH calendar_day AS (
     SELECT CAST(unnest(generate_series(CAST(? AS TIMESTAMP), CAST(? AS TIMESTAMP), interval '1' day)) AS DATE) as "day"
 ),
 pulls AS (
-    SELECT
-        id,
-        "data_source",
-        unnest(json_transform_strict("data",
-            '[{{
-                "url": "VARCHAR",
-                "base": {{
-                    "repo": {{
-                        "name": "VARCHAR",
-                        "owner": {{
-                            "login": "VARCHAR"
-                        }}
-                    }}
-                }},
-                "state": "VARCHAR",
-                "created_at": "TIMESTAMP",
-                "closed_at": "TIMESTAMP",
-                "merged_at": "TIMESTAMP",
-                "updated_at": "TIMESTAMP",
-                "draft": "BOOLEAN"
-            }}]')) AS row,
-    FROM wallowa_raw_data
-    WHERE "data_source" = 'github_rest_api'
-    AND data_type = 'pulls'
+    SELECT *
+    FROM github_pull_request
+    WHERE "data_source" = ?
 ),
 repos AS (
     {repo_placeholders}
@@ -104,25 +92,14 @@ calendar_day_repos AS (
     -- Generate a series of days for each repo so that each day+repo has a rolling average represented
     SELECT calendar_day."day", repos.repo FROM calendar_day CROSS JOIN repos
 ),
-latest_deduped_pulls AS (
-    SELECT
-        row.url AS "url",
-        (row.base.repo.owner.login || '/' || row.base.repo.name) AS repo,
-        row.created_at AS created_at,
-        row.merged_at AS merged_at,
-        row.updated_at AS updated_at,
-        row_number() OVER (PARTITION BY "url" ORDER BY updated_at DESC) AS row_number
-    FROM pulls
-    WHERE repo IN (SELECT repo FROM repos)
-),
 rolling AS (
     SELECT
         repo,
         CAST(created_at AS DATE) AS created_date,
         CAST(merged_at AS DATE) AS merged_date,
         AVG(EPOCH(AGE(merged_at, created_at)) / 86400) OVER thirty AS duration
-    FROM latest_deduped_pulls
-    WHERE row_number = 1
+    FROM pulls
+    WHERE repo IN (SELECT repo FROM repos)
     AND merged_at NOT NULL
     WINDOW thirty AS (
         PARTITION BY repo
@@ -141,6 +118,7 @@ ORDER BY 1,2
     let end_date_naive = end_date.naive_utc();
     params.push(start_date_naive.to_sql()?);
     params.push(end_date_naive.to_sql()?);
+    params.push(data_source.to_sql()?);
     for repo in repos {
         params.push(repo.to_sql()?);
     }
@@ -153,9 +131,10 @@ ORDER BY 1,2
     Ok(batches)
 }
 
-/// Query the closed GitHub Pull Requests
+/// Query the closed Pull Requests from the `data_source`
 pub fn closed_prs(
     pool: &Pool,
+    data_source: &str,
     start_date: DateTime<FixedOffset>,
     end_date: DateTime<FixedOffset>,
     repos: &Vec<String>,
@@ -165,7 +144,7 @@ pub fn closed_prs(
     let conn = pool.get()?;
 
     let repo_placeholders = if repos.is_empty() {
-        "SELECT DISTINCT (row.base.repo.owner.login || '/' || row.base.repo.name) AS repo FROM pulls".to_string()
+        "SELECT DISTINCT repo FROM pulls".to_string()
     } else {
         let mut placeholders = "?,".repeat(repos.len());
         placeholders.pop(); // Remove the trailing comma (`,`)
@@ -176,59 +155,814 @@ pub fn closed_prs(
     let mut stmt = conn.prepare(&format!(
         r#"
 WITH pulls AS (
-    SELECT
-        id,
-        "data_source",
-        unnest(json_transform_strict("data",
-            '[{{
-                "url": "VARCHAR",
-                "base": {{
-                    "repo": {{
-                        "name": "VARCHAR",
-                        "owner": {{
-                            "login": "VARCHAR"
-                        }}
-                    }}
-                }},
-                "state": "VARCHAR",
-                "created_at": "TIMESTAMP",
-                "closed_at": "TIMESTAMP",
-                "merged_at": "TIMESTAMP",
-                "updated_at": "TIMESTAMP",
-                "draft": "BOOLEAN"
-            }}]')) AS row,
-    FROM wallowa_raw_data
+    SELECT *
+    FROM github_pull_request
+    WHERE "data_source" = ?
+),
+repos AS (
+    {repo_placeholders}
+)
+SELECT
+    "url",
+    repo,
+    created_at,
+    merged_at,
+    updated_at,
+    CAST(closed_at AS DATE) AS closed_at
+FROM pulls
+WHERE repo IN (SELECT repo FROM repos)
+AND pulls.closed_at >= ?
+AND pulls.closed_at <= ?
+"#,
+        repo_placeholders = repo_placeholders
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    params.push(data_source.to_sql()?);
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
+
+/// Query the rolling daily average time to first review and time to approval of
+/// GitHub Pull Requests.
+///
+/// The first review is the earliest review or review comment by anyone other than the
+/// author of the Pull Request.
+pub fn pr_review_duration_rolling_daily_average(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `pr_review_duration_rolling_daily_average`");
+
+    let conn = pool.get()?;
+
+    let repo_placeholders = if repos.is_empty() {
+        "SELECT DISTINCT repo FROM pulls".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    };
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(r#"
+-- pr_review_duration_rolling_daily_average
+-- Time to first review and time to approval of GitHub Pull Requests, rolling daily average
+WITH calendar_day AS (
+    -- Generate a series of days so that each day has a rolling average represented
+    SELECT CAST(unnest(generate_series(CAST(? AS TIMESTAMP), CAST(? AS TIMESTAMP), interval '1' day)) AS DATE) as "day"
+),
+pulls AS (
+    SELECT *
+    FROM github_pull_request
     WHERE "data_source" = 'github_rest_api'
-    AND data_type = 'pulls'
 ),
 repos AS (
     {repo_placeholders}
 ),
-latest_deduped_pulls_window AS (
+calendar_day_repos AS (
+    -- Generate a series of days for each repo so that each day+repo has a rolling average represented
+    SELECT calendar_day."day", repos.repo FROM calendar_day CROSS JOIN repos
+),
+review_activity AS (
+    SELECT
+        pull_request_url AS "url",
+        reviewer,
+        state,
+        submitted_at AS reviewed_at
+    FROM github_pull_review
+    UNION ALL
+    SELECT
+        pull_request_url AS "url",
+        reviewer,
+        'COMMENTED' AS state,
+        created_at AS reviewed_at
+    FROM github_pull_review_comment
+),
+pull_review_times AS (
     SELECT
-        row.url AS "url",
-        (row.base.repo.owner.login || '/' || row.base.repo.name) AS repo,
-        row.created_at AS created_at,
-        row.merged_at AS merged_at,
-        row.updated_at AS updated_at,
-        row.closed_at AS closed_at,
-        row_number() OVER (PARTITION BY "url" ORDER BY updated_at DESC) AS row_number
+        pulls."url",
+        pulls.repo,
+        pulls.created_at,
+        MIN(review_activity.reviewed_at) AS first_reviewed_at,
+        MIN(review_activity.reviewed_at) FILTER (WHERE review_activity.state = 'APPROVED') AS approved_at
     FROM pulls
+    JOIN review_activity
+        ON review_activity."url" = pulls."url"
+        AND review_activity.reviewer IS DISTINCT FROM pulls.author
+    WHERE pulls.repo IN (SELECT repo FROM repos)
+    GROUP BY 1,2,3
+),
+rolling AS (
+    SELECT
+        repo,
+        CAST(created_at AS DATE) AS created_date,
+        AVG(EPOCH(AGE(first_reviewed_at, created_at)) / 86400) OVER thirty AS time_to_first_review,
+        AVG(EPOCH(AGE(approved_at, created_at)) / 86400) OVER thirty AS time_to_approval
+    FROM pull_review_times
+    WINDOW thirty AS (
+        PARTITION BY repo
+        ORDER BY created_at ASC
+        RANGE BETWEEN INTERVAL 30 DAYS PRECEDING
+                AND INTERVAL 0 DAYS FOLLOWING)
+)
+SELECT
+    calendar_day_repos."day" AS "day",
+    rolling.repo,
+    AVG(rolling.time_to_first_review) AS time_to_first_review,
+    AVG(rolling.time_to_approval) AS time_to_approval
+FROM calendar_day_repos ASOF LEFT JOIN rolling ON (calendar_day_repos.repo = rolling.repo AND calendar_day_repos."day" >= rolling.created_date)
+GROUP BY 1,2
+ORDER BY 1,2
+"#, repo_placeholders = repo_placeholders))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
+
+/// The common table expressions for the latest copy of each GitHub Actions workflow run
+/// attempt in the `repos` (defined by `repo_placeholders`) that was created within a date range.
+///
+/// Expects the parameters for `repo_placeholders` followed by the start and end dates.
+fn workflow_runs_ctes(repo_placeholders: &str) -> String {
+    format!(
+        r#"
+repos AS (
+    {repo_placeholders}
+),
+latest_deduped_runs AS (
+    SELECT
+        id,
+        repo,
+        workflow,
+        branch,
+        status,
+        conclusion,
+        created_at,
+        run_started_at,
+        updated_at
+    FROM github_workflow_run
     WHERE repo IN (SELECT repo FROM repos)
+    AND status = 'completed'
+    AND created_at >= ?
+    AND created_at <= ?
+)"#,
+        repo_placeholders = repo_placeholders
+    )
+}
+
+/// Query the duration percentiles, in minutes, of completed GitHub Actions workflow runs
+/// by workflow and branch
+pub fn workflow_run_durations(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `workflow_run_durations`");
+
+    query_workflow_runs(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+SELECT
+    repo,
+    workflow,
+    branch,
+    COUNT(id) AS run_count,
+    quantile_cont(EPOCH(AGE(updated_at, run_started_at)) / 60, 0.5) AS p50,
+    quantile_cont(EPOCH(AGE(updated_at, run_started_at)) / 60, 0.9) AS p90,
+    quantile_cont(EPOCH(AGE(updated_at, run_started_at)) / 60, 0.95) AS p95
+FROM latest_deduped_runs
+GROUP BY 1,2,3
+ORDER BY 1,2,3
+"#,
+    )
+}
+
+/// Query the count of each conclusion and the success rate of completed GitHub Actions
+/// workflow runs by workflow and branch
+pub fn workflow_run_conclusions(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `workflow_run_conclusions`");
+
+    query_workflow_runs(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+SELECT
+    repo,
+    workflow,
+    branch,
+    COUNT(id) AS run_count,
+    COUNT(id) FILTER (WHERE conclusion = 'success') AS success_count,
+    COUNT(id) FILTER (WHERE conclusion = 'failure') AS failure_count,
+    COUNT(id) FILTER (WHERE conclusion NOT IN ('success', 'failure')) AS other_count,
+    COUNT(id) FILTER (WHERE conclusion = 'success') / COUNT(id) AS success_rate
+FROM latest_deduped_runs
+GROUP BY 1,2,3
+ORDER BY 1,2,3
+"#,
+    )
+}
+
+/// Query the queue time percentiles, in minutes, of completed GitHub Actions workflow runs
+/// by workflow and branch. Queue time is the time elapsed between creating a run and the
+/// run starting.
+pub fn workflow_run_queue_times(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `workflow_run_queue_times`");
+
+    query_workflow_runs(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+SELECT
+    repo,
+    workflow,
+    branch,
+    COUNT(id) AS run_count,
+    quantile_cont(EPOCH(AGE(run_started_at, created_at)) / 60, 0.5) AS p50,
+    quantile_cont(EPOCH(AGE(run_started_at, created_at)) / 60, 0.9) AS p90,
+    quantile_cont(EPOCH(AGE(run_started_at, created_at)) / 60, 0.95) AS p95
+FROM latest_deduped_runs
+GROUP BY 1,2,3
+ORDER BY 1,2,3
+"#,
+    )
+}
+
+/// Run the `select` statement against the `latest_deduped_runs` for the date range and repos
+fn query_workflow_runs(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    select: &str,
+) -> Result<Vec<RecordBatch>> {
+    let conn = pool.get()?;
+
+    let repo_placeholders = if repos.is_empty() {
+        "SELECT DISTINCT repo FROM github_workflow_run".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    };
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH {ctes}\n{select}",
+        ctes = workflow_runs_ctes(&repo_placeholders),
+        select = select
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
+
+/// The common table expressions for the deployments of the `repos` (defined by
+/// `repo_placeholders`) used to calculate the DORA metrics.
+///
+/// A deployment is a GitHub Deployment to the given environment with the state of its latest
+/// status. For repos without any Deployments, each published GitHub Release (excluding
+/// pre-releases) is treated as a successful deployment.
+///
+/// Expects the environment parameter followed by the parameters for `repo_placeholders`.
+fn dora_ctes(repo_placeholders: &str) -> String {
+    format!(
+        r#"
+latest_deployment_statuses AS (
+    SELECT
+        deployment_url,
+        state,
+        created_at,
+        row_number() OVER (PARTITION BY deployment_url ORDER BY created_at DESC, id DESC) AS row_number
+    FROM github_deployment_status
+),
+deploys AS (
+    SELECT
+        github_deployment.repo,
+        github_deployment.sha,
+        latest_deployment_statuses.state,
+        latest_deployment_statuses.created_at AS deployed_at
+    FROM github_deployment
+    JOIN latest_deployment_statuses
+        ON latest_deployment_statuses.deployment_url = github_deployment."url"
+    WHERE latest_deployment_statuses.row_number = 1
+    AND github_deployment.environment = ?
+),
+release_deploys AS (
+    SELECT
+        repo,
+        id,
+        published_at AS deployed_at
+    FROM github_release
+    WHERE NOT draft
+    AND NOT prerelease
+    AND published_at NOT NULL
+    AND repo NOT IN (SELECT repo FROM deploys)
+),
+successful_deploys AS (
+    SELECT repo, sha, deployed_at FROM deploys WHERE state = 'success'
+    UNION ALL
+    SELECT repo, NULL AS sha, deployed_at FROM release_deploys
+),
+repos AS (
+    {repo_placeholders}
+)"#,
+        repo_placeholders = repo_placeholders
+    )
+}
+
+/// Query the DORA deployment frequency: the count of successful deployments per week
+pub fn deployment_frequency(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    environment: &str,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `deployment_frequency`");
+
+    query_dora(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        environment,
+        r#"
+SELECT
+    CAST(date_trunc('week', deployed_at) AS DATE) AS week,
+    repo,
+    COUNT(*) AS deployments
+FROM successful_deploys
+WHERE repo IN (SELECT repo FROM repos)
+AND deployed_at >= ?
+AND deployed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the DORA lead time for changes: the median number of hours between a commit and the
+/// first successful deployment that shipped it, by the week of the deployment.
+///
+/// A deployment ships the commits in the ancestry of its `sha` that weren't shipped by an earlier
+/// deployment, so commits on other branches or merged after the deployed commit aren't counted.
+/// Only commits made since the commit of the previous deployment are walked, which keeps the
+/// walk to the range between deployments. Commits from before the previous deployment that
+/// are merged later aren't counted.
+/// Releases don't record which commit they're of, so for repos deployed with releases each commit
+/// is matched to the first release published at or after it instead.
+pub fn lead_time_for_changes(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    environment: &str,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `lead_time_for_changes`");
+
+    query_dora(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        environment,
+        r#"
+, repo_commits AS (
+    SELECT repo, sha, committed_at, parents
+    FROM github_commit
+    WHERE repo IN (SELECT repo FROM repos)
+),
+commit_parents AS (
+    SELECT
+        child.repo,
+        child.sha,
+        parent.sha AS parent,
+        parent.committed_at AS parent_committed_at
+    FROM (SELECT repo, sha, unnest(parents) AS parent FROM repo_commits) AS child
+    JOIN repo_commits AS parent
+        ON parent.repo = child.repo
+        AND parent.sha = child.parent
+),
+sha_deploys AS (
+    -- A sha that was deployed more than once was shipped by its first deployment
+    SELECT repo, sha, min(deployed_at) AS deployed_at
+    FROM successful_deploys
+    WHERE sha NOT NULL
+    AND repo IN (SELECT repo FROM repos)
+    GROUP BY repo, sha
+),
+bounded_deploys AS (
+    -- Each deployment only ships commits made since the commit of the previous deployment
+    SELECT
+        sha_deploys.repo,
+        sha_deploys.sha,
+        sha_deploys.deployed_at,
+        lag(repo_commits.committed_at) OVER (
+            PARTITION BY sha_deploys.repo ORDER BY sha_deploys.deployed_at
+        ) AS since
+    FROM sha_deploys
+    LEFT JOIN repo_commits
+        ON repo_commits.repo = sha_deploys.repo
+        AND repo_commits.sha = sha_deploys.sha
+),
+-- Walk the ancestry of each deployed sha back to the commit of the previous deployment. The
+-- walk stops at shas deployed earlier since everything behind them was already shipped, and
+-- at commits older than the previous deployment so that merged branches don't lead the walk
+-- through the whole history again.
+shipped(repo, deployed_at, since, sha) AS (
+    SELECT repo, deployed_at, since, sha FROM bounded_deploys
+    UNION
+    SELECT
+        shipped.repo,
+        shipped.deployed_at,
+        shipped.since,
+        commit_parents.parent
+    FROM shipped
+    JOIN commit_parents
+        ON commit_parents.repo = shipped.repo
+        AND commit_parents.sha = shipped.sha
+    WHERE (shipped.since IS NULL OR commit_parents.parent_committed_at >= shipped.since)
+    AND NOT EXISTS (
+        SELECT 1
+        FROM sha_deploys
+        WHERE sha_deploys.repo = shipped.repo
+        AND sha_deploys.sha = commit_parents.parent
+        AND sha_deploys.deployed_at < shipped.deployed_at
+    )
+),
+commit_deploys AS (
+    SELECT
+        repo_commits.repo,
+        repo_commits.committed_at,
+        min(shipped.deployed_at) AS deployed_at
+    FROM shipped
+    JOIN repo_commits
+        ON repo_commits.repo = shipped.repo
+        AND repo_commits.sha = shipped.sha
+    GROUP BY repo_commits.repo, repo_commits.sha, repo_commits.committed_at
+    UNION ALL
+    SELECT
+        repo_commits.repo,
+        repo_commits.committed_at,
+        release_deploys.deployed_at
+    FROM repo_commits ASOF JOIN release_deploys
+        ON repo_commits.repo = release_deploys.repo
+        AND release_deploys.deployed_at >= repo_commits.committed_at
+)
+SELECT
+    CAST(date_trunc('week', deployed_at) AS DATE) AS week,
+    repo,
+    median(EPOCH(AGE(deployed_at, committed_at)) / 3600) AS lead_time
+FROM commit_deploys
+WHERE deployed_at >= ?
+AND deployed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the DORA change failure rate: the share of deployments per week whose latest status
+/// is `failure` or `error`
+pub fn change_failure_rate(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    environment: &str,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `change_failure_rate`");
+
+    query_dora(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        environment,
+        r#"
+SELECT
+    CAST(date_trunc('week', deployed_at) AS DATE) AS week,
+    repo,
+    COUNT(*) AS deployments,
+    COUNT(*) FILTER (WHERE state IN ('failure', 'error')) AS failed_deployments,
+    COUNT(*) FILTER (WHERE state IN ('failure', 'error')) / COUNT(*) AS change_failure_rate
+FROM deploys
+WHERE state IN ('success', 'failure', 'error')
+AND repo IN (SELECT repo FROM repos)
+AND deployed_at >= ?
+AND deployed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the DORA time to restore service: the median number of hours between a failed
+/// deployment and the next successful deployment, by the week of the failed deployment
+pub fn time_to_restore(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    environment: &str,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `time_to_restore`");
+
+    query_dora(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        environment,
+        r#"
+, failed_deploys AS (
+    SELECT repo, deployed_at AS failed_at
+    FROM deploys
+    WHERE state IN ('failure', 'error')
+    AND repo IN (SELECT repo FROM repos)
+),
+restores AS (
+    SELECT
+        failed_deploys.repo,
+        failed_deploys.failed_at,
+        successful_deploys.deployed_at AS restored_at
+    FROM failed_deploys ASOF JOIN successful_deploys
+        ON failed_deploys.repo = successful_deploys.repo
+        AND successful_deploys.deployed_at >= failed_deploys.failed_at
+)
+SELECT
+    CAST(date_trunc('week', failed_at) AS DATE) AS week,
+    repo,
+    median(EPOCH(AGE(restored_at, failed_at)) / 3600) AS time_to_restore
+FROM restores
+WHERE failed_at >= ?
+AND failed_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Run the `select` statement (optionally beginning with more common table expressions)
+/// after the DORA common table expressions for the environment, repos, and date range
+fn query_dora(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    environment: &str,
+    select: &str,
+) -> Result<Vec<RecordBatch>> {
+    let conn = pool.get()?;
+
+    let repo_placeholders = if repos.is_empty() {
+        "SELECT DISTINCT repo FROM deploys UNION SELECT DISTINCT repo FROM release_deploys"
+            .to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    };
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH RECURSIVE {ctes}\n{select}",
+        ctes = dora_ctes(&repo_placeholders),
+        select = select
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    params.push(environment.to_sql()?);
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
+
+/// The common table expressions for the latest copy of each GitHub Issue in the `repos`
+/// (defined by `repo_placeholders`).
+///
+/// Expects the parameters for `repo_placeholders`.
+fn issues_ctes(repo_placeholders: &str) -> String {
+    format!(
+        r#"
+repos AS (
+    {repo_placeholders}
+),
+latest_deduped_issues AS (
+    SELECT
+        "url",
+        repo,
+        labels,
+        state,
+        created_at,
+        closed_at,
+        updated_at
+    FROM github_issue
+    WHERE repo IN (SELECT repo FROM repos)
+)"#,
+        repo_placeholders = repo_placeholders
+    )
+}
+
+/// Query the count of GitHub Issues opened and closed by week
+pub fn issues_opened_closed(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `issues_opened_closed`");
+
+    query_issues(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+, issue_events AS (
+    SELECT repo, created_at AS event_at, 'opened' AS event FROM latest_deduped_issues
+    UNION ALL
+    SELECT repo, closed_at AS event_at, 'closed' AS event FROM latest_deduped_issues WHERE closed_at NOT NULL
+)
+SELECT
+    CAST(date_trunc('week', event_at) AS DATE) AS week,
+    repo,
+    COUNT(*) FILTER (WHERE event = 'opened') AS opened,
+    COUNT(*) FILTER (WHERE event = 'closed') AS closed
+FROM issue_events
+WHERE event_at >= ?
+AND event_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the age in days, as of the end date, of each GitHub Issue that was open at the end
+/// date. The start date is unused since an issue can be open for any length of time.
+pub fn open_issue_ages(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `open_issue_ages`");
+
+    query_issues(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+, as_of AS (
+    -- Keep the parameter order the same as the other issue queries
+    SELECT CAST(? AS TIMESTAMP) AS start_date, CAST(? AS TIMESTAMP) AS end_date
 )
 SELECT
     "url",
     repo,
     created_at,
-    merged_at,
-    updated_at,
-    CAST(latest_deduped_pulls_window.closed_at AS DATE) AS closed_at
-FROM latest_deduped_pulls_window
-WHERE row_number = 1
-AND closed_at >= ?
-AND closed_at <= ?
+    EPOCH(AGE((SELECT end_date FROM as_of), created_at)) / 86400 AS age
+FROM latest_deduped_issues
+WHERE created_at <= (SELECT end_date FROM as_of)
+AND (closed_at IS NULL OR closed_at > (SELECT end_date FROM as_of))
+ORDER BY age DESC
 "#,
-        repo_placeholders = repo_placeholders
+    )
+}
+
+/// Query the median number of days to close GitHub Issues by label, for the issues closed
+/// within the date range. Issues without a label are grouped under `(no label)`.
+pub fn issue_time_to_close_by_label(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `issue_time_to_close_by_label`");
+
+    query_issues(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        r#"
+, closed_issue_labels AS (
+    SELECT
+        repo,
+        created_at,
+        closed_at,
+        unnest(CASE WHEN len(labels) = 0 THEN ['(no label)'] ELSE labels END) AS label
+    FROM latest_deduped_issues
+    WHERE closed_at >= ?
+    AND closed_at <= ?
+)
+SELECT
+    repo,
+    label,
+    COUNT(*) AS closed,
+    median(EPOCH(AGE(closed_at, created_at)) / 86400) AS time_to_close
+FROM closed_issue_labels
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Run the `select` statement (optionally beginning with more common table expressions)
+/// after the issue common table expressions for the repos and date range
+fn query_issues(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    select: &str,
+) -> Result<Vec<RecordBatch>> {
+    let conn = pool.get()?;
+
+    let repo_placeholders = if repos.is_empty() {
+        "SELECT DISTINCT repo FROM github_issue".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    };
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH {ctes}\n{select}",
+        ctes = issues_ctes(&repo_placeholders),
+        select = select
     ))?;
 
     let mut params = Vec::new();
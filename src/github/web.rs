@@ -1,36 +1,43 @@
-use std::{io::BufWriter, sync::Arc};
+use std::sync::Arc;
 
 use axum::{
     body::Body,
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     response::Html,
     routing::{get, post},
     Router,
 };
 use axum_extra::extract::Query;
-use chrono::{DateTime, Datelike, Days, FixedOffset, TimeZone, Utc};
 use minijinja::context;
-use serde::Deserialize;
-use tracing::error;
-use wallowa_duckdb::duckdb::arrow::{datatypes::Schema, ipc::writer::FileWriter};
 
 use crate::{
-    web::{render, AppState},
+    config_value,
+    web::{date_range, render, to_arrow_ipc, AppState, ChartParams},
     AppResult,
 };
 
-use super::{
-    fetch::fetch_all,
-    queries::{closed_prs, merged_pr_duration_rolling_daily_average, select_distinct_repos},
+use super::fetch::Api;
+use super::queries::{
+    change_failure_rate, closed_prs, deployment_frequency, issue_time_to_close_by_label,
+    issues_opened_closed, lead_time_for_changes, merged_pr_duration_rolling_daily_average,
+    open_issue_ages, pr_review_duration_rolling_daily_average, select_distinct_repos,
+    select_distinct_workflow_run_repos, time_to_restore, workflow_run_conclusions,
+    workflow_run_durations, workflow_run_queue_times,
 };
+use super::webhook::{webhook, MAX_PAYLOAD_BYTES};
 
 /// All page-related routes for GitHub
 pub fn page_routes() -> Router<Arc<AppState>, Body> {
     Router::new()
         .route("/pr_duration", get(github_pr_duration))
         .route("/closed_pr_count", get(github_closed_pr_count))
+        .route("/workflow_runs", get(github_workflow_runs))
+        .route("/issues", get(github_issues))
+        .route(
+            "/webhook",
+            post(webhook).layer(DefaultBodyLimit::max(MAX_PAYLOAD_BYTES)),
+        )
         .route("/", get(github_dashboard))
-        .route("/fetch", post(fetch_source))
 }
 
 /// All data-related routes for GitHub
@@ -41,84 +48,217 @@ pub fn data_routes() -> Router<Arc<AppState>, Body> {
             get(merged_pr_duration_rolling_daily_average_arrow),
         )
         .route("/closed_prs.arrow", get(closed_prs_arrow))
+        .route(
+            "/pr_review_duration_rolling_daily_average.arrow",
+            get(pr_review_duration_rolling_daily_average_arrow),
+        )
+        .route(
+            "/deployment_frequency.arrow",
+            get(deployment_frequency_arrow),
+        )
+        .route(
+            "/lead_time_for_changes.arrow",
+            get(lead_time_for_changes_arrow),
+        )
+        .route("/change_failure_rate.arrow", get(change_failure_rate_arrow))
+        .route("/time_to_restore.arrow", get(time_to_restore_arrow))
+        .route(
+            "/issues_opened_closed.arrow",
+            get(issues_opened_closed_arrow),
+        )
+        .route("/open_issue_ages.arrow", get(open_issue_ages_arrow))
+        .route(
+            "/issue_time_to_close_by_label.arrow",
+            get(issue_time_to_close_by_label_arrow),
+        )
+        .route(
+            "/workflow_run_durations.arrow",
+            get(workflow_run_durations_arrow),
+        )
+        .route(
+            "/workflow_run_conclusions.arrow",
+            get(workflow_run_conclusions_arrow),
+        )
+        .route(
+            "/workflow_run_queue_times.arrow",
+            get(workflow_run_queue_times_arrow),
+        )
 }
 
-async fn fetch_source(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
-    let result = fetch_all(&state.pool).await;
+async fn merged_pr_duration_rolling_daily_average_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = merged_pr_duration_rolling_daily_average(
+        &state.read_pool,
+        Api::GitHub.data_source(),
+        start_date,
+        end_date,
+        &params.repo,
+    )?;
 
-    let message = match result {
-        Ok(timestamp) => timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
-        Err(err) => {
-            let msg = format!("{err}");
-            error!(msg);
-            msg
-        }
-    };
-    Ok(Html(render(
-        state,
-        "sources/fetch_source.html",
-        context! {
-            message,
-        },
-    )?))
+    to_arrow_ipc(results)
+}
+
+async fn pr_review_duration_rolling_daily_average_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = pr_review_duration_rolling_daily_average(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+    )?;
+
+    to_arrow_ipc(results)
 }
 
-#[derive(Deserialize, Debug)]
-struct MergedPRParams {
-    start_date: Option<DateTime<FixedOffset>>,
-    end_date: Option<DateTime<FixedOffset>>,
-    #[serde(default)]
-    repo: Vec<String>,
+async fn workflow_run_durations_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = workflow_run_durations(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
 }
 
-async fn merged_pr_duration_rolling_daily_average_arrow(
+async fn workflow_run_conclusions_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = workflow_run_conclusions(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn workflow_run_queue_times_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = workflow_run_queue_times(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn deployment_frequency_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+    let environment: String = config_value("github.deployment_environment").await?;
+
+    let results = deployment_frequency(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+        &environment,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn lead_time_for_changes_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+    let environment: String = config_value("github.deployment_environment").await?;
+
+    let results = lead_time_for_changes(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+        &environment,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn change_failure_rate_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+    let environment: String = config_value("github.deployment_environment").await?;
+
+    let results = change_failure_rate(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+        &environment,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn time_to_restore_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+    let environment: String = config_value("github.deployment_environment").await?;
+
+    let results = time_to_restore(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+        &environment,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn issues_opened_closed_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = issues_opened_closed(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn open_issue_ages_arrow(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<MergedPRParams>,
+    Query(params): Query<ChartParams>,
 ) -> AppResult<Vec<u8>> {
-    // TODO better error handling for invalid or missing parameters
-    let end_date = if let Some(end) = params.end_date {
-        end
-    } else {
-        let now = chrono::offset::Utc::now();
-        let beginning_of_today = Utc
-            .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
-            .unwrap();
-        beginning_of_today.fixed_offset()
-    };
-    let start_date = if let Some(start) = params.start_date {
-        start
-    } else {
-        end_date.checked_sub_days(Days::new(30)).unwrap()
-    };
+    let (start_date, end_date) = date_range(&params);
+
+    let results = open_issue_ages(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn issue_time_to_close_by_label_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
 
     let results =
-        merged_pr_duration_rolling_daily_average(&state.pool, start_date, end_date, &params.repo)?;
-
-    let mut ipc_data: Vec<u8> = Vec::new();
-    if !results.is_empty() {
-        // Use the schema from the first RecordBatch as the IPC schema
-        let schema = results[0].schema();
-        let metadata = schema.metadata.clone();
-        let fields: Vec<Arc<wallowa_duckdb::duckdb::arrow::datatypes::Field>> = schema
-            .all_fields()
-            .iter()
-            .map(|field| Arc::new((*field).clone()))
-            .collect();
-        let ipc_schema = Schema::new_with_metadata(fields, metadata);
-
-        let buf = BufWriter::new(&mut ipc_data);
-        let mut writer = FileWriter::try_new(buf, &ipc_schema)?;
-        for batch in results {
-            writer.write(&batch)?;
-        }
-        writer.finish()?;
-    }
-
-    Ok(ipc_data)
+        issue_time_to_close_by_label(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
 }
 
 async fn github_pr_duration(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
-    let distinct_repos = select_distinct_repos(&state.pool)?;
+    let distinct_repos = select_distinct_repos(&state.read_pool, Api::GitHub.data_source())?;
     let html = render(
         state,
         "github/pr_duration.html",
@@ -132,51 +272,23 @@ async fn github_pr_duration(State(state): State<Arc<AppState>>) -> AppResult<Htm
 
 async fn closed_prs_arrow(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<MergedPRParams>,
+    Query(params): Query<ChartParams>,
 ) -> AppResult<Vec<u8>> {
-    // TODO better error handling for invalid or missing parameters
-    let end_date = if let Some(end) = params.end_date {
-        end
-    } else {
-        let now = chrono::offset::Utc::now();
-        let beginning_of_today = Utc
-            .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
-            .unwrap();
-        beginning_of_today.fixed_offset()
-    };
-    let start_date = if let Some(start) = params.start_date {
-        start
-    } else {
-        end_date.checked_sub_days(Days::new(30)).unwrap()
-    };
-
-    let results = closed_prs(&state.pool, start_date, end_date, &params.repo)?;
-
-    let mut ipc_data: Vec<u8> = Vec::new();
-    if !results.is_empty() {
-        // Use the schema from the first RecordBatch as the IPC schema
-        let schema = results[0].schema();
-        let metadata = schema.metadata.clone();
-        let fields: Vec<Arc<wallowa_duckdb::duckdb::arrow::datatypes::Field>> = schema
-            .all_fields()
-            .iter()
-            .map(|field| Arc::new((*field).clone()))
-            .collect();
-        let ipc_schema = Schema::new_with_metadata(fields, metadata);
-
-        let buf = BufWriter::new(&mut ipc_data);
-        let mut writer = FileWriter::try_new(buf, &ipc_schema)?;
-        for batch in results {
-            writer.write(&batch)?;
-        }
-        writer.finish()?;
-    }
-
-    Ok(ipc_data)
+    let (start_date, end_date) = date_range(&params);
+
+    let results = closed_prs(
+        &state.read_pool,
+        Api::GitHub.data_source(),
+        start_date,
+        end_date,
+        &params.repo,
+    )?;
+
+    to_arrow_ipc(results)
 }
 
 async fn github_closed_pr_count(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
-    let distinct_repos = select_distinct_repos(&state.pool)?;
+    let distinct_repos = select_distinct_repos(&state.read_pool, Api::GitHub.data_source())?;
     let html = render(
         state,
         "github/pr_count.html",
@@ -188,6 +300,32 @@ async fn github_closed_pr_count(State(state): State<Arc<AppState>>) -> AppResult
     Ok(Html(html))
 }
 
+async fn github_workflow_runs(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_workflow_run_repos(&state.read_pool)?;
+    let html = render(
+        state,
+        "github/workflow_runs.html",
+        context! {
+            current_nav => "/github/workflow_runs",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn github_issues(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool, Api::GitHub.data_source())?;
+    let html = render(
+        state,
+        "github/issues.html",
+        context! {
+            current_nav => "/github/issues",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
 async fn github_dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
     let html = render(
         state,
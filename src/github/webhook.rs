@@ -0,0 +1,245 @@
+//! Receive GitHub webhook events so that changes show up without waiting for the next fetch
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use reqwest::Url;
+use ring::hmac;
+use serde_json::{json, Value};
+use tokio::task;
+use tracing::{debug, error, info};
+use wallowa_duckdb::{duckdb::params, Pool};
+
+use super::{fetch::Api, load::load_raw_data};
+use crate::{config_value, web::AppState, AppResult};
+
+/// The largest webhook payload that GitHub sends
+pub const MAX_PAYLOAD_BYTES: usize = 25 * 1024 * 1024;
+
+/// The repo that a webhook event is for, matching the `metadata` of fetched rows
+struct EventRepo {
+    host: String,
+    owner: String,
+    name: String,
+}
+
+/// Verify and store a GitHub webhook event.
+///
+/// The `X-Hub-Signature-256` header must be the HMAC of the body with the configured
+/// `github.webhook.secret`. The webhook is disabled until the secret is configured.
+///
+/// `pull_request`, `pull_request_review`, `workflow_run`, and `deployment_status` events are
+/// stored in the same shape as the pages fetched from the REST API and loaded into the
+/// normalized tables so that the queries pick them up. Other events are acknowledged and
+/// ignored.
+pub async fn webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Response> {
+    let Some(secret) = config_value::<Option<String>>("github.webhook.secret").await? else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            "The GitHub webhook is disabled. Configure `github.webhook.secret` to enable it.",
+        )
+            .into_response());
+    };
+
+    let signature = headers
+        .get("x-hub-signature-256")
+        .and_then(|value| value.to_str().ok());
+    if !signature.is_some_and(|signature| verify_signature(&secret, &body, signature)) {
+        return Ok((StatusCode::UNAUTHORIZED, "Invalid or missing signature").into_response());
+    }
+
+    let event = headers
+        .get("x-github-event")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let delivery = headers
+        .get("x-github-delivery")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    debug!("Received GitHub webhook delivery {delivery} for `{event}`");
+
+    if !matches!(
+        event,
+        "pull_request" | "pull_request_review" | "workflow_run" | "deployment_status"
+    ) {
+        return Ok((StatusCode::OK, format!("Ignored `{event}` event")).into_response());
+    }
+
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            return Ok((StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}")).into_response());
+        }
+    };
+    // Storing waits for the write connection, which a fetch may be using, so it's done off
+    // the async workers
+    let (pool, event_name, delivery_id) =
+        (state.pool.clone(), event.to_string(), delivery.to_string());
+    let stored =
+        task::spawn_blocking(move || store_event(&pool, &event_name, &delivery_id, &payload))
+            .await?;
+    if let Err(e) = stored {
+        return Ok((StatusCode::BAD_REQUEST, format!("{e:#}")).into_response());
+    }
+    info!("Stored GitHub `{event}` webhook event");
+
+    // The event is loaded in the background so that the delivery is acknowledged before
+    // GitHub times it out. The event is stored, so a failed load is picked up by the next one.
+    let (pool, event_name) = (state.pool.clone(), event.to_string());
+    task::spawn_blocking(move || {
+        if let Err(e) = load_raw_data(&pool) {
+            error!("Error loading GitHub `{event_name}` webhook event: {e:#}");
+        }
+    });
+
+    Ok((StatusCode::OK, format!("Stored `{event}` event")).into_response())
+}
+
+/// Whether the `signature` (`sha256=` followed by the hex HMAC) matches the `body`
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(tag) = signature
+        .strip_prefix("sha256=")
+        .and_then(|tag| hex::decode(tag).ok())
+    else {
+        return false;
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, body, &tag).is_ok()
+}
+
+/// Store the parts of the `payload` of an `event` that the queries use.
+///
+/// Each row is marked with the `delivery` ID in its `metadata` so that webhook rows aren't
+/// used as the watermark for fetching. A webhook can arrive for one item while other items
+/// changed without a webhook, and the next fetch still needs to pick those up.
+fn store_event(pool: &Pool, event: &str, delivery: &str, payload: &Value) -> Result<()> {
+    let repo = event_repo(payload)?;
+    let field = |name: &str| {
+        payload
+            .get(name)
+            .filter(|value| value.is_object())
+            .cloned()
+            .ok_or_else(|| anyhow!("No `{name}` in `{event}` event"))
+    };
+
+    // Each row is (data_type, parent key and ID for the metadata, data)
+    let rows = match event {
+        "pull_request" => vec![("pulls", None, json!([field("pull_request")?]))],
+        "pull_request_review" => {
+            let mut review = field("review")?;
+            // Webhooks send review states in lowercase while the REST API uses uppercase
+            if let Some(Value::String(review_state)) = review.get_mut("state") {
+                *review_state = review_state.to_uppercase();
+            }
+            let pull_number = field("pull_request")?
+                .get("number")
+                .and_then(Value::as_i64)
+                .ok_or_else(|| anyhow!("No pull request number in `{event}` event"))?;
+            vec![(
+                "pull_reviews",
+                Some(("pull_number", pull_number)),
+                json!([review]),
+            )]
+        }
+        "workflow_run" => vec![(
+            "workflow_runs",
+            None,
+            json!({ "total_count": 1, "workflow_runs": [field("workflow_run")?] }),
+        )],
+        "deployment_status" => {
+            let deployment = field("deployment")?;
+            let deployment_id = deployment
+                .get("id")
+                .and_then(Value::as_i64)
+                .ok_or_else(|| anyhow!("No deployment ID in `{event}` event"))?;
+            vec![
+                ("deployments", None, json!([deployment])),
+                (
+                    "deployment_statuses",
+                    Some(("deployment_id", deployment_id)),
+                    json!([field("deployment_status")?]),
+                ),
+            ]
+        }
+        _ => return Err(anyhow!("Unexpected `{event}` event")),
+    };
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    for (data_type, parent, data) in rows {
+        let mut metadata = json!({
+            "host": repo.host,
+            "owner": repo.owner,
+            "repo": repo.name,
+            "etag": "",
+            "webhook": delivery,
+        });
+        if let Some((parent_key, parent_id)) = parent {
+            metadata[parent_key] = json!(parent_id);
+        }
+        tx.execute(
+            r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    ?,
+    ?,
+    ?
+)
+"#,
+            params![
+                Api::GitHub.data_source(),
+                data_type,
+                serde_json::to_string(&metadata)?,
+                serde_json::to_string(&data)?
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// The repo of an event, with the host of its REST API URL so that events from a GitHub
+/// Enterprise Server are named the same way as the repos fetched from it
+fn event_repo(payload: &Value) -> Result<EventRepo> {
+    let repository = payload
+        .get("repository")
+        .ok_or_else(|| anyhow!("No `repository` in event"))?;
+    let string = |value: Option<&Value>, name: &str| {
+        value
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("No repository `{name}` in event"))
+    };
+
+    let url = string(repository.get("url"), "url")?;
+    let parsed = Url::parse(&url).with_context(|| format!("Invalid repository URL {url}"))?;
+    let host = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => anyhow::bail!("Invalid repository URL {url}"),
+    };
+
+    Ok(EventRepo {
+        host,
+        owner: string(
+            repository.get("owner").and_then(|owner| owner.get("login")),
+            "owner",
+        )?,
+        name: string(repository.get("name"), "name")?,
+    })
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crate::{config_value, fetch_run::record_response};
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, RETRY_AFTER},
+    Client, Response, StatusCode,
+};
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// The longest time to wait between retries, not including jitter
+const MAX_BACKOFF_SECS: u64 = 60;
+
+/// A client for the GitLab REST API that retries rate limited requests and transient errors
+pub struct GitLabClient {
+    client: Client,
+    max_retries: u32,
+}
+
+impl GitLabClient {
+    /// Build a client authenticated with the configured `gitlab.auth.token`
+    pub async fn new() -> Result<Self> {
+        let token: String = config_value("gitlab.auth.token").await?;
+        let max_retries: u32 = config_value("gitlab.max_retries").await?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str("application/json")?);
+        let mut authz_value = HeaderValue::from_str(format!("Bearer {}", token).as_str())?;
+        authz_value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, authz_value);
+
+        let client = reqwest::ClientBuilder::new()
+            .user_agent("wallowa/0.2.0")
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            max_retries,
+        })
+    }
+
+    /// Make a GET request to `url`.
+    ///
+    /// Rate limited responses, server errors, and network errors are retried up to
+    /// `max_retries` times, waiting for the `Retry-After` of rate limited responses and with
+    /// exponential backoff otherwise. The last response is returned once the retries run out
+    /// so that the caller can decide how to handle it.
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let result = self.client.get(url).send().await;
+            let retries_left = attempt < self.max_retries;
+
+            let resp = match result {
+                Ok(resp) => resp,
+                Err(e) if retries_left && (e.is_timeout() || e.is_connect() || e.is_request()) => {
+                    let wait = backoff(attempt);
+                    warn!("Error requesting {url}: {e}. Retrying in {wait:?}");
+                    sleep(wait).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            record_response(resp.status().as_u16());
+
+            let status = resp.status();
+            if status == StatusCode::TOO_MANY_REQUESTS && retries_left {
+                let retry_after = resp
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse().ok())
+                    .map(Duration::from_secs);
+                let wait = retry_after.unwrap_or_else(|| backoff(attempt));
+                info!("Rate limited by GitLab; retrying {url} in {wait:?}");
+                sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+
+            if status.is_server_error() && retries_left {
+                let wait = backoff(attempt);
+                warn!("HTTP {status} from request to {url}. Retrying in {wait:?}");
+                sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+
+            return Ok(resp);
+        }
+    }
+}
+
+/// The exponential backoff for a retry `attempt` (starting at 0) with up to 1 second of jitter
+fn backoff(attempt: u32) -> Duration {
+    let secs = 2u64.saturating_pow(attempt).min(MAX_BACKOFF_SECS);
+    // The sub-second part of the current time is random enough to spread out retries
+    let jitter_millis = u64::from(Utc::now().timestamp_subsec_millis());
+    Duration::from_secs(secs) + Duration::from_millis(jitter_millis)
+}
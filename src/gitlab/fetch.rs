@@ -0,0 +1,445 @@
+use super::client::GitLabClient;
+use crate::{
+    config_value,
+    fetch_run::{count_items, record_fetch_run, record_rows_inserted},
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::{stream, StreamExt};
+use reqwest::{
+    header::{HeaderMap, LINK},
+    Url,
+};
+use serde::Deserialize;
+use std::fmt;
+use tracing::{debug, error, info};
+use wallowa_duckdb::{duckdb::params, Pool};
+
+/// A GitLab project and the REST API (gitlab.com or a self-managed instance) it's fetched from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitLabProject {
+    /// The base URL of the REST API, like `https://gitlab.com/api/v4`
+    pub api_url: String,
+    /// The host (and port, if any) of the `api_url`
+    pub host: String,
+    /// The full path of the project, like `group/subgroup/project`
+    pub path: String,
+}
+
+impl GitLabProject {
+    /// The URL of the project in the REST API, which identifies the project by its URL-encoded
+    /// path
+    fn url(&self) -> String {
+        format!(
+            "{}/projects/{}",
+            self.api_url,
+            self.path.replace('/', "%2F")
+        )
+    }
+}
+
+impl fmt::Display for GitLabProject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)
+    }
+}
+
+/// Format a timestamp for the date filters of the GitLab REST API
+fn api_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Fetch merge requests for a specific project, along with the approvals of each merge
+/// request that has been updated since the last fetch.
+///
+/// The approvals on each page are fetched before the page is stored. The watermark comes from
+/// the stored pages, so when fetching approvals fails the page is fetched again next time
+/// rather than its approvals being skipped.
+pub async fn fetch_merge_requests(pool: &Pool, project: &GitLabProject) -> Result<()> {
+    let per_page: String = config_value("gitlab.per_page").await?;
+    let mut url = format!(
+        "{project_url}/merge_requests?state=all&order_by=updated_at&sort=desc&per_page={per_page}",
+        project_url = project.url(),
+        per_page = per_page,
+    );
+
+    if let Some(watermark) = select_watermark(pool, project, "merge_requests")? {
+        url.push_str(&format!("&updated_after={}", updated_after(watermark)));
+    }
+
+    let client = GitLabClient::new().await?;
+    let mut url_opt = Some(url);
+    while let Some(request_url) = url_opt {
+        let Some((text, next_url)) = fetch_page(&client, "merge_requests", &request_url).await?
+        else {
+            break;
+        };
+
+        let merge_requests: Vec<MergeRequestIid> = serde_json::from_str(&text)
+            .with_context(|| format!("Unexpected response from request to {request_url}"))?;
+        info!(
+            "Fetching approvals for {} updated merge requests in {project}",
+            merge_requests.len()
+        );
+        for merge_request in merge_requests {
+            fetch_merge_request_approvals(pool, project, merge_request.iid).await?;
+        }
+
+        store_page(pool, project, "merge_requests", &text)?;
+        url_opt = next_url;
+    }
+
+    Ok(())
+}
+
+/// The `iid` of a merge request, which identifies it within its project
+#[derive(Debug, Deserialize)]
+struct MergeRequestIid {
+    iid: i64,
+}
+
+/// Fetch the approvals of a specific merge request.
+///
+/// Approvals are not fetched incrementally. They are fetched each time the merge request is
+/// updated so that the latest state of the approvals is stored.
+pub async fn fetch_merge_request_approvals(
+    pool: &Pool,
+    project: &GitLabProject,
+    merge_request_iid: i64,
+) -> Result<()> {
+    let client = GitLabClient::new().await?;
+    let url = format!(
+        "{project_url}/merge_requests/{merge_request_iid}/approvals",
+        project_url = project.url(),
+        merge_request_iid = merge_request_iid,
+    );
+
+    info!("Making request to {url}");
+    let resp = client.get(&url).await?;
+    let resp_status = resp.status();
+    let text = resp.text().await?;
+    debug!("Response status code from GitLab merge_request_approvals: {resp_status:?}");
+    if resp_status.is_server_error() || resp_status.is_client_error() {
+        return Err(anyhow!(
+            "HTTP {resp_status}: '{text}' from request to {url}"
+        ));
+    }
+
+    let conn = pool.get()?;
+    conn.execute(
+        r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    'gitlab_rest_api',
+    'merge_request_approvals',
+    to_json({host: ?, project: ?, merge_request_iid: ?}),
+    ?
+)
+"#,
+        params![project.host, project.path, merge_request_iid, text],
+    )?;
+    record_rows_inserted(1);
+
+    Ok(())
+}
+
+/// Fetch CI/CD pipelines for a specific project
+pub async fn fetch_pipelines(pool: &Pool, project: &GitLabProject) -> Result<()> {
+    let per_page: String = config_value("gitlab.per_page").await?;
+    let mut url = format!(
+        "{project_url}/pipelines?order_by=updated_at&sort=desc&per_page={per_page}",
+        project_url = project.url(),
+        per_page = per_page,
+    );
+
+    if let Some(watermark) = select_watermark(pool, project, "pipelines")? {
+        url.push_str(&format!("&updated_after={}", updated_after(watermark)));
+    }
+
+    fetch_pages(pool, project, "pipelines", url).await
+}
+
+/// The `updated_after` filter for items updated after the `watermark`. The filter includes
+/// items updated at exactly the given time, so the time just after the watermark is used to
+/// avoid fetching the latest item again.
+fn updated_after(watermark: DateTime<Utc>) -> String {
+    api_timestamp(watermark + Duration::milliseconds(1))
+}
+
+/// Fetch every page of results starting at `url` for a specific project and store each
+/// non-empty page in `wallowa_raw_data` with the given `data_type`
+async fn fetch_pages(
+    pool: &Pool,
+    project: &GitLabProject,
+    data_type: &str,
+    url: String,
+) -> Result<()> {
+    let client = GitLabClient::new().await?;
+
+    let mut url_opt = Some(url);
+    while let Some(request_url) = url_opt {
+        let Some((text, next_url)) = fetch_page(&client, data_type, &request_url).await? else {
+            break;
+        };
+        store_page(pool, project, data_type, &text)?;
+        url_opt = next_url;
+    }
+
+    Ok(())
+}
+
+/// Fetch a single page of results, returning its body and the URL of the next page. Returns
+/// `None` for an empty page, which means there is nothing new since the last fetch.
+async fn fetch_page(
+    client: &GitLabClient,
+    data_type: &str,
+    request_url: &str,
+) -> Result<Option<(String, Option<String>)>> {
+    info!("Making request to {request_url}");
+    let resp = client.get(request_url).await?;
+
+    let resp_status = resp.status();
+    let resp_headers = resp.headers().clone();
+    let text = resp.text().await?;
+
+    debug!("Response status code from GitLab {data_type}: {resp_status:?}");
+    if resp_status.is_server_error() || resp_status.is_client_error() {
+        // Error - stop making requests and bubble up the error
+        return Err(anyhow!(
+            "HTTP {resp_status}: '{text}' from request to {request_url}"
+        ));
+    }
+
+    if text.trim() == "[]" {
+        return Ok(None);
+    }
+
+    let next_url = next_page_url(&resp_headers)?;
+    Ok(Some((text, next_url)))
+}
+
+/// Store a page of results for a specific project in `wallowa_raw_data` with the given
+/// `data_type`
+fn store_page(pool: &Pool, project: &GitLabProject, data_type: &str, text: &str) -> Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    'gitlab_rest_api',
+    ?,
+    to_json({host: ?, project: ?}),
+    ?
+)
+"#,
+        params![data_type, project.host, project.path, text],
+    )?;
+    record_rows_inserted(count_items(text));
+
+    Ok(())
+}
+
+/// Select the latest `updated_at` of the items of `data_type` stored for a specific project
+fn select_watermark(
+    pool: &Pool,
+    project: &GitLabProject,
+    data_type: &str,
+) -> Result<Option<DateTime<Utc>>> {
+    let conn = pool.get()?;
+
+    let watermark = conn.query_row(
+        r#"
+WITH raw AS (
+    SELECT
+        unnest(json_transform("data", '[{"updated_at": "TIMESTAMP"}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = 'gitlab_rest_api'
+    AND data_type = ?
+    AND (metadata->>'$.host') = ?
+    AND (metadata->>'$.project') = ?
+)
+SELECT max(row.updated_at) AS updated_at
+FROM raw
+"#,
+        params![data_type, project.host, project.path],
+        |row| row.get::<_, Option<DateTime<Utc>>>(0),
+    )?;
+    Ok(watermark)
+}
+
+/// Fetch all of the configured GitLab projects
+pub async fn fetch_all(pool: &Pool) -> Result<()> {
+    info!("Fetching from GitLab");
+
+    let api_url: String = config_value("gitlab.api_url").await?;
+    let max_concurrency: usize = config_value("gitlab.max_concurrency").await?;
+    let project_strings: Vec<String> = config_value("gitlab.repos").await?;
+
+    let mut first_error = None;
+    let mut projects = vec![];
+    for project_string in &project_strings {
+        match parse_project_str(project_string, &api_url) {
+            Ok(project) => projects.push(project),
+            Err(e) => {
+                let e = e.context(format!("Error fetching {project_string} from GitLab"));
+                error!("{e:#}");
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    // Each fetch owns its project and a handle to the pool so that the futures don't borrow
+    // from `projects`, which keeps the resulting future `Send` for `tokio::spawn`
+    let project_fetches = projects.into_iter().map(|project| {
+        let pool = pool.clone();
+        async move { fetch_project(&pool, &project).await }
+    });
+    let results: Vec<Result<()>> = stream::iter(project_fetches)
+        .buffer_unordered(max_concurrency.max(1))
+        .collect()
+        .await;
+
+    for result in results {
+        if let Err(e) = result {
+            error!("{e:#}");
+            first_error.get_or_insert(e);
+        }
+    }
+    info!("Fetching from GitLab complete");
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Fetch all of the data types for a single project, recording the run in `wallowa_fetch_run`
+async fn fetch_project(pool: &Pool, project: &GitLabProject) -> Result<()> {
+    let fetched = record_fetch_run(pool, "gitlab_rest_api", &project.to_string(), async {
+        fetch_merge_requests(pool, project).await?;
+        fetch_pipelines(pool, project).await
+    })
+    .await;
+
+    fetched.with_context(|| format!("Error fetching {project} from GitLab"))
+}
+
+/// Check the GitLab configuration, returning whether any projects are configured to be
+/// fetched. Returns an error for projects that can't be parsed or when no token is configured.
+pub async fn validate_config() -> Result<bool> {
+    let api_url: String = config_value("gitlab.api_url").await?;
+    let project_strings: Vec<String> = config_value("gitlab.repos").await?;
+    if project_strings.is_empty() {
+        return Ok(false);
+    }
+
+    for project_string in &project_strings {
+        parse_project_str(project_string, &api_url)
+            .with_context(|| format!("Invalid project {project_string} in `gitlab.repos`"))?;
+    }
+    if config_value::<String>("gitlab.auth.token").await.is_err() {
+        return Err(anyhow!(
+            "`gitlab.auth.token` is required to fetch from GitLab"
+        ));
+    }
+
+    Ok(true)
+}
+
+/// Parse the URL of the next page of results from the `Link` header, if there is one
+fn next_page_url(headers: &HeaderMap) -> Result<Option<String>> {
+    let next = match headers.get(LINK) {
+        Some(link_header) => {
+            let link_header_str = link_header.to_str()?;
+            match parse_link_header::parse_with_rel(link_header_str) {
+                Ok(links) => links.get("next").map(|next_link| next_link.raw_uri.clone()),
+                Err(e) => {
+                    debug!("Error parsing link header: {}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    Ok(next)
+}
+
+/// Parse a project string of the form `{group}/{project}` (with any number of subgroups)
+/// into a [`GitLabProject`] fetched from the `api_url`.
+///
+/// Returns an error if the string is not in the correct format
+fn parse_project_str(project: &str, api_url: &str) -> Result<GitLabProject> {
+    let path = project.trim_matches('/');
+    if !path.contains('/') || path.split('/').any(|part| part.is_empty()) {
+        anyhow::bail!("Project string must be of the form {{group}}/{{project}}");
+    }
+
+    let api_url = api_url.trim_end_matches('/');
+    let parsed =
+        Url::parse(api_url).with_context(|| format!("Invalid GitLab API URL {api_url}"))?;
+    let host = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => anyhow::bail!("Invalid GitLab API URL {api_url}"),
+    };
+
+    Ok(GitLabProject {
+        api_url: api_url.to_string(),
+        host,
+        path: path.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parse_project_str_reads_nested_groups() {
+        let project =
+            parse_project_str("/group/subgroup/project/", "https://gitlab.com/api/v4/").unwrap();
+        assert_eq!(
+            project,
+            GitLabProject {
+                api_url: "https://gitlab.com/api/v4".to_string(),
+                host: "gitlab.com".to_string(),
+                path: "group/subgroup/project".to_string(),
+            }
+        );
+        assert_eq!(
+            project.url(),
+            "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject"
+        );
+    }
+
+    #[test]
+    fn parse_project_str_keeps_the_port_in_the_host() {
+        let project =
+            parse_project_str("group/project", "https://gitlab.example.com:8443/api/v4").unwrap();
+        assert_eq!(project.host, "gitlab.example.com:8443");
+    }
+
+    #[test]
+    fn parse_project_str_rejects_invalid_projects() {
+        let api_url = "https://gitlab.com/api/v4";
+        assert!(parse_project_str("project", api_url).is_err());
+        assert!(parse_project_str("group//project", api_url).is_err());
+        assert!(parse_project_str("", api_url).is_err());
+        assert!(parse_project_str("group/project", "not a url").is_err());
+    }
+
+    #[test]
+    fn updated_after_skips_the_watermark() {
+        let watermark = Utc.with_ymd_and_hms(2023, 10, 1, 12, 30, 0).unwrap();
+        assert_eq!(updated_after(watermark), "2023-10-01T12:30:00.001Z");
+    }
+}
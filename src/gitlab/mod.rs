@@ -0,0 +1,66 @@
+/// All GitLab-related functionality
+pub mod client;
+pub mod fetch;
+pub mod queries;
+pub mod web;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::{future::BoxFuture, FutureExt};
+use wallowa_duckdb::Pool;
+
+use crate::{
+    source::{NavEntry, Source},
+    web::AppState,
+};
+
+/// Merge request, approval, and pipeline activity from the GitLab REST API
+pub struct GitLabSource;
+
+impl Source for GitLabSource {
+    fn name(&self) -> &'static str {
+        "gitlab"
+    }
+
+    fn label(&self) -> &'static str {
+        "GitLab"
+    }
+
+    fn description(&self) -> &'static str {
+        r#"Collect merge request activity data from
+<a href="https://gitlab.com/" target="_blank" class="link" referrerpolicy="same-origin">GitLab</a>
+or a self-managed GitLab instance."#
+    }
+
+    fn data_source(&self) -> &'static str {
+        "gitlab_rest_api"
+    }
+
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>> {
+        fetch::validate_config().boxed()
+    }
+
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>> {
+        fetch::fetch_all(pool).boxed()
+    }
+
+    fn page_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::page_routes()
+    }
+
+    fn data_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::data_routes()
+    }
+
+    fn nav_entries(&self) -> Vec<NavEntry> {
+        vec![NavEntry {
+            children: vec![
+                NavEntry::new("/gitlab/closed_mr_count", "Closed MR count"),
+                NavEntry::new("/gitlab/mr_duration", "MR duration"),
+            ],
+            ..NavEntry::new("/gitlab", "GitLab")
+        }]
+    }
+}
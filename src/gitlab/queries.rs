@@ -0,0 +1,202 @@
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, FixedOffset};
+use tracing::{debug, error};
+use wallowa_duckdb::duckdb::{params_from_iter, ToSql};
+use wallowa_duckdb::Pool;
+
+/// Get the list of distinct GitLab project paths (like `group/project`) in the database
+pub fn select_distinct_repos(pool: &Pool) -> Result<Vec<String>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(
+        r#"
+SELECT DISTINCT (metadata->>'$.project') AS repo
+FROM wallowa_raw_data
+WHERE "data_source" = 'gitlab_rest_api'
+AND data_type = 'merge_requests'
+ORDER BY repo
+"#,
+    )?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    let mut repo_names = vec![];
+    for row in rows {
+        match row {
+            Ok(repo_name) => repo_names.push(repo_name),
+            Err(e) => error!("Error querying distinct GitLab projects: {:?}", e),
+        }
+    }
+    Ok(repo_names)
+}
+
+/// The CTEs shared by the merge request queries: `merge_requests` (every stored merge
+/// request), `repos` (the selected projects), and `latest_deduped_merge_requests` (the
+/// latest version of each merge request of the selected projects)
+fn merge_requests_ctes(repo_placeholders: &str) -> String {
+    format!(
+        r#"
+merge_requests AS (
+    SELECT
+        metadata,
+        unnest(json_transform("data",
+            '[{{
+                "id": "BIGINT",
+                "state": "VARCHAR",
+                "created_at": "TIMESTAMP",
+                "updated_at": "TIMESTAMP",
+                "merged_at": "TIMESTAMP",
+                "closed_at": "TIMESTAMP",
+                "draft": "BOOLEAN"
+            }}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = 'gitlab_rest_api'
+    AND data_type = 'merge_requests'
+),
+repos AS (
+    {repo_placeholders}
+),
+latest_deduped_merge_requests AS (
+    SELECT
+        row.id AS id,
+        (metadata->>'$.project') AS repo,
+        row.state AS state,
+        row.created_at AS created_at,
+        row.merged_at AS merged_at,
+        -- Merged merge requests don't have a `closed_at` so they are closed when merged
+        coalesce(row.closed_at, row.merged_at) AS closed_at,
+        row.updated_at AS updated_at,
+        row_number() OVER (PARTITION BY (metadata->>'$.host'), row.id ORDER BY row.updated_at DESC) AS row_number
+    FROM merge_requests
+    WHERE repo IN (SELECT repo FROM repos)
+    QUALIFY row_number = 1
+)"#,
+        repo_placeholders = repo_placeholders
+    )
+}
+
+/// The SQL for the selected `repos`, or every project with merge requests when none are
+/// selected
+fn repo_placeholders(repos: &[String]) -> String {
+    if repos.is_empty() {
+        "SELECT DISTINCT (metadata->>'$.project') AS repo FROM merge_requests".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    }
+}
+
+/// Query the rolling daily average time to merge GitLab merge requests
+pub fn merged_mr_duration_rolling_daily_average(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `merged_mr_duration_rolling_daily_average`");
+
+    let conn = pool.get()?;
+
+    let repo_placeholders = repo_placeholders(repos);
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        r#"
+-- merged_mr_duration_rolling_daily_average
+-- Duration of merged GitLab merge requests, rolling daily average
+WITH calendar_day AS (
+    -- Generate a series of days so that each day has a rolling average represented
+    SELECT CAST(unnest(generate_series(CAST(? AS TIMESTAMP), CAST(? AS TIMESTAMP), interval '1' day)) AS DATE) as "day"
+),
+{ctes},
+calendar_day_repos AS (
+    -- Generate a series of days for each repo so that each day+repo has a rolling average represented
+    SELECT calendar_day."day", repos.repo FROM calendar_day CROSS JOIN repos
+),
+rolling AS (
+    SELECT
+        repo,
+        CAST(created_at AS DATE) AS created_date,
+        CAST(merged_at AS DATE) AS merged_date,
+        AVG(EPOCH(AGE(merged_at, created_at)) / 86400) OVER thirty AS duration
+    FROM latest_deduped_merge_requests
+    WHERE merged_at NOT NULL
+    WINDOW thirty AS (
+        PARTITION BY repo
+        ORDER BY created_at ASC
+        RANGE BETWEEN INTERVAL 30 DAYS PRECEDING
+                AND INTERVAL 0 DAYS FOLLOWING)
+)
+SELECT calendar_day_repos."day" AS "day", rolling.repo, AVG(rolling.duration) AS "duration"
+FROM calendar_day_repos ASOF LEFT JOIN rolling ON (calendar_day_repos.repo = rolling.repo AND calendar_day_repos."day" >= rolling.merged_date)
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+        ctes = merge_requests_ctes(&repo_placeholders)
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
+
+/// Query the closed (including merged) GitLab merge requests
+pub fn closed_mrs(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `closed_mrs`");
+
+    let conn = pool.get()?;
+
+    let repo_placeholders = repo_placeholders(repos);
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        r#"
+WITH {ctes}
+SELECT
+    id,
+    repo,
+    created_at,
+    merged_at,
+    updated_at,
+    CAST(closed_at AS DATE) AS closed_at
+FROM latest_deduped_merge_requests
+WHERE state IN ('closed', 'merged')
+AND closed_at >= ?
+AND closed_at <= ?
+"#,
+        ctes = merge_requests_ctes(&repo_placeholders)
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
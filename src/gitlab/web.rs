@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use axum::{body::Body, extract::State, response::Html, routing::get, Router};
+use axum_extra::extract::Query;
+use minijinja::context;
+
+use crate::{
+    web::{date_range, render, to_arrow_ipc, AppState, ChartParams},
+    AppResult,
+};
+
+use super::queries::{closed_mrs, merged_mr_duration_rolling_daily_average, select_distinct_repos};
+
+/// All page-related routes for GitLab
+pub fn page_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/mr_duration", get(gitlab_mr_duration))
+        .route("/closed_mr_count", get(gitlab_closed_mr_count))
+        .route("/", get(gitlab_dashboard))
+}
+
+/// All data-related routes for GitLab
+pub fn data_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route(
+            "/merged_mr_duration_rolling_daily_average.arrow",
+            get(merged_mr_duration_rolling_daily_average_arrow),
+        )
+        .route("/closed_mrs.arrow", get(closed_mrs_arrow))
+}
+
+async fn merged_mr_duration_rolling_daily_average_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = merged_mr_duration_rolling_daily_average(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn closed_mrs_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = closed_mrs(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn gitlab_mr_duration(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool)?;
+    let html = render(
+        state,
+        "gitlab/mr_duration.html",
+        context! {
+            current_nav => "/gitlab/mr_duration",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn gitlab_closed_mr_count(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool)?;
+    let html = render(
+        state,
+        "gitlab/mr_count.html",
+        context! {
+            current_nav => "/gitlab/closed_mr_count",
+            repos => distinct_repos,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn gitlab_dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let html = render(
+        state,
+        "gitlab/index.html",
+        context! {
+            current_nav => "/gitlab",
+        },
+    )?;
+    Ok(Html(html))
+}
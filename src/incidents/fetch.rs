@@ -0,0 +1,253 @@
+use super::client::PagerDutyClient;
+use crate::{
+    config_value,
+    fetch_run::{record_fetch_run, record_rows_inserted},
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+use tracing::{debug, info};
+use wallowa_duckdb::{duckdb::params, Pool};
+
+/// The `data_source` of the rows fetched from PagerDuty
+pub const DATA_SOURCE: &str = "pagerduty_rest_api";
+
+/// The number of days of incidents and log entries to request at a time. Classic pagination
+/// stops at 10,000 results, so shorter windows keep busy accounts under that limit.
+const WINDOW_DAYS: i64 = 7;
+
+/// A page of results from a PagerDuty list endpoint. The items are in the field named after
+/// the endpoint (`incidents` or `log_entries`).
+#[derive(Debug, Deserialize)]
+struct ListPage {
+    #[serde(default)]
+    more: bool,
+    #[serde(flatten)]
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Fetch the incidents, and the log entries used to find when each incident was first
+/// acknowledged, that were created since the last fetch.
+///
+/// Incidents that were still open at the last fetch are fetched again so that their
+/// acknowledgements and resolutions are picked up. The queries only use the latest version of
+/// each incident.
+pub async fn fetch_incidents(pool: &Pool, api_url: &str, host: &str) -> Result<()> {
+    let lookback_days: i64 = config_value("pagerduty.lookback_days").await?;
+    let client = PagerDutyClient::new().await?;
+
+    let until = Utc::now();
+    let earliest = until - Duration::days(lookback_days);
+    let since = match select_window_start(pool, host)? {
+        Some(start) => start.max(earliest),
+        None => earliest,
+    };
+    debug!("Fetching PagerDuty incidents created between {since} and {until}");
+
+    let mut window_start = since;
+    while window_start < until {
+        let window_end = (window_start + Duration::days(WINDOW_DAYS)).min(until);
+        fetch_list(
+            pool,
+            &client,
+            api_url,
+            host,
+            "incidents",
+            window_start,
+            window_end,
+        )
+        .await?;
+        fetch_list(
+            pool,
+            &client,
+            api_url,
+            host,
+            "log_entries",
+            window_start,
+            window_end,
+        )
+        .await?;
+        window_start = window_end;
+    }
+
+    Ok(())
+}
+
+/// Fetch every page of the `endpoint` (`incidents` or `log_entries`) between `since` and
+/// `until`, storing each page as a row with the `endpoint` as its `data_type`
+async fn fetch_list(
+    pool: &Pool,
+    client: &PagerDutyClient,
+    api_url: &str,
+    host: &str,
+    endpoint: &str,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<()> {
+    let per_page: usize = config_value("pagerduty.per_page").await?;
+    let service_ids: Vec<String> = config_value("pagerduty.service_ids").await?;
+
+    let since = since.to_rfc3339();
+    let until = until.to_rfc3339();
+    let limit = per_page.to_string();
+    let mut base_params = vec![
+        ("since", since.as_str()),
+        ("until", until.as_str()),
+        ("time_zone", "UTC"),
+        ("limit", limit.as_str()),
+    ];
+    if endpoint == "incidents" {
+        base_params.push(("sort_by", "created_at:asc"));
+        for service_id in &service_ids {
+            base_params.push(("service_ids[]", service_id.as_str()));
+        }
+    } else {
+        // Only the overview entries (triggers, acknowledgements, resolutions, and so on)
+        base_params.push(("is_overview", "true"));
+    }
+
+    let endpoint_url = format!("{api_url}/{endpoint}");
+    let mut offset = 0;
+    loop {
+        let offset_param = offset.to_string();
+        let request_url = Url::parse_with_params(
+            &endpoint_url,
+            base_params
+                .iter()
+                .copied()
+                .chain([("offset", offset_param.as_str())]),
+        )?;
+        info!("Making request to {request_url}");
+        let resp = client.get(request_url.as_str()).await?;
+
+        let resp_status = resp.status();
+        let text = resp.text().await?;
+
+        debug!("Response status code from PagerDuty {endpoint}: {resp_status:?}");
+        if resp_status.is_server_error() || resp_status.is_client_error() {
+            // Error - stop making requests and bubble up the error
+            return Err(anyhow!(
+                "HTTP {resp_status}: '{text}' from request to {request_url}"
+            ));
+        }
+
+        let page: ListPage = serde_json::from_str(&text)
+            .with_context(|| format!("Unexpected response from request to {request_url}"))?;
+        let items = match page.fields.get(endpoint) {
+            Some(serde_json::Value::Array(items)) => items,
+            _ => return Err(anyhow!("No `{endpoint}` in response to {request_url}")),
+        };
+        if items.is_empty() {
+            break;
+        }
+
+        // Only the items are stored since the rest of the page is only used for paging
+        let conn = pool.get()?;
+        conn.execute(
+            r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    ?,
+    to_json({host: ?}),
+    ?
+)
+"#,
+            params![DATA_SOURCE, endpoint, host, serde_json::to_string(items)?],
+        )?;
+        record_rows_inserted(items.len() as u64);
+
+        if !page.more {
+            break;
+        }
+        offset += items.len();
+    }
+
+    Ok(())
+}
+
+/// Select the time to start fetching from: the creation time of the earliest incident that
+/// was open at the last fetch, or of the latest incident when none were open
+fn select_window_start(pool: &Pool, host: &str) -> Result<Option<DateTime<Utc>>> {
+    let conn = pool.get()?;
+
+    let start = conn.query_row(
+        r#"
+WITH raw AS (
+    SELECT
+        id AS raw_id,
+        unnest(json_transform("data", '[{"id": "VARCHAR", "status": "VARCHAR", "created_at": "TIMESTAMP"}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = ?
+    AND data_type = 'incidents'
+    AND (metadata->>'$.host') = ?
+),
+latest AS (
+    SELECT row.status AS status, row.created_at AS created_at
+    FROM raw
+    QUALIFY row_number() OVER (PARTITION BY row.id ORDER BY raw_id DESC) = 1
+)
+SELECT coalesce(min(created_at) FILTER (WHERE status <> 'resolved'), max(created_at))
+FROM latest
+"#,
+        params![DATA_SOURCE, host],
+        |row| row.get::<_, Option<DateTime<Utc>>>(0),
+    )?;
+    Ok(start)
+}
+
+/// Fetch from PagerDuty, recording the run in `wallowa_fetch_run`
+pub async fn fetch_all(pool: &Pool) -> Result<()> {
+    info!("Fetching from PagerDuty");
+
+    let (api_url, host) = configured_api().await?;
+    let fetched = record_fetch_run(
+        pool,
+        DATA_SOURCE,
+        &host,
+        fetch_incidents(pool, &api_url, &host),
+    )
+    .await;
+
+    info!("Fetching from PagerDuty complete");
+    fetched.context("Error fetching incidents from PagerDuty")
+}
+
+/// Check the PagerDuty configuration, returning whether a token is configured so that
+/// incidents can be fetched. Returns an error for an invalid `pagerduty.api_url` or
+/// `pagerduty.lookback_days`.
+pub async fn validate_config() -> Result<bool> {
+    if config_value::<Option<String>>("pagerduty.auth.token")
+        .await?
+        .is_none()
+    {
+        return Ok(false);
+    }
+
+    configured_api().await?;
+    let lookback_days: i64 = config_value("pagerduty.lookback_days").await?;
+    if lookback_days < 1 {
+        return Err(anyhow!("`pagerduty.lookback_days` must be at least 1"));
+    }
+
+    Ok(true)
+}
+
+/// The configured `pagerduty.api_url` (without a trailing `/`) and its host (and port, if any)
+async fn configured_api() -> Result<(String, String)> {
+    let api_url: String = config_value("pagerduty.api_url").await?;
+    let api_url = api_url.trim_end_matches('/').to_string();
+    let parsed =
+        Url::parse(&api_url).with_context(|| format!("Invalid PagerDuty API URL {api_url}"))?;
+    let host = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => anyhow::bail!("Invalid PagerDuty API URL {api_url}"),
+    };
+    Ok((api_url, host))
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use tracing::info;
+use wallowa_duckdb::{duckdb::params, Pool};
+
+/// The `data_source` of the rows imported from JSON and CSV files
+pub const DATA_SOURCE: &str = "incident_import";
+
+/// An incident in the format the incident queries expect, with all times in UTC
+#[derive(Debug, Serialize)]
+struct ImportedIncident {
+    id: String,
+    service: String,
+    severity: Option<String>,
+    created_at: String,
+    acknowledged_at: Option<String>,
+    resolved_at: Option<String>,
+}
+
+/// Import the incidents in the JSON or CSV file at `path` (chosen by its extension),
+/// returning the number of incidents imported.
+///
+/// A JSON file holds an array of objects and a CSV file has a header row. Each incident needs
+/// an `id`, `service`, and `created_at`, and can have a `severity`, `acknowledged_at`, and
+/// `resolved_at`. Importing an incident again replaces it in the queries, so a file can be
+/// re-imported as incidents are acknowledged and resolved.
+pub async fn import_incidents(pool: &Pool, path: &str) -> Result<usize> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let records: Vec<Value> = match extension.as_deref() {
+        Some("json") => {
+            let text = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Unable to read {path}"))?;
+            serde_json::from_str(&text)
+                .with_context(|| format!("{path} must hold a JSON array of incidents"))?
+        }
+        Some("csv") => read_csv(pool, path)?,
+        _ => return Err(anyhow!("{path} must be a `.json` or `.csv` file")),
+    };
+
+    let incidents = records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| {
+            match record {
+                Value::Object(fields) => parse_incident(fields),
+                _ => Err(anyhow!("not an object")),
+            }
+            .with_context(|| format!("Invalid incident {} in {path}", index + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if incidents.is_empty() {
+        info!("No incidents found in {path}");
+        return Ok(0);
+    }
+
+    let file_name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string());
+    let conn = pool.get()?;
+    conn.execute(
+        r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    'incidents',
+    to_json({file: ?}),
+    ?
+)
+"#,
+        params![DATA_SOURCE, file_name, serde_json::to_string(&incidents)?],
+    )?;
+
+    info!("Imported {} incidents from {path}", incidents.len());
+    Ok(incidents.len())
+}
+
+/// Read the rows of a CSV file (with a header row) as JSON objects of strings
+fn read_csv(pool: &Pool, path: &str) -> Result<Vec<Value>> {
+    let conn = pool.get()?;
+    let rows: Option<String> = conn
+        .query_row(
+            r#"
+SELECT CAST(to_json(list(t)) AS VARCHAR)
+FROM read_csv_auto(?, header = true, all_varchar = true) t
+"#,
+            [path],
+            |row| row.get(0),
+        )
+        .with_context(|| format!("Unable to read {path} as CSV"))?;
+    match rows {
+        Some(rows) => Ok(serde_json::from_str(&rows)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Parse an incident from the `fields` of a JSON object or CSV row
+fn parse_incident(fields: &Map<String, Value>) -> Result<ImportedIncident> {
+    let required =
+        |name: &str| optional_string(fields, name)?.ok_or_else(|| anyhow!("`{name}` is required"));
+    let timestamp = |name: &str| match optional_string(fields, name)? {
+        Some(value) => parse_timestamp(&value)
+            .map(Some)
+            .with_context(|| format!("`{name}` has an invalid time {value:?}")),
+        None => Ok(None),
+    };
+
+    let created_at = timestamp("created_at")?.ok_or_else(|| anyhow!("`created_at` is required"))?;
+    Ok(ImportedIncident {
+        id: required("id")?,
+        service: required("service")?,
+        severity: optional_string(fields, "severity")?,
+        created_at,
+        acknowledged_at: timestamp("acknowledged_at")?,
+        resolved_at: timestamp("resolved_at")?,
+    })
+}
+
+/// The string or number in the field `name`, treating a missing, `null`, or blank field as
+/// `None`
+fn optional_string(fields: &Map<String, Value>, name: &str) -> Result<Option<String>> {
+    match fields.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) if value.trim().is_empty() => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.trim().to_string())),
+        Some(Value::Number(value)) => Ok(Some(value.to_string())),
+        Some(_) => Err(anyhow!("`{name}` must be a string")),
+    }
+}
+
+/// Parse an RFC 3339 time, or a time without an offset in UTC, into an RFC 3339 time in UTC
+fn parse_timestamp(value: &str) -> Result<String> {
+    let timestamp = match DateTime::parse_from_rfc3339(value) {
+        Ok(timestamp) => timestamp.with_timezone(&Utc),
+        Err(_) => NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+            .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f"))?
+            .and_utc(),
+    };
+    Ok(timestamp.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn parse_timestamp_converts_to_utc() {
+        assert_eq!(
+            parse_timestamp("2023-10-01T12:00:00+02:00").unwrap(),
+            "2023-10-01T10:00:00Z"
+        );
+        assert_eq!(
+            parse_timestamp("2023-10-01T12:00:00.5Z").unwrap(),
+            "2023-10-01T12:00:00.500Z"
+        );
+    }
+
+    #[test]
+    fn parse_timestamp_treats_times_without_an_offset_as_utc() {
+        assert_eq!(
+            parse_timestamp("2023-10-01 12:00:00").unwrap(),
+            "2023-10-01T12:00:00Z"
+        );
+        assert_eq!(
+            parse_timestamp("2023-10-01T12:00:00").unwrap(),
+            "2023-10-01T12:00:00Z"
+        );
+    }
+
+    #[test]
+    fn parse_timestamp_rejects_invalid_times() {
+        assert!(parse_timestamp("2023-10-01").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn parse_incident_reads_csv_and_json_fields() {
+        let incident = parse_incident(&fields(json!({
+            "id": 42,
+            "service": " api ",
+            "severity": "",
+            "created_at": "2023-10-01 12:00:00",
+            "acknowledged_at": null,
+            "resolved_at": "2023-10-01T13:00:00Z",
+        })))
+        .unwrap();
+
+        assert_eq!(incident.id, "42");
+        assert_eq!(incident.service, "api");
+        assert_eq!(incident.severity, None);
+        assert_eq!(incident.created_at, "2023-10-01T12:00:00Z");
+        assert_eq!(incident.acknowledged_at, None);
+        assert_eq!(
+            incident.resolved_at.as_deref(),
+            Some("2023-10-01T13:00:00Z")
+        );
+    }
+
+    #[test]
+    fn parse_incident_rejects_invalid_incidents() {
+        let valid = json!({"id": "1", "service": "api", "created_at": "2023-10-01T12:00:00Z"});
+        assert!(parse_incident(&fields(valid.clone())).is_ok());
+
+        for (name, value) in [
+            ("id", Value::Null),
+            ("service", json!("  ")),
+            ("created_at", Value::Null),
+            ("created_at", json!("not a time")),
+            ("resolved_at", json!("not a time")),
+            ("severity", json!(["high"])),
+        ] {
+            let mut invalid = fields(valid.clone());
+            invalid.insert(name.to_string(), value);
+            assert!(parse_incident(&invalid).is_err(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+/// All incident-related functionality
+pub mod client;
+pub mod fetch;
+pub mod import;
+pub mod queries;
+pub mod web;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::{future::BoxFuture, FutureExt};
+use wallowa_duckdb::Pool;
+
+use crate::{
+    source::{NavEntry, Source},
+    web::AppState,
+};
+
+/// Incidents fetched from the PagerDuty REST API or imported from JSON and CSV files
+pub struct IncidentSource;
+
+impl Source for IncidentSource {
+    fn name(&self) -> &'static str {
+        "incidents"
+    }
+
+    fn label(&self) -> &'static str {
+        "Incidents"
+    }
+
+    fn description(&self) -> &'static str {
+        r#"Collect incidents from
+<a href="https://www.pagerduty.com/" target="_blank" class="link" referrerpolicy="same-origin">PagerDuty</a>
+or import them from JSON and CSV files with <code>wallowa import-incidents</code>."#
+    }
+
+    fn data_source(&self) -> &'static str {
+        fetch::DATA_SOURCE
+    }
+
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>> {
+        fetch::validate_config().boxed()
+    }
+
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>> {
+        fetch::fetch_all(pool).boxed()
+    }
+
+    fn page_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::page_routes()
+    }
+
+    fn data_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::data_routes()
+    }
+
+    fn nav_entries(&self) -> Vec<NavEntry> {
+        vec![NavEntry {
+            children: vec![NavEntry::new("/incidents/response_times", "Response times")],
+            ..NavEntry::new("/incidents", "Incidents")
+        }]
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, FixedOffset};
+use tracing::{debug, error};
+use wallowa_duckdb::duckdb::{params_from_iter, ToSql};
+use wallowa_duckdb::Pool;
+
+use super::{fetch, import};
+
+/// Get the list of distinct services of the incidents in the database
+pub fn select_distinct_services(pool: &Pool) -> Result<Vec<String>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(&format!(
+        r#"
+WITH {ctes}
+SELECT DISTINCT service
+FROM incidents
+WHERE service IS NOT NULL
+ORDER BY service
+"#,
+        ctes = incidents_ctes("SELECT DISTINCT service FROM incidents")
+    ))?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    let mut services = vec![];
+    for row in rows {
+        match row {
+            Ok(service) => services.push(service),
+            Err(e) => error!("Error querying distinct incident services: {:?}", e),
+        }
+    }
+    Ok(services)
+}
+
+/// The CTEs shared by the incident queries: `incidents` (every stored version of every
+/// incident from PagerDuty and imports, in the same shape), `services` (the selected
+/// services), and `latest_deduped_incidents` (the latest version of each incident of the
+/// selected services).
+///
+/// PagerDuty incidents are acknowledged at the time of their first acknowledgement in the log
+/// entries, and use their priority as their severity, falling back to their urgency.
+fn incidents_ctes(service_placeholders: &str) -> String {
+    format!(
+        r#"
+pagerduty_incidents AS (
+    SELECT
+        id AS raw_id,
+        unnest(json_transform("data",
+            '[{{
+                "id": "VARCHAR",
+                "status": "VARCHAR",
+                "urgency": "VARCHAR",
+                "created_at": "TIMESTAMP",
+                "resolved_at": "TIMESTAMP",
+                "last_status_change_at": "TIMESTAMP",
+                "service": {{"summary": "VARCHAR"}},
+                "priority": {{"summary": "VARCHAR"}}
+            }}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = '{PAGERDUTY}'
+    AND data_type = 'incidents'
+),
+pagerduty_log_entries AS (
+    SELECT
+        unnest(json_transform("data",
+            '[{{"type": "VARCHAR", "created_at": "TIMESTAMP", "incident": {{"id": "VARCHAR"}}}}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = '{PAGERDUTY}'
+    AND data_type = 'log_entries'
+),
+pagerduty_acknowledgements AS (
+    SELECT row.incident.id AS incident_id, min(row.created_at) AS acknowledged_at
+    FROM pagerduty_log_entries
+    WHERE row.type = 'acknowledge_log_entry'
+    GROUP BY 1
+),
+imported_incidents AS (
+    SELECT
+        id AS raw_id,
+        unnest(json_transform("data",
+            '[{{
+                "id": "VARCHAR",
+                "service": "VARCHAR",
+                "severity": "VARCHAR",
+                "created_at": "TIMESTAMP",
+                "acknowledged_at": "TIMESTAMP",
+                "resolved_at": "TIMESTAMP"
+            }}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = '{IMPORT}'
+    AND data_type = 'incidents'
+),
+incidents AS (
+    SELECT
+        raw_id,
+        '{PAGERDUTY}' AS "data_source",
+        row.id AS id,
+        row.service.summary AS service,
+        coalesce(row.priority.summary, row.urgency) AS severity,
+        row.created_at AS created_at,
+        acknowledgements.acknowledged_at AS acknowledged_at,
+        coalesce(
+            row.resolved_at,
+            CASE WHEN row.status = 'resolved' THEN row.last_status_change_at END
+        ) AS resolved_at,
+    FROM pagerduty_incidents
+    LEFT JOIN pagerduty_acknowledgements AS acknowledgements
+        ON acknowledgements.incident_id = row.id
+    UNION ALL
+    SELECT
+        raw_id,
+        '{IMPORT}' AS "data_source",
+        row.id AS id,
+        row.service AS service,
+        row.severity AS severity,
+        row.created_at AS created_at,
+        row.acknowledged_at AS acknowledged_at,
+        row.resolved_at AS resolved_at,
+    FROM imported_incidents
+),
+services AS (
+    {service_placeholders}
+),
+latest_deduped_incidents AS (
+    SELECT *
+    FROM (
+        SELECT *
+        FROM incidents
+        QUALIFY row_number() OVER (PARTITION BY "data_source", id ORDER BY raw_id DESC) = 1
+    )
+    WHERE service IN (SELECT service FROM services)
+)"#,
+        PAGERDUTY = fetch::DATA_SOURCE,
+        IMPORT = import::DATA_SOURCE,
+        service_placeholders = service_placeholders
+    )
+}
+
+/// Query the mean time to acknowledge (MTTA) and mean time to resolve (MTTR), in minutes, of
+/// the incidents created in the date range by service. Incidents that haven't been
+/// acknowledged or resolved are counted, but left out of the MTTA or MTTR.
+pub fn mtta_mttr(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    services: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `mtta_mttr`");
+
+    let conn = pool.get()?;
+
+    let service_placeholders = if services.is_empty() {
+        "SELECT DISTINCT service FROM incidents".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(services.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS service", placeholders)
+    };
+    debug!(
+        "service_placeholders: {:?} for {:?}",
+        service_placeholders, services
+    );
+
+    let mut stmt = conn.prepare(&format!(
+        r#"
+WITH {ctes}
+SELECT
+    service,
+    COUNT(*) AS incidents,
+    avg(EPOCH(AGE(acknowledged_at, created_at)) / 60)
+        FILTER (WHERE acknowledged_at >= created_at) AS mtta_minutes,
+    avg(EPOCH(AGE(resolved_at, created_at)) / 60)
+        FILTER (WHERE resolved_at >= created_at) AS mttr_minutes
+FROM latest_deduped_incidents
+WHERE created_at >= ?
+AND created_at <= ?
+GROUP BY 1
+ORDER BY 1
+"#,
+        ctes = incidents_ctes(&service_placeholders)
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    for service in services {
+        params.push(service.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use axum::{body::Body, extract::State, response::Html, routing::get, Router};
+use axum_extra::extract::Query;
+use minijinja::context;
+
+use crate::{
+    web::{date_range, render, to_arrow_ipc, AppState, ChartParams},
+    AppResult,
+};
+
+use super::queries::{mtta_mttr, select_distinct_services};
+
+/// All page-related routes for incidents
+pub fn page_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/response_times", get(incidents_response_times))
+        .route("/", get(incidents_dashboard))
+}
+
+/// All data-related routes for incidents
+pub fn data_routes() -> Router<Arc<AppState>, Body> {
+    Router::new().route("/mtta_mttr.arrow", get(mtta_mttr_arrow))
+}
+
+/// MTTA and MTTR by service. The `repo` parameters select the services.
+async fn mtta_mttr_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = mtta_mttr(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn incidents_response_times(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_services = select_distinct_services(&state.read_pool)?;
+    let html = render(
+        state,
+        "incidents/response_times.html",
+        context! {
+            current_nav => "/incidents/response_times",
+            repos => distinct_services,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn incidents_dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let html = render(
+        state,
+        "incidents/index.html",
+        context! {
+            current_nav => "/incidents",
+        },
+    )?;
+    Ok(Html(html))
+}
@@ -0,0 +1,141 @@
+//! Accept events pushed to `POST /ingest/{source}/{type}` and store them in `wallowa_raw_data`
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tokio::task;
+use tracing::{debug, info};
+use wallowa_duckdb::{duckdb::params, Pool};
+
+use crate::{config_value, source, web::AppState, AppResult};
+
+/// The longest `data_source` or `data_type` that can be pushed
+const MAX_NAME_LEN: usize = 64;
+
+/// Store the JSON event in the request body as a row of `wallowa_raw_data` with the
+/// `data_source` and `data_type` from the path. The query parameters are stored as the
+/// `metadata` of the row.
+///
+/// Requests must have an `Authorization: Bearer {ingest.auth.token}` header. Ingestion is
+/// disabled until `ingest.auth.token` is configured. The `data_source` of a registered source
+/// can't be pushed to so that pushed events don't mix with fetched data.
+pub async fn ingest(
+    State(state): State<Arc<AppState>>,
+    Path((data_source, data_type)): Path<(String, String)>,
+    Query(metadata): Query<BTreeMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Response> {
+    let Some(token) = config_value::<Option<String>>("ingest.auth.token").await? else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            "Ingestion is disabled. Configure `ingest.auth.token` to enable it.",
+        )
+            .into_response());
+    };
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !bearer.is_some_and(|bearer| constant_time_eq(bearer.as_bytes(), token.as_bytes())) {
+        return Ok((StatusCode::UNAUTHORIZED, "Invalid or missing bearer token").into_response());
+    }
+
+    for name in [&data_source, &data_type] {
+        if !is_valid_name(name) {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Invalid name {name:?}. Names are up to {MAX_NAME_LEN} lowercase letters, \
+                     digits, and underscores."
+                ),
+            )
+                .into_response());
+        }
+    }
+    if source::sources()
+        .iter()
+        .any(|source| source.data_source() == data_source)
+    {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!("`{data_source}` is fetched by a source and can't be pushed to"),
+        )
+            .into_response());
+    }
+
+    // The body is stored as it was sent once it's known to be valid JSON
+    if let Err(e) = serde_json::from_slice::<serde_json::Value>(&body) {
+        return Ok((StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}")).into_response());
+    }
+    let data = std::str::from_utf8(&body)?.to_string();
+
+    // The insert waits for the write connection, which a fetch can hold for a while, so it
+    // runs off of the async workers
+    let (pool, pushed_source, pushed_type, metadata_json) = (
+        state.pool.clone(),
+        data_source.clone(),
+        data_type.clone(),
+        serde_json::to_string(&metadata)?,
+    );
+    let id = task::spawn_blocking(move || {
+        store_event(&pool, &pushed_source, &pushed_type, &metadata_json, &data)
+    })
+    .await??;
+    debug!("Stored pushed event {id} with metadata {metadata:?}");
+    info!("Ingested a `{data_type}` event for `{data_source}`");
+
+    Ok((StatusCode::CREATED, Json(json!({ "id": id }))).into_response())
+}
+
+/// Store the pushed event `data` as a row of `wallowa_raw_data`, returning the `id` of the row
+fn store_event(
+    pool: &Pool,
+    data_source: &str,
+    data_type: &str,
+    metadata: &str,
+    data: &str,
+) -> Result<i64> {
+    let conn = pool.get()?;
+    let id = conn.query_row(
+        r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    ?,
+    ?,
+    ?
+)
+RETURNING id
+"#,
+        params![data_source, data_type, metadata, data],
+        |row| row.get::<_, i64>(0),
+    )?;
+    Ok(id)
+}
+
+/// Whether `name` can be used as a pushed `data_source` or `data_type`
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Compare the bytes in constant time so that the time taken doesn't reveal how much of a
+/// token matched
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
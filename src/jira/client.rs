@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use crate::{config_value, fetch_run::record_response};
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, RETRY_AFTER},
+    Client, Response, StatusCode,
+};
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// The longest time to wait between retries, not including jitter
+const MAX_BACKOFF_SECS: u64 = 60;
+
+/// A client for the Jira REST API that retries rate limited requests and transient errors
+pub struct JiraClient {
+    client: Client,
+    /// The `jira.auth.email` of the account that the token belongs to, if any
+    email: Option<String>,
+    token: String,
+    max_retries: u32,
+}
+
+impl JiraClient {
+    /// Build a client authenticated with the configured `jira.auth.token`. The token is used
+    /// with basic auth as an API token for the `jira.auth.email` account when it is set (for
+    /// Jira Cloud), and as a personal access token otherwise (for Jira Data Center).
+    pub async fn new() -> Result<Self> {
+        let token: String = config_value("jira.auth.token").await?;
+        let email: Option<String> = config_value("jira.auth.email").await?;
+        let max_retries: u32 = config_value("jira.max_retries").await?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str("application/json")?);
+
+        let client = reqwest::ClientBuilder::new()
+            .user_agent("wallowa/0.2.0")
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            email,
+            token,
+            max_retries,
+        })
+    }
+
+    /// Make a GET request to `url`.
+    ///
+    /// Rate limited responses, server errors, and network errors are retried up to
+    /// `max_retries` times, waiting for the `Retry-After` of rate limited responses and with
+    /// exponential backoff otherwise. The last response is returned once the retries run out
+    /// so that the caller can decide how to handle it.
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let request = match &self.email {
+                Some(email) => self.client.get(url).basic_auth(email, Some(&self.token)),
+                None => self.client.get(url).bearer_auth(&self.token),
+            };
+            let result = request.send().await;
+            let retries_left = attempt < self.max_retries;
+
+            let resp = match result {
+                Ok(resp) => resp,
+                Err(e) if retries_left && (e.is_timeout() || e.is_connect() || e.is_request()) => {
+                    let wait = backoff(attempt);
+                    warn!("Error requesting {url}: {e}. Retrying in {wait:?}");
+                    sleep(wait).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            record_response(resp.status().as_u16());
+
+            let status = resp.status();
+            if status == StatusCode::TOO_MANY_REQUESTS && retries_left {
+                let retry_after = resp
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse().ok())
+                    .map(Duration::from_secs);
+                let wait = retry_after.unwrap_or_else(|| backoff(attempt));
+                info!("Rate limited by Jira; retrying {url} in {wait:?}");
+                sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+
+            if status.is_server_error() && retries_left {
+                let wait = backoff(attempt);
+                warn!("HTTP {status} from request to {url}. Retrying in {wait:?}");
+                sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+
+            return Ok(resp);
+        }
+    }
+}
+
+/// The exponential backoff for a retry `attempt` (starting at 0) with up to 1 second of jitter
+fn backoff(attempt: u32) -> Duration {
+    let secs = 2u64.saturating_pow(attempt).min(MAX_BACKOFF_SECS);
+    // The sub-second part of the current time is random enough to spread out retries
+    let jitter_millis = u64::from(Utc::now().timestamp_subsec_millis());
+    Duration::from_secs(secs) + Duration::from_millis(jitter_millis)
+}
@@ -0,0 +1,389 @@
+use super::client::JiraClient;
+use crate::{
+    config_value,
+    fetch_run::{record_fetch_run, record_rows_inserted},
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::{stream, StreamExt};
+use reqwest::Url;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+use tracing::{debug, error, info};
+use wallowa_duckdb::{duckdb::params, Pool};
+
+/// The `data_source` of the rows fetched from Jira
+pub const DATA_SOURCE: &str = "jira_rest_api";
+
+/// The fields of each issue to fetch. The changelog of each issue is fetched too.
+const FIELDS: &str = "summary,issuetype,status,created,updated,resolutiondate";
+
+/// A Jira project and the JQL used to search for its issues
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JiraProject {
+    /// The base URL of the Jira site, like `https://example.atlassian.net`
+    pub api_url: String,
+    /// The host (and port, if any) of the `api_url`
+    pub host: String,
+    /// The key of the project, like `PROJ`
+    pub key: String,
+    /// The JQL to search for the issues of the project, without an `ORDER BY` clause
+    pub jql: String,
+}
+
+impl fmt::Display for JiraProject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key)
+    }
+}
+
+/// The parts of a page of search results used for paging. Jira Data Center (and the older
+/// Jira Cloud search API) pages with `startAt` and `total`, while the newer Jira Cloud search
+/// API pages with `nextPageToken`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchPage {
+    #[serde(default)]
+    start_at: u64,
+    total: Option<u64>,
+    next_page_token: Option<String>,
+    issues: Vec<serde_json::Value>,
+}
+
+/// Fetch the issues (with their changelogs) of a specific project that have been updated
+/// since the last fetch.
+///
+/// Jira interprets the times in JQL in the time zone of the user, so issues updated in the
+/// day before the latest stored update are fetched again. The queries only use the latest
+/// version of each issue.
+pub async fn fetch_issues(pool: &Pool, project: &JiraProject) -> Result<()> {
+    let search_path: String = config_value("jira.search_path").await?;
+    let per_page: String = config_value("jira.per_page").await?;
+    let client = JiraClient::new().await?;
+
+    let jql = match select_watermark(pool, project)? {
+        Some(watermark) => format!(
+            r#"({jql}) AND updated >= "{updated}" ORDER BY updated ASC"#,
+            jql = project.jql,
+            updated = (watermark - Duration::days(1)).format("%Y-%m-%d %H:%M"),
+        ),
+        None => format!("({}) ORDER BY updated ASC", project.jql),
+    };
+    let search_url = format!("{}{}", project.api_url, search_path);
+    let base_params = [
+        ("jql", jql.as_str()),
+        ("fields", FIELDS),
+        ("expand", "changelog"),
+        ("maxResults", per_page.as_str()),
+    ];
+
+    let mut url_opt = Some(Url::parse_with_params(&search_url, base_params)?);
+    while let Some(request_url) = url_opt {
+        info!("Making request to {request_url}");
+        let resp = client.get(request_url.as_str()).await?;
+
+        let resp_status = resp.status();
+        let text = resp.text().await?;
+
+        debug!("Response status code from Jira issues: {resp_status:?}");
+        if resp_status.is_server_error() || resp_status.is_client_error() {
+            // Error - stop making requests and bubble up the error
+            return Err(anyhow!(
+                "HTTP {resp_status}: '{text}' from request to {request_url}"
+            ));
+        }
+
+        let page: SearchPage = serde_json::from_str(&text)
+            .with_context(|| format!("Unexpected response from request to {request_url}"))?;
+        if page.issues.is_empty() {
+            break;
+        }
+
+        // Only the issues are stored since the rest of the page is only used for paging
+        let conn = pool.get()?;
+        conn.execute(
+            r#"
+INSERT INTO wallowa_raw_data (
+    "data_source",
+    data_type,
+    metadata,
+    "data"
+) VALUES (
+    ?,
+    'issues',
+    to_json({host: ?, project: ?}),
+    ?
+)
+"#,
+            params![
+                DATA_SOURCE,
+                project.host,
+                project.key,
+                serde_json::to_string(&page.issues)?
+            ],
+        )?;
+        record_rows_inserted(page.issues.len() as u64);
+
+        url_opt = next_page_url(&search_url, &base_params, &page)?;
+    }
+
+    Ok(())
+}
+
+/// The URL of the page of search results after `page`, or `None` for the last page. Pages
+/// with a `nextPageToken` are followed by the token, and otherwise `startAt` is advanced until
+/// the `total` has been fetched.
+fn next_page_url(
+    search_url: &str,
+    base_params: &[(&str, &str)],
+    page: &SearchPage,
+) -> Result<Option<Url>> {
+    let fetched = page.start_at + page.issues.len() as u64;
+    let url = match (&page.next_page_token, page.total) {
+        (Some(token), _) => Some(Url::parse_with_params(
+            search_url,
+            base_params
+                .iter()
+                .copied()
+                .chain([("nextPageToken", token.as_str())]),
+        )?),
+        (None, Some(total)) if fetched < total => Some(Url::parse_with_params(
+            search_url,
+            base_params
+                .iter()
+                .copied()
+                .chain([("startAt", fetched.to_string().as_str())]),
+        )?),
+        _ => None,
+    };
+    Ok(url)
+}
+
+/// Select the latest `updated` time of the issues stored for a specific project
+fn select_watermark(pool: &Pool, project: &JiraProject) -> Result<Option<DateTime<Utc>>> {
+    let conn = pool.get()?;
+
+    let watermark = conn.query_row(
+        r#"
+WITH raw AS (
+    SELECT
+        unnest(json_transform("data", '[{"fields": {"updated": "TIMESTAMP"}}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = ?
+    AND data_type = 'issues'
+    AND (metadata->>'$.host') = ?
+    AND (metadata->>'$.project') = ?
+)
+SELECT max(row.fields.updated) AS updated
+FROM raw
+"#,
+        params![DATA_SOURCE, project.host, project.key],
+        |row| row.get::<_, Option<DateTime<Utc>>>(0),
+    )?;
+    Ok(watermark)
+}
+
+/// Fetch all of the configured Jira projects
+pub async fn fetch_all(pool: &Pool) -> Result<()> {
+    info!("Fetching from Jira");
+
+    let max_concurrency: usize = config_value("jira.max_concurrency").await?;
+    let projects = configured_projects().await?;
+
+    // Each fetch owns its project and a handle to the pool so that the futures don't borrow
+    // from `projects`, which keeps the resulting future `Send` for `tokio::spawn`
+    let project_fetches = projects.into_iter().map(|project| {
+        let pool = pool.clone();
+        async move { fetch_project(&pool, &project).await }
+    });
+    let results: Vec<Result<()>> = stream::iter(project_fetches)
+        .buffer_unordered(max_concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut first_error = None;
+    for result in results {
+        if let Err(e) = result {
+            error!("{e:#}");
+            first_error.get_or_insert(e);
+        }
+    }
+    info!("Fetching from Jira complete");
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Fetch the issues of a single project, recording the run in `wallowa_fetch_run`
+async fn fetch_project(pool: &Pool, project: &JiraProject) -> Result<()> {
+    let fetched = record_fetch_run(
+        pool,
+        DATA_SOURCE,
+        &project.to_string(),
+        fetch_issues(pool, project),
+    )
+    .await;
+
+    fetched.with_context(|| format!("Error fetching {project} from Jira"))
+}
+
+/// Check the Jira configuration, returning whether any projects are configured to be fetched.
+/// Returns an error for invalid project keys, an invalid `jira.api_url`, or when no token is
+/// configured.
+pub async fn validate_config() -> Result<bool> {
+    let project_keys: Vec<String> = config_value("jira.projects").await?;
+    if project_keys.is_empty() {
+        return Ok(false);
+    }
+
+    configured_projects().await?;
+    if config_value::<Option<String>>("jira.auth.token")
+        .await?
+        .is_none()
+    {
+        return Err(anyhow!("`jira.auth.token` is required to fetch from Jira"));
+    }
+
+    Ok(true)
+}
+
+/// The configured `jira.projects`, each searched with its JQL from `jira.jql` or with
+/// `project = {key}` by default
+async fn configured_projects() -> Result<Vec<JiraProject>> {
+    let api_url: Option<String> = config_value("jira.api_url").await?;
+    let project_keys: Vec<String> = config_value("jira.projects").await?;
+    let jql: HashMap<String, String> = config_value("jira.jql").await?;
+
+    parse_projects(api_url.as_deref(), project_keys, &jql)
+}
+
+/// Build a [`JiraProject`] on the Jira site at `api_url` for each of the `project_keys`,
+/// searched with its JQL from `jql` or with `project = {key}` by default
+fn parse_projects(
+    api_url: Option<&str>,
+    project_keys: Vec<String>,
+    jql: &HashMap<String, String>,
+) -> Result<Vec<JiraProject>> {
+    let Some(api_url) = api_url else {
+        return Err(anyhow!("`jira.api_url` is required to fetch from Jira"));
+    };
+    let api_url = api_url.trim_end_matches('/');
+    let parsed = Url::parse(api_url).with_context(|| format!("Invalid Jira API URL {api_url}"))?;
+    let host = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => anyhow::bail!("Invalid Jira API URL {api_url}"),
+    };
+
+    let mut projects = vec![];
+    for key in project_keys {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("Invalid project key {key:?} in `jira.projects`");
+        }
+        projects.push(JiraProject {
+            api_url: api_url.to_string(),
+            host: host.clone(),
+            jql: jql
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| format!("project = {key}")),
+            key,
+        });
+    }
+    Ok(projects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SEARCH_URL: &str = "https://example.atlassian.net/rest/api/3/search/jql";
+    const BASE_PARAMS: [(&str, &str); 1] = [("jql", "project = PROJ")];
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn page(start_at: u64, total: Option<u64>, next_page_token: Option<&str>) -> SearchPage {
+        SearchPage {
+            start_at,
+            total,
+            next_page_token: next_page_token.map(str::to_string),
+            issues: vec![json!({}), json!({})],
+        }
+    }
+
+    #[test]
+    fn parse_projects_uses_the_configured_jql() {
+        let jql = HashMap::from([(
+            "OPS".to_string(),
+            "project = OPS AND type = Bug".to_string(),
+        )]);
+        let projects = parse_projects(
+            Some("https://jira.example.com:8443/"),
+            keys(&["PROJ", "OPS"]),
+            &jql,
+        )
+        .unwrap();
+
+        assert_eq!(projects.len(), 2);
+        assert_eq!(projects[0].api_url, "https://jira.example.com:8443");
+        assert_eq!(projects[0].host, "jira.example.com:8443");
+        assert_eq!(projects[0].jql, "project = PROJ");
+        assert_eq!(projects[1].jql, "project = OPS AND type = Bug");
+    }
+
+    #[test]
+    fn parse_projects_rejects_invalid_keys() {
+        let api_url = Some("https://example.atlassian.net");
+        let jql = HashMap::new();
+        assert!(parse_projects(api_url, keys(&["PROJ_2"]), &jql).is_ok());
+        for key in ["", "PROJ OR project = OPS", "PROJ\"", "PROJ-1"] {
+            assert!(
+                parse_projects(api_url, keys(&[key]), &jql).is_err(),
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_projects_requires_a_valid_api_url() {
+        let jql = HashMap::new();
+        assert!(parse_projects(None, keys(&["PROJ"]), &jql).is_err());
+        assert!(parse_projects(Some("not a url"), keys(&["PROJ"]), &jql).is_err());
+    }
+
+    #[test]
+    fn next_page_url_follows_the_next_page_token() {
+        let url = next_page_url(SEARCH_URL, &BASE_PARAMS, &page(0, None, Some("abc")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(url.query(), Some("jql=project+%3D+PROJ&nextPageToken=abc"));
+        // The token takes precedence over `startAt` paging
+        let url = next_page_url(SEARCH_URL, &BASE_PARAMS, &page(0, Some(10), Some("abc")))
+            .unwrap()
+            .unwrap();
+        assert!(url.query().unwrap().ends_with("nextPageToken=abc"));
+    }
+
+    #[test]
+    fn next_page_url_advances_start_at_until_the_total() {
+        let url = next_page_url(SEARCH_URL, &BASE_PARAMS, &page(2, Some(5), None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(url.query(), Some("jql=project+%3D+PROJ&startAt=4"));
+        assert!(
+            next_page_url(SEARCH_URL, &BASE_PARAMS, &page(3, Some(5), None))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            next_page_url(SEARCH_URL, &BASE_PARAMS, &page(0, None, None))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
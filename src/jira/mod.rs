@@ -0,0 +1,63 @@
+/// All Jira-related functionality
+pub mod client;
+pub mod fetch;
+pub mod queries;
+pub mod web;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::{future::BoxFuture, FutureExt};
+use wallowa_duckdb::Pool;
+
+use crate::{
+    source::{NavEntry, Source},
+    web::AppState,
+};
+
+/// Issues and their changelogs from the Jira REST API
+pub struct JiraSource;
+
+impl Source for JiraSource {
+    fn name(&self) -> &'static str {
+        "jira"
+    }
+
+    fn label(&self) -> &'static str {
+        "Jira"
+    }
+
+    fn description(&self) -> &'static str {
+        r#"Collect issue activity data from
+<a href="https://www.atlassian.com/software/jira" target="_blank" class="link" referrerpolicy="same-origin">Jira</a>
+Cloud or Jira Data Center."#
+    }
+
+    fn data_source(&self) -> &'static str {
+        fetch::DATA_SOURCE
+    }
+
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>> {
+        fetch::validate_config().boxed()
+    }
+
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>> {
+        fetch::fetch_all(pool).boxed()
+    }
+
+    fn page_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::page_routes()
+    }
+
+    fn data_routes(&self) -> Router<Arc<AppState>, Body> {
+        web::data_routes()
+    }
+
+    fn nav_entries(&self) -> Vec<NavEntry> {
+        vec![NavEntry {
+            children: vec![NavEntry::new("/jira/issues", "Issues")],
+            ..NavEntry::new("/jira", "Jira")
+        }]
+    }
+}
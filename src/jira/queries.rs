@@ -0,0 +1,247 @@
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, FixedOffset};
+use tracing::{debug, error};
+use wallowa_duckdb::duckdb::{params_from_iter, ToSql};
+use wallowa_duckdb::Pool;
+
+use super::fetch::DATA_SOURCE;
+
+/// Get the list of distinct Jira project keys in the database
+pub fn select_distinct_repos(pool: &Pool) -> Result<Vec<String>> {
+    let conn = pool.get()?;
+
+    let mut stmt = conn.prepare(
+        r#"
+SELECT DISTINCT (metadata->>'$.project') AS repo
+FROM wallowa_raw_data
+WHERE "data_source" = ?
+AND data_type = 'issues'
+ORDER BY repo
+"#,
+    )?;
+    let rows = stmt.query_map([DATA_SOURCE], |row| row.get(0))?;
+    let mut repo_names = vec![];
+    for row in rows {
+        match row {
+            Ok(repo_name) => repo_names.push(repo_name),
+            Err(e) => error!("Error querying distinct Jira projects: {:?}", e),
+        }
+    }
+    Ok(repo_names)
+}
+
+/// The CTEs shared by the issue queries: `issues` (every stored issue), `repos` (the selected
+/// projects), `latest_deduped_issues` (the latest version of each issue of the selected
+/// projects), and `status_changes` (every change to the status of those issues)
+fn issues_ctes(repo_placeholders: &str) -> String {
+    format!(
+        r#"
+issues AS (
+    SELECT
+        (metadata->>'$.project') AS repo,
+        unnest(json_transform("data",
+            '[{{
+                "key": "VARCHAR",
+                "fields": {{
+                    "issuetype": {{"name": "VARCHAR"}},
+                    "status": {{"name": "VARCHAR"}},
+                    "created": "TIMESTAMP",
+                    "updated": "TIMESTAMP",
+                    "resolutiondate": "TIMESTAMP"
+                }},
+                "changelog": {{
+                    "histories": [{{
+                        "created": "TIMESTAMP",
+                        "items": [{{"field": "VARCHAR", "toString": "VARCHAR"}}]
+                    }}]
+                }}
+            }}]')) AS row,
+    FROM wallowa_raw_data
+    WHERE "data_source" = '{DATA_SOURCE}'
+    AND data_type = 'issues'
+),
+repos AS (
+    {repo_placeholders}
+),
+latest_deduped_issues AS (
+    SELECT
+        repo,
+        row.key AS "key",
+        row.fields.issuetype.name AS issue_type,
+        row.fields.status.name AS status,
+        row.fields.created AS created_at,
+        row.fields.resolutiondate AS resolved_at,
+        row.fields.updated AS updated_at,
+        row.changelog.histories AS histories,
+    FROM issues
+    WHERE repo IN (SELECT repo FROM repos)
+    QUALIFY row_number() OVER (PARTITION BY "key" ORDER BY updated_at DESC) = 1
+),
+histories AS (
+    SELECT repo, "key", issue_type, unnest(histories) AS history
+    FROM latest_deduped_issues
+),
+history_items AS (
+    SELECT repo, "key", issue_type, history.created AS changed_at, unnest(history.items) AS item
+    FROM histories
+),
+status_changes AS (
+    SELECT repo, "key", issue_type, changed_at, item.toString AS to_status
+    FROM history_items
+    WHERE item.field = 'status'
+)"#,
+        DATA_SOURCE = DATA_SOURCE,
+        repo_placeholders = repo_placeholders
+    )
+}
+
+/// Query the count of issues resolved by week and issue type
+pub fn throughput(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `throughput`");
+
+    query_issues(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        &[],
+        r#"
+SELECT
+    CAST(date_trunc('week', resolved_at) AS DATE) AS week,
+    issue_type,
+    COUNT(*) AS resolved
+FROM latest_deduped_issues
+WHERE resolved_at >= ?
+AND resolved_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the median lead time (the days between creating and resolving an issue) of the
+/// issues resolved by week and issue type
+pub fn lead_time(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `lead_time`");
+
+    query_issues(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        &[],
+        r#"
+SELECT
+    CAST(date_trunc('week', resolved_at) AS DATE) AS week,
+    issue_type,
+    median(EPOCH(AGE(resolved_at, created_at)) / 86400) AS lead_time
+FROM latest_deduped_issues
+WHERE resolved_at >= ?
+AND resolved_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Query the median cycle time (the days between an issue first moving to the `start_status`
+/// and last moving to the `end_status`) by the week of the move to the `end_status` and
+/// issue type. Statuses are matched case-insensitively.
+pub fn cycle_time(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    start_status: &str,
+    end_status: &str,
+) -> Result<Vec<RecordBatch>> {
+    debug!("Running `cycle_time` from {start_status} to {end_status}");
+
+    query_issues(
+        pool,
+        start_date,
+        end_date,
+        repos,
+        &[start_status, end_status],
+        r#"
+, cycles AS (
+    SELECT
+        "key",
+        issue_type,
+        min(changed_at) FILTER (WHERE lower(to_status) = lower(?)) AS started_at,
+        max(changed_at) FILTER (WHERE lower(to_status) = lower(?)) AS ended_at
+    FROM status_changes
+    GROUP BY 1,2
+)
+SELECT
+    CAST(date_trunc('week', ended_at) AS DATE) AS week,
+    issue_type,
+    median(EPOCH(AGE(ended_at, started_at)) / 86400) AS cycle_time
+FROM cycles
+WHERE ended_at > started_at
+AND ended_at >= ?
+AND ended_at <= ?
+GROUP BY 1,2
+ORDER BY 1,2
+"#,
+    )
+}
+
+/// Run the `select` statement (optionally beginning with more common table expressions)
+/// after the issue common table expressions for the repos and date range. The `extra_params`
+/// are bound after the repos and before the date range.
+fn query_issues(
+    pool: &Pool,
+    start_date: DateTime<FixedOffset>,
+    end_date: DateTime<FixedOffset>,
+    repos: &Vec<String>,
+    extra_params: &[&str],
+    select: &str,
+) -> Result<Vec<RecordBatch>> {
+    let conn = pool.get()?;
+
+    let repo_placeholders = if repos.is_empty() {
+        "SELECT DISTINCT repo FROM issues".to_string()
+    } else {
+        let mut placeholders = "?,".repeat(repos.len());
+        placeholders.pop(); // Remove the trailing comma (`,`)
+        format!("SELECT unnest([{}]) AS repo", placeholders)
+    };
+    debug!("repo_placeholders: {:?} for {:?}", repo_placeholders, repos);
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH {ctes}\n{select}",
+        ctes = issues_ctes(&repo_placeholders),
+        select = select
+    ))?;
+
+    let mut params = Vec::new();
+    let start_date_naive = start_date.naive_utc();
+    let end_date_naive = end_date.naive_utc();
+    for repo in repos {
+        params.push(repo.to_sql()?);
+    }
+    for param in extra_params {
+        params.push(param.to_sql()?);
+    }
+    params.push(start_date_naive.to_sql()?);
+    params.push(end_date_naive.to_sql()?);
+
+    let rows = stmt.query_arrow(params_from_iter(params))?;
+    let mut batches = Vec::new();
+    for row in rows {
+        batches.push(row);
+    }
+    Ok(batches)
+}
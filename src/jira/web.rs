@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use axum::{body::Body, extract::State, response::Html, routing::get, Router};
+use axum_extra::extract::Query;
+use minijinja::context;
+
+use crate::{
+    config_value,
+    web::{date_range, render, to_arrow_ipc, AppState, ChartParams},
+    AppResult,
+};
+
+use super::queries::{cycle_time, lead_time, select_distinct_repos, throughput};
+
+/// All page-related routes for Jira
+pub fn page_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/issues", get(jira_issues))
+        .route("/", get(jira_dashboard))
+}
+
+/// All data-related routes for Jira
+pub fn data_routes() -> Router<Arc<AppState>, Body> {
+    Router::new()
+        .route("/throughput.arrow", get(throughput_arrow))
+        .route("/lead_time.arrow", get(lead_time_arrow))
+        .route("/cycle_time.arrow", get(cycle_time_arrow))
+}
+
+async fn throughput_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = throughput(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn lead_time_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+
+    let results = lead_time(&state.read_pool, start_date, end_date, &params.repo)?;
+
+    to_arrow_ipc(results)
+}
+
+async fn cycle_time_arrow(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartParams>,
+) -> AppResult<Vec<u8>> {
+    let (start_date, end_date) = date_range(&params);
+    let start_status: String = config_value("jira.cycle_time.start_status").await?;
+    let end_status: String = config_value("jira.cycle_time.end_status").await?;
+
+    let results = cycle_time(
+        &state.read_pool,
+        start_date,
+        end_date,
+        &params.repo,
+        &start_status,
+        &end_status,
+    )?;
+
+    to_arrow_ipc(results)
+}
+
+async fn jira_issues(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let distinct_repos = select_distinct_repos(&state.read_pool)?;
+    let start_status: String = config_value("jira.cycle_time.start_status").await?;
+    let end_status: String = config_value("jira.cycle_time.end_status").await?;
+    let html = render(
+        state,
+        "jira/issues.html",
+        context! {
+            current_nav => "/jira/issues",
+            repos => distinct_repos,
+            start_status => start_status,
+            end_status => end_status,
+        },
+    )?;
+    Ok(Html(html))
+}
+
+async fn jira_dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
+    let html = render(
+        state,
+        "jira/index.html",
+        context! {
+            current_nav => "/jira",
+        },
+    )?;
+    Ok(Html(html))
+}
@@ -1,8 +1,9 @@
-use std::path::Path;
-use std::{sync::OnceLock, time::Duration};
+use std::path::{Path, PathBuf};
+use std::{collections::HashMap, sync::OnceLock, time::Duration};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use config::Config;
+use source::sources;
 use tokio::fs::{try_exists, DirBuilder, OpenOptions};
 use tokio::io::AsyncWriteExt;
 use tokio::{
@@ -11,21 +12,79 @@ use tokio::{
     time,
 };
 use tracing::error;
-use tracing::{debug, info, metadata::LevelFilter};
+use tracing::{debug, info, metadata::LevelFilter, warn};
 use tracing_subscriber::{
     fmt, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, EnvFilter,
 };
 use wallowa_duckdb::Pool;
 
 pub mod cli;
+pub mod compact;
+pub mod fetch_run;
+pub mod git;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
+pub mod incidents;
+pub mod ingest;
+pub mod jira;
+pub mod query;
+pub mod source;
 pub mod web;
 
-/// Fetch all of the configured data sources in the background one time
+/// Fetch all of the configured data sources in the background one time.
+///
+/// Sources that aren't configured are skipped. Every source is fetched even if another fails;
+/// the first error is returned and any later errors are logged. The fetched GitHub and Gitea
+/// data is then loaded into the normalized tables that the queries read from.
 pub async fn fetch_all(pool: &Pool) -> AppResult<()> {
     info!("Fetching in background");
-    github::fetch::fetch_all(pool).await?;
+    let mut first_error = None;
+    for source in sources() {
+        let fetched = async {
+            if source.validate_config().await? {
+                source.fetch(pool).await
+            } else {
+                debug!("Skipping fetch from {}; not configured", source.label());
+                Ok(())
+            }
+        }
+        .await
+        .with_context(|| format!("Error fetching from {}", source.label()));
+
+        if let Err(e) = fetched {
+            if first_error.is_some() {
+                error!("{e:#}");
+            } else {
+                first_error = Some(e);
+            }
+        }
+    }
     info!("Fetching in background complete");
+
+    // Load whatever was fetched, even if some of the sources failed
+    if let Err(e) = github::load::load_raw_data(pool).context("Error loading the fetched data") {
+        if first_error.is_some() {
+            error!("{e:#}");
+        } else {
+            first_error = Some(e);
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Backfill the `window` for all of the configured data sources, or only for `repo` when given
+pub async fn backfill(
+    pool: &Pool,
+    window: github::fetch::Backfill,
+    repo: Option<&str>,
+) -> AppResult<()> {
+    github::fetch::backfill(pool, window, repo).await?;
+    github::load::load_raw_data(pool)?;
     Ok(())
 }
 
@@ -48,7 +107,7 @@ pub async fn fetch_all_periodically(pool: &Pool) -> AppResult<JoinHandle<()>> {
                 interval.tick().await;
                 match fetch_all(&pool).await {
                     Ok(_) => (),
-                    Err(e) => debug!("Error with periodic fetch all: {:?}", e),
+                    Err(e) => error!("Error with periodic fetch all: {:#}", e.0),
                 }
             }
         });
@@ -59,6 +118,45 @@ pub async fn fetch_all_periodically(pool: &Pool) -> AppResult<JoinHandle<()>> {
     }
 }
 
+/// Compact the raw data in the background on the interval configured with
+/// `compact.interval` (default: 1 day) if `compact.enabled` is true (default: false).
+/// The original rows are archived to `compact.archive_dir` when it's configured.
+pub async fn compact_periodically(pool: &Pool) -> AppResult<JoinHandle<()>> {
+    let compact_enabled: bool = config_value("compact.enabled").await?;
+    if compact_enabled {
+        let compact_interval: u64 = config_value("compact.interval").await?;
+        let archive_dir: Option<PathBuf> = config_value("compact.archive_dir").await?;
+        debug!(
+            "Background compact task started with interval {} seconds",
+            compact_interval
+        );
+        // Skip the first tick so that compacting doesn't hold up the first fetch
+        let period = Duration::from_secs(compact_interval);
+        let mut interval = time::interval_at(time::Instant::now() + period, period);
+        let pool = pool.clone();
+
+        let forever = task::spawn(async move {
+            loop {
+                interval.tick().await;
+                // Compacting is slow and synchronous so it's kept off of the async workers
+                let (pool, archive_dir) = (pool.clone(), archive_dir.clone());
+                let compacted =
+                    task::spawn_blocking(move || compact::compact(&pool, archive_dir.as_deref()))
+                        .await;
+                match compacted {
+                    Ok(Ok(_)) => (),
+                    Ok(Err(e)) => error!("Error with periodic compact: {:#}", e),
+                    Err(e) => error!("Periodic compact task failed: {}", e),
+                }
+            }
+        });
+        Ok(forever)
+    } else {
+        debug!("Background compact task disabled");
+        Ok(task::spawn(async {})) // Intentional no-op
+    }
+}
+
 /// Global static reference to a RwLock'd configuration initialized in `main`
 pub static CONFIG: OnceLock<RwLock<Config>> = OnceLock::new();
 
@@ -74,18 +172,99 @@ pub async fn config_value<'de, T: serde::de::Deserialize<'de>>(key: &str) -> Res
     Ok(val)
 }
 
+/// The settings that could be set with environment variables before sections were separated
+/// by `__`, when `WALLOWA_GITHUB_AUTH_TOKEN` set `github.auth.token`. Those names are still
+/// read (with a warning) so that existing `.env` files keep working.
+const LEGACY_ENV_KEYS: [&str; 12] = [
+    "github.auth.token",
+    "github.per_page",
+    "github.repos",
+    "server.host",
+    "server.port",
+    "server.response.compression.br",
+    "server.response.compression.gzip",
+    "server.response.compression.zstd",
+    "server.response.compression.deflate",
+    "server.response.compression.level",
+    "fetch.enabled",
+    "fetch.interval",
+];
+
+/// The environment variable for the config `key`, with sections separated by `separator`
+fn env_var_name(key: &str, separator: &str) -> String {
+    format!("WALLOWA_{}", key.to_uppercase().replace('.', separator))
+}
+
 /// Initialize the configuration system
 pub fn init_config(config_path: &str) -> Result<()> {
+    // Sections of keys are separated by `__` since keys like `github.max_concurrency` contain
+    // `_` themselves, so `WALLOWA_GITHUB__MAX_CONCURRENCY` sets `github.max_concurrency`
     let env_source = config::Environment::with_prefix("WALLOWA")
         .try_parsing(true)
-        .separator("_")
+        .prefix_separator("_")
+        .separator("__")
         .list_separator(",")
-        .with_list_parse_key("github.repos");
-
-    let config = config::Config::builder()
+        .with_list_parse_key("github.repos")
+        .with_list_parse_key("github.orgs")
+        .with_list_parse_key("gitlab.repos")
+        .with_list_parse_key("gitea.repos")
+        .with_list_parse_key("git.repos")
+        .with_list_parse_key("jira.projects")
+        .with_list_parse_key("pagerduty.service_ids");
+
+    let mut builder = config::Config::builder()
         .set_default("database", "wallowa.db")?
+        .set_default("pool.size", "1")?
+        .set_default("pool.read.size", "4")?
+        .set_default("github.api_url", "https://api.github.com")?
+        .set_default("github.auth.app_id", None::<String>)?
+        .set_default("github.auth.installation_id", None::<String>)?
+        .set_default("github.auth.private_key_path", None::<String>)?
         .set_default("github.per_page", "100")?
+        .set_default("github.deployment_environment", "production")?
+        .set_default("github.max_concurrency", "2")?
+        .set_default("github.max_retries", "5")?
         .set_default::<&str, Vec<String>>("github.repos", vec![])?
+        .set_default::<&str, Vec<String>>("github.orgs", vec![])?
+        .set_default::<&str, Vec<String>>("github.include_repos", vec![])?
+        .set_default::<&str, Vec<String>>("github.exclude_repos", vec![])?
+        .set_default("github.exclude_archived", true)?
+        .set_default("github.exclude_forks", true)?
+        .set_default("github.webhook.secret", None::<String>)?
+        .set_default("gitlab.api_url", "https://gitlab.com/api/v4")?
+        .set_default("gitlab.per_page", "100")?
+        .set_default("gitlab.max_concurrency", "2")?
+        .set_default("gitlab.max_retries", "5")?
+        .set_default::<&str, Vec<String>>("gitlab.repos", vec![])?
+        .set_default("gitea.api_url", None::<String>)?
+        .set_default("gitea.auth.token", None::<String>)?
+        .set_default("gitea.per_page", "50")?
+        .set_default("gitea.max_concurrency", "2")?
+        .set_default("gitea.max_retries", "5")?
+        .set_default::<&str, Vec<String>>("gitea.repos", vec![])?
+        .set_default::<&str, Vec<String>>("git.repos", vec![])?
+        .set_default("jira.api_url", None::<String>)?
+        .set_default("jira.auth.email", None::<String>)?
+        .set_default("jira.auth.token", None::<String>)?
+        .set_default("jira.search_path", "/rest/api/2/search")?
+        .set_default("jira.per_page", "50")?
+        .set_default("jira.max_concurrency", "2")?
+        .set_default("jira.max_retries", "5")?
+        .set_default("jira.cycle_time.start_status", "In Progress")?
+        .set_default("jira.cycle_time.end_status", "Done")?
+        .set_default::<&str, Vec<String>>("jira.projects", vec![])?
+        .set_default("jira.jql", HashMap::<String, String>::new())?
+        .set_default("pagerduty.api_url", "https://api.pagerduty.com")?
+        .set_default("pagerduty.auth.token", None::<String>)?
+        .set_default("pagerduty.per_page", "100")?
+        .set_default("pagerduty.max_retries", "5")?
+        .set_default("pagerduty.lookback_days", "90")?
+        .set_default::<&str, Vec<String>>("pagerduty.service_ids", vec![])?
+        .set_default("ingest.auth.token", None::<String>)?
+        .set_default("query.auth.token", None::<String>)?
+        .set_default("query.max_rows", "10000")?
+        .set_default("query.max_running", "1")?
+        .set_default("query.timeout", "30")?
         .set_default("server.host", "0.0.0.0")?
         .set_default("server.port", "9843")?
         .set_default("server.response.compression.br", false)?
@@ -95,9 +274,29 @@ pub fn init_config(config_path: &str) -> Result<()> {
         .set_default("server.response.compression.level", "fastest")?
         .set_default("fetch.enabled", "true")?
         .set_default("fetch.interval", "3600")?
+        .set_default("compact.enabled", "false")?
+        .set_default("compact.interval", "86400")?
+        .set_default("compact.archive_dir", None::<String>)?
         .add_source(config::File::with_name(config_path))
-        .add_source(env_source)
-        .build()?;
+        .add_source(env_source);
+
+    // Read the legacy name of a setting unless it's also set with its current name
+    for key in LEGACY_ENV_KEYS {
+        let (legacy_name, name) = (env_var_name(key, "_"), env_var_name(key, "__"));
+        if std::env::var_os(&name).is_some() {
+            continue;
+        }
+        if let Ok(value) = std::env::var(&legacy_name) {
+            warn!("`{legacy_name}` is deprecated and will stop being read in a future version; rename it to `{name}`");
+            builder = if key == "github.repos" {
+                let repos: Vec<String> = value.split(',').map(str::to_string).collect();
+                builder.set_override(key, repos)?
+            } else {
+                builder.set_override(key, value)?
+            };
+        }
+    }
+    let config = builder.build()?;
 
     let _ = CONFIG.set(RwLock::new(config));
 
@@ -249,7 +448,19 @@ const NEW_DOT_ENV: &str = r#"# See https://www.unre.in/wallowa/docs/configuratio
 
 # Put your authentication keys in this file to avoid committing
 # them to source control.
-WALLOWA_GITHUB_AUTH_TOKEN='YOUR_TOKEN'
+WALLOWA_GITHUB__AUTH__TOKEN='YOUR_TOKEN'
+# The secret of a GitHub webhook pointed at `/github/webhook`
+#WALLOWA_GITHUB__WEBHOOK__SECRET='YOUR_WEBHOOK_SECRET'
+#WALLOWA_GITLAB__AUTH__TOKEN='YOUR_TOKEN'
+#WALLOWA_GITEA__AUTH__TOKEN='YOUR_TOKEN'
+#WALLOWA_JIRA__AUTH__TOKEN='YOUR_TOKEN'
+#WALLOWA_PAGERDUTY__AUTH__TOKEN='YOUR_TOKEN'
+
+# Set a long, random token to accept events pushed to `POST /ingest/{source}/{type}`
+#WALLOWA_INGEST__AUTH__TOKEN='A_LONG_RANDOM_TOKEN'
+
+# Set a long, random token to enable the `/query` page and the `/data/query` endpoint
+#WALLOWA_QUERY__AUTH__TOKEN='A_LONG_RANDOM_TOKEN'
 "#;
 
 const NEW_CONFIG: &str = r#"# See https://www.unre.in/wallowa/docs/configuration for documentation
@@ -266,12 +477,123 @@ const NEW_CONFIG: &str = r#"# See https://www.unre.in/wallowa/docs/configuration
 # Default: [] (empty list)
 [github]
 repos = ["open-telemetry/opentelemetry-rust"]
+# Track every repo of these GitHub orgs. The repos of each org are looked up at the
+# start of each fetch so new repos are tracked automatically. Default: []
+#orgs = ["open-telemetry"]
+# Glob patterns (matched against `owner/repo`) that the repos of `orgs` must match to
+# be tracked, and patterns for repos to skip. Default: [] (include all, exclude none)
+#include_repos = ["open-telemetry/opentelemetry-*"]
+#exclude_repos = ["open-telemetry/*-contrib"]
+# Whether to skip archived and forked repos of `orgs`. Default: true
+#exclude_archived = true
+#exclude_forks = true
+# The base URL of the GitHub REST API. Set this to fetch from a GitHub Enterprise
+# Server instance, like "https://github.example.com/api/v3". A single repo can use
+# a different API by prepending the API URL to it in `repos`, like
+# "https://github.example.com/api/v3/owner/repo". Default: https://api.github.com
+#api_url = "https://api.github.com"
+# Authenticate as a GitHub App instead of with the `WALLOWA_GITHUB__AUTH__TOKEN`
+# token by uncommenting these settings and filling in the app details.
+#auth.app_id = "YOUR_APP_ID"
+#auth.installation_id = "YOUR_INSTALLATION_ID"
+#auth.private_key_path = "wallowa.private-key.pem"
 # The number of items to fetch per page (maximum of 100). Default: 100
 #per_page = "100"
+# The maximum number of repos to fetch at the same time. Default: 2
+#max_concurrency = 2
+# The number of times to retry a GitHub API request that was rate limited or
+# failed with a server or network error. Default: 5
+#max_retries = 5
+# The GitHub Deployments environment used for the DORA metrics. Default: production
+#deployment_environment = "production"
 
 # The database file to use. Default: wallowa.db
 #database = "wallowa.db"
 
+# Add any GitLab projects (of the form "group/project", with any subgroups) that
+# you'd like to track inside the `repos = []` brackets and add your GitLab access
+# token to `.env`. Default: [] (empty list)
+[gitlab]
+#repos = ["gitlab-org/gitlab-runner"]
+# The base URL of the GitLab REST API. Set this to fetch from a self-managed GitLab
+# instance, like "https://gitlab.example.com/api/v4". Default: https://gitlab.com/api/v4
+#api_url = "https://gitlab.com/api/v4"
+# The number of items to fetch per page (maximum of 100). Default: 100
+#per_page = "100"
+# The maximum number of projects to fetch at the same time. Default: 2
+#max_concurrency = 2
+# The number of times to retry a GitLab API request that was rate limited or
+# failed with a server or network error. Default: 5
+#max_retries = 5
+
+# Add any Gitea or Forgejo repos (of the form "owner/repo") that you'd like to track
+# inside the `repos = []` brackets and set `api_url` to the REST API of your
+# instance. Add an access token to `.env` to fetch private repos. Default: [] (empty list)
+[gitea]
+#repos = ["forgejo/forgejo"]
+# The base URL of the Gitea REST API, like "https://codeberg.org/api/v1". Required
+# when `repos` is set. A single repo can use a different instance by prepending the
+# API URL to it in `repos`, like "https://git.example.com/api/v1/owner/repo".
+#api_url = "https://codeberg.org/api/v1"
+# The number of items to fetch per page (maximum of 50 by default). Default: 50
+#per_page = "50"
+# The maximum number of repos to fetch at the same time. Default: 2
+#max_concurrency = 2
+# The number of times to retry a Gitea API request that was rate limited or
+# failed with a server or network error. Default: 5
+#max_retries = 5
+
+# Add the paths of any local git clones that you'd like to read commits from inside
+# the `repos = []` brackets. The `git` CLI must be installed. Default: [] (empty list)
+[git]
+#repos = ["/src/service-a"]
+
+# Add the keys of any Jira projects that you'd like to track inside the
+# `projects = []` brackets, set `api_url` to your Jira site, and add your Jira
+# token to `.env`. Default: [] (empty list)
+[jira]
+#projects = ["PROJ"]
+#api_url = "https://example.atlassian.net"
+# The email address of the account that the token belongs to. Set this for Jira
+# Cloud API tokens and leave it unset for Jira Data Center personal access tokens.
+#auth.email = "you@example.com"
+# The path of the issue search API. Jira Cloud sites can use "/rest/api/3/search/jql".
+# Default: /rest/api/2/search
+#search_path = "/rest/api/2/search"
+# The number of issues to fetch per page. Default: 50
+#per_page = "50"
+# The maximum number of projects to fetch at the same time. Default: 2
+#max_concurrency = 2
+# The number of times to retry a Jira API request that was rate limited or
+# failed with a server or network error. Default: 5
+#max_retries = 5
+# The statuses that start and end the cycle time of an issue.
+# Default: "In Progress" and "Done"
+#cycle_time.start_status = "In Progress"
+#cycle_time.end_status = "Done"
+
+# The JQL used to search for the issues of each project (without an ORDER BY
+# clause). Default: project = {key}
+#[jira.jql]
+#PROJ = "project = PROJ AND issuetype != Epic"
+
+# Add your PagerDuty REST API token to `.env` to fetch incidents from PagerDuty.
+# Incidents can also be imported from JSON and CSV files with
+# `wallowa import-incidents FILE`.
+[pagerduty]
+# The base URL of the PagerDuty REST API. Accounts in the EU service region use
+# "https://api.eu.pagerduty.com". Default: https://api.pagerduty.com
+#api_url = "https://api.pagerduty.com"
+# Only fetch the incidents of these service IDs. Default: [] (all services)
+#service_ids = ["PABC123"]
+# The number of days of incidents to fetch the first time. Default: 90
+#lookback_days = 90
+# The number of items to fetch per page (maximum of 100). Default: 100
+#per_page = "100"
+# The number of times to retry a PagerDuty API request that was rate limited or
+# failed with a server or network error. Default: 5
+#max_retries = 5
+
 [fetch]
 # The time interval to wait between fetching for additional data, in seconds.
 # Default: 3600 seconds (1 hour)
@@ -281,6 +603,42 @@ repos = ["open-telemetry/opentelemetry-rust"]
 # Default: true (enabled)
 #enabled = true
 
+[compact]
+# Whether to compact the raw data in the background by removing the older versions
+# of items that were fetched again. Use the `wallowa db compact` CLI command to
+# compact whenever you'd like instead.
+# Default: false (disabled)
+#enabled = false
+# The time interval to wait between compactions, in seconds.
+# Default: 86400 seconds (1 day)
+#interval = 86400
+# The directory to archive the original versions of compacted rows to as Parquet
+# files. The original rows are not archived when this isn't set.
+#archive_dir = "archive"
+
+[pool]
+# The maximum number of database connections for fetches and other writes.
+# Default: 1
+#size = 1
+
+[pool.read]
+# The maximum number of database connections for the queries behind the charts,
+# separate from the connections for writes so that the charts stay responsive
+# while fetching. Default: 4
+#size = 4
+
+[query]
+# The maximum number of rows returned by a query from the `/query` page or the
+# `/data/query` endpoint. Rows past the limit are left out. Default: 10000
+#max_rows = 10000
+# The maximum number of queries from the `/query` page or the `/data/query`
+# endpoint that run at once, each using a connection from the read pool.
+# Keep it below `pool.read.size` so the charts stay responsive. Default: 1
+#max_running = 1
+# The time to wait for a query from the `/query` page or the `/data/query`
+# endpoint before giving up, in seconds. Default: 30
+#timeout = 30
+
 [server]
 # The network address to bind to. Default: 0.0.0.0
 #host = "0.0.0.0"
@@ -1,16 +1,24 @@
+use chrono::{Duration, NaiveTime};
 use clap::Parser;
 use dotenvy::dotenv;
 use tokio::join;
-use tracing::error;
-use wallowa::cli::{Cli, Commands};
+use tracing::{error, info};
+use wallowa::cli::{Cli, Commands, DbCommands};
+use wallowa::compact::compact;
+use wallowa::github::fetch::Backfill;
+use wallowa::github::load::{load_raw_data, rebuild};
+use wallowa::incidents::import::import_incidents;
 use wallowa::web::serve;
 use wallowa::{
-    config_value, create_project, fetch_all, fetch_all_periodically, init_config, init_logging,
-    AppResult,
+    backfill, compact_periodically, config_value, create_project, fetch_all,
+    fetch_all_periodically, init_config, init_logging, AppResult,
+};
+use wallowa_duckdb::{
+    migration_status, open_db_pool, open_db_pool_without_migrations, open_read_pool,
+    pending_migrations, run_migrations,
 };
-use wallowa_duckdb::open_db_pool;
 
-#[tokio::main(flavor = "current_thread")]
+#[tokio::main]
 async fn main() -> AppResult<()> {
     dotenv().ok();
 
@@ -19,7 +27,64 @@ async fn main() -> AppResult<()> {
     init_logging(&cli.log_format)?;
 
     match cli.command {
-        Some(Commands::Fetch {}) => {
+        Some(Commands::Db { command }) => {
+            if let Some(cmd_line_cfg_file) = cli.config {
+                init_config(cmd_line_cfg_file.as_str())?;
+            } else {
+                init_config("wallowa.config")?;
+            }
+
+            let database_string: String = config_value("database").await?;
+            let pool_size: u32 = config_value("pool.size").await?;
+
+            match command {
+                DbCommands::Compact { archive_dir } => {
+                    let pool = open_db_pool(database_string.as_str(), pool_size)?;
+
+                    let archive_dir = match archive_dir {
+                        Some(archive_dir) => Some(archive_dir),
+                        None => config_value("compact.archive_dir").await?,
+                    };
+                    let stats = compact(&pool, archive_dir.as_deref())?;
+                    if let Some(archive) = stats.archive {
+                        info!("Archived the original rows to {}", archive.display());
+                    }
+                }
+                DbCommands::Migrate { dry_run } => {
+                    let pool =
+                        open_db_pool_without_migrations(database_string.as_str(), pool_size)?;
+                    let mut conn = pool.get()?;
+
+                    if dry_run {
+                        let pending = pending_migrations(&conn)?;
+                        if pending.is_empty() {
+                            println!("No migrations to run");
+                        }
+                        for migration in pending {
+                            println!("-- Migration `{}`{}", migration.name, migration.sql);
+                        }
+                    } else {
+                        let migrated = run_migrations(&mut conn)?;
+                        println!("Ran {} migrations", migrated.len());
+                    }
+                }
+                DbCommands::Status {} => {
+                    let pool =
+                        open_db_pool_without_migrations(database_string.as_str(), pool_size)?;
+                    let conn = pool.get()?;
+
+                    println!("{:<40} {:<8} APPLIED AT", "MIGRATION", "STATE");
+                    for status in migration_status(&conn)? {
+                        let applied_at = status
+                            .applied_at
+                            .map(|applied_at| applied_at.to_string())
+                            .unwrap_or_default();
+                        println!("{:<40} {:<8} {}", status.name, status.state, applied_at);
+                    }
+                }
+            }
+        }
+        Some(Commands::Fetch { since, until, repo }) => {
             // Fetches from all sources
             if let Some(cmd_line_cfg_file) = cli.config {
                 init_config(cmd_line_cfg_file.as_str())?;
@@ -29,16 +94,58 @@ async fn main() -> AppResult<()> {
 
             // Each source is expected to run *only* if it is configured
             let database_string: String = config_value("database").await?;
-            let pool = open_db_pool(database_string.as_str(), 1)?;
+            let pool_size: u32 = config_value("pool.size").await?;
+            let pool = open_db_pool(database_string.as_str(), pool_size)?;
 
-            if let Err(error) = fetch_all(&pool).await {
+            let fetched = match since {
+                Some(since) => {
+                    // `until` is inclusive so the window ends at the start of the next day
+                    let window = Backfill {
+                        since: since.and_time(NaiveTime::MIN).and_utc(),
+                        until: until.map(|until| {
+                            (until + Duration::days(1))
+                                .and_time(NaiveTime::MIN)
+                                .and_utc()
+                        }),
+                    };
+                    backfill(&pool, window, repo.as_deref()).await
+                }
+                None => fetch_all(&pool).await,
+            };
+            if let Err(error) = fetched {
                 let e: anyhow::Error = error.0;
                 error!("{e:#}")
             };
         }
+        Some(Commands::ImportIncidents { path }) => {
+            if let Some(cmd_line_cfg_file) = cli.config {
+                init_config(cmd_line_cfg_file.as_str())?;
+            } else {
+                init_config("wallowa.config")?;
+            }
+
+            let database_string: String = config_value("database").await?;
+            let pool_size: u32 = config_value("pool.size").await?;
+            let pool = open_db_pool(database_string.as_str(), pool_size)?;
+
+            import_incidents(&pool, &path).await?;
+        }
         Some(Commands::New { path }) => {
             create_project(&path).await?;
         }
+        Some(Commands::Rebuild {}) => {
+            if let Some(cmd_line_cfg_file) = cli.config {
+                init_config(cmd_line_cfg_file.as_str())?;
+            } else {
+                init_config("wallowa.config")?;
+            }
+
+            let database_string: String = config_value("database").await?;
+            let pool_size: u32 = config_value("pool.size").await?;
+            let pool = open_db_pool(database_string.as_str(), pool_size)?;
+
+            rebuild(&pool)?;
+        }
         Some(Commands::Serve {}) | None => {
             if let Some(cmd_line_cfg_file) = cli.config {
                 init_config(cmd_line_cfg_file.as_str())?;
@@ -47,16 +154,25 @@ async fn main() -> AppResult<()> {
             }
 
             let database_string: String = config_value("database").await?;
-            let pool = open_db_pool(database_string.as_str(), 1)?;
+            let pool_size: u32 = config_value("pool.size").await?;
+            let pool = open_db_pool(database_string.as_str(), pool_size)?;
+
+            // Load any raw data that wasn't loaded yet, such as data stored by an older version
+            load_raw_data(&pool)?;
 
             let fetcher = fetch_all_periodically(&pool);
+            let compactor = compact_periodically(&pool);
 
             let host: String = config_value("server.host").await?;
             let port: String = config_value("server.port").await?;
-            let server = serve(&host, &port, pool.clone());
+            let read_pool_size: u32 = config_value("pool.read.size").await?;
+            let read_pool = open_read_pool(&pool, read_pool_size)?;
+            let server = serve(&host, &port, pool.clone(), read_pool);
 
-            let (fetcher_result, server_result) = join!(fetcher, server);
+            let (fetcher_result, compactor_result, server_result) =
+                join!(fetcher, compactor, server);
             fetcher_result?;
+            compactor_result?;
             server_result?;
         }
     }
@@ -0,0 +1,641 @@
+//! Run ad-hoc, read-only SQL against the database from the `/query` page and the
+//! `POST /data/query` endpoint, and store saved queries in `wallowa_saved_query`
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Form,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use minijinja::context;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::{task, time};
+use tracing::debug;
+use wallowa_duckdb::{
+    duckdb::{
+        arrow::{
+            array::Array,
+            datatypes::DataType,
+            record_batch::RecordBatch,
+            util::display::{ArrayFormatter, FormatOptions},
+        },
+        params,
+    },
+    Pool,
+};
+
+use crate::{
+    config_value,
+    ingest::constant_time_eq,
+    web::{render, to_arrow_ipc, AppState},
+    AppResult,
+};
+
+/// The formats that query results can be returned in
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryFormat {
+    /// An HTML table for the `/query` page
+    #[default]
+    Table,
+    /// The Arrow IPC file format
+    Arrow,
+    Csv,
+    /// An array with an object for each row
+    Json,
+}
+
+/// The form fields of `POST /data/query`
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    pub sql: String,
+    #[serde(default)]
+    pub format: QueryFormat,
+}
+
+/// The results of a query, limited to the configured `query.max_rows`
+pub struct QueryResults {
+    pub batches: Vec<RecordBatch>,
+    /// Whether rows past the limit were left out
+    pub truncated: bool,
+}
+
+/// Run the read-only `sql` and return the results in the requested format.
+///
+/// Only a single `SELECT` statement (including `WITH`, `FROM`-first, and `VALUES` queries) is
+/// allowed, and it can't read files or URLs. It runs on the read pool in a transaction that
+/// is rolled back, returns at most `query.max_rows` rows, and is abandoned after
+/// `query.timeout` seconds. At most `query.max_running` queries run at once, including the
+/// ones that timed out but haven't finished yet.
+pub async fn query_data(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(request): Form<QueryRequest>,
+) -> AppResult<Response> {
+    if let Some(response) = check_authorization(&headers).await? {
+        return Ok(response);
+    }
+    let max_rows: usize = config_value("query.max_rows").await?;
+    let timeout: u64 = config_value("query.timeout").await?;
+
+    let Ok(permit) = state.query_permits.clone().try_acquire_owned() else {
+        return query_error(
+            state,
+            request.format,
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many queries are running. Try again once they finish.".to_string(),
+        );
+    };
+    let pool = state.read_pool.clone();
+    let sql = request.sql;
+    // The query runs on a blocking thread so that a timeout can be enforced. DuckDB can't
+    // interrupt it from here, so a query that times out still runs to completion in the
+    // background and its results are discarded. It holds its permit until then so that
+    // abandoned queries can't use up the read pool.
+    let query = task::spawn_blocking(move || {
+        let results = run_query(&pool, &sql, max_rows);
+        drop(permit);
+        results
+    });
+    let results = match time::timeout(Duration::from_secs(timeout), query).await {
+        Ok(joined) => joined?,
+        Err(_) => {
+            return query_error(
+                state,
+                request.format,
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("The query timed out after {timeout}s"),
+            )
+        }
+    };
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            return query_error(
+                state,
+                request.format,
+                StatusCode::BAD_REQUEST,
+                format!("{e:#}"),
+            )
+        }
+    };
+
+    match request.format {
+        QueryFormat::Table => {
+            let columns: Vec<String> = match results.batches.first() {
+                Some(batch) => batch
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|field| field.name().to_string())
+                    .collect(),
+                None => vec![],
+            };
+            let rows = to_rows(&results.batches)?;
+            Ok(Html(render(
+                state,
+                "query/results.html",
+                context! {
+                    columns,
+                    row_count => rows.len(),
+                    rows,
+                    truncated => results.truncated,
+                    max_rows,
+                },
+            )?)
+            .into_response())
+        }
+        QueryFormat::Arrow => Ok((
+            [
+                (header::CONTENT_TYPE, "application/vnd.apache.arrow.file"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"query.arrow\"",
+                ),
+            ],
+            to_arrow_ipc(results.batches)?,
+        )
+            .into_response()),
+        QueryFormat::Csv => Ok((
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"query.csv\"",
+                ),
+            ],
+            to_csv(&results.batches)?,
+        )
+            .into_response()),
+        QueryFormat::Json => Ok((
+            [
+                (header::CONTENT_TYPE, "application/json"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"query.json\"",
+                ),
+            ],
+            serde_json::to_string(&to_json(&results.batches)?)?,
+        )
+            .into_response()),
+    }
+}
+
+/// Respond with the `message` of a query that failed. The table format is rendered as an
+/// alert for the `/query` page, which only swaps in successful responses.
+fn query_error(
+    state: Arc<AppState>,
+    format: QueryFormat,
+    status: StatusCode,
+    message: String,
+) -> AppResult<Response> {
+    debug!("Query failed: {message}");
+    Ok(match format {
+        QueryFormat::Table => Html(render(
+            state,
+            "query/results.html",
+            context! { error => message },
+        )?)
+        .into_response(),
+        _ => (status, message).into_response(),
+    })
+}
+
+/// Run the read-only `sql` on a connection from `pool`, returning at most `max_rows` rows
+pub fn run_query(pool: &Pool, sql: &str, max_rows: usize) -> Result<QueryResults> {
+    let conn = pool.get()?;
+
+    // DuckDB parses the SQL and only serializes `SELECT` statements, which makes it an
+    // allowlist of statement types. The statement is then rebuilt from the parsed SQL so
+    // that it can be wrapped with a limit regardless of trailing semicolons or comments.
+    let serialized: String = conn.query_row(
+        "SELECT json_serialize_sql(CAST(? AS VARCHAR))",
+        [sql],
+        |row| row.get(0),
+    )?;
+    let parsed: Value = serde_json::from_str(&serialized)?;
+    if parsed["error"].as_bool().unwrap_or(true) {
+        let message = parsed["error_message"].as_str().unwrap_or("Invalid SQL");
+        if message.starts_with("Only SELECT statements") {
+            bail!("Only SELECT statements are allowed");
+        }
+        bail!("{message}");
+    }
+    match parsed["statements"].as_array().map(Vec::len) {
+        Some(1) => (),
+        Some(0) | None => bail!("The query is empty"),
+        Some(_) => bail!("Only a single statement is allowed"),
+    }
+    check_sources(&parsed["statements"])?;
+    let statement: String = conn.query_row(
+        "SELECT json_deserialize_sql(CAST(? AS JSON))",
+        [&serialized],
+        |row| row.get(0),
+    )?;
+
+    // Anything the query changes anyway, such as sequences incremented by `nextval`, is rolled
+    // back when the connection is returned to the read pool
+    let mut stmt = conn.prepare(&format!("SELECT * FROM ({statement}) LIMIT ?"))?;
+    let results: Vec<RecordBatch> = stmt.query_arrow(params![max_rows + 1])?.collect();
+
+    let mut batches = vec![];
+    let mut rows = 0;
+    let mut truncated = false;
+    for batch in results {
+        if rows + batch.num_rows() > max_rows {
+            batches.push(batch.slice(0, max_rows - rows));
+            truncated = true;
+            break;
+        }
+        rows += batch.num_rows();
+        batches.push(batch);
+    }
+    Ok(QueryResults { batches, truncated })
+}
+
+/// The table functions that queries can use, including the catalog functions that describe
+/// the tables. The others can read files or URLs (such as `read_csv_auto`, `read_parquet`, and
+/// `glob`) or reveal secrets, settings, and paths on the server (such as `duckdb_secrets`,
+/// `duckdb_settings`, and `duckdb_extensions`).
+const ALLOWED_TABLE_FUNCTIONS: [&str; 8] = [
+    "range",
+    "generate_series",
+    "unnest",
+    "duckdb_tables",
+    "duckdb_columns",
+    "duckdb_views",
+    "duckdb_types",
+    "duckdb_functions",
+];
+
+/// The scalar functions that queries can't use since they reveal the configuration of the
+/// database (which can hold credentials, such as `s3_secret_access_key`) or the environment
+/// of the server
+const DENIED_FUNCTIONS: [&str; 2] = ["current_setting", "getenv"];
+
+/// Check every table that the serialized statement reads from, rejecting table functions
+/// other than the [`ALLOWED_TABLE_FUNCTIONS`] and table names that DuckDB would read as files
+/// or URLs, such as `FROM 'data.csv'`. Calls to any of the [`DENIED_FUNCTIONS`] are rejected
+/// too.
+fn check_sources(node: &Value) -> Result<()> {
+    match node {
+        Value::Object(object) => {
+            match object.get("type").and_then(Value::as_str) {
+                Some("FUNCTION") => {
+                    let name = object["function_name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_lowercase();
+                    if DENIED_FUNCTIONS.contains(&name.as_str()) {
+                        bail!("The `{name}` function isn't allowed");
+                    }
+                }
+                Some("TABLE_FUNCTION") => {
+                    let name = object["function"]["function_name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_lowercase();
+                    if !ALLOWED_TABLE_FUNCTIONS.contains(&name.as_str()) {
+                        bail!("The `{name}` table function isn't allowed");
+                    }
+                }
+                Some("BASE_TABLE") => {
+                    let name = object["table_name"].as_str().unwrap_or_default();
+                    if name.contains(['.', '/', '\\', ':']) {
+                        bail!("Reading from files or URLs isn't allowed");
+                    }
+                }
+                _ => (),
+            }
+            object.values().try_for_each(check_sources)
+        }
+        Value::Array(array) => array.iter().try_for_each(check_sources),
+        _ => Ok(()),
+    }
+}
+
+/// Format the value at `row` of each column of each batch as a string, or `None` for nulls
+fn to_rows(batches: &[RecordBatch]) -> Result<Vec<Vec<Option<String>>>> {
+    let options = FormatOptions::default();
+    let mut rows = vec![];
+    for batch in batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|column| ArrayFormatter::try_new(column.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            rows.push(
+                batch
+                    .columns()
+                    .iter()
+                    .zip(&formatters)
+                    .map(|(column, formatter)| {
+                        (!column.is_null(row)).then(|| formatter.value(row).to_string())
+                    })
+                    .collect(),
+            );
+        }
+    }
+    Ok(rows)
+}
+
+/// Serialize the batches as CSV with a header row. Nulls are empty fields.
+fn to_csv(batches: &[RecordBatch]) -> Result<String> {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut csv = String::new();
+    if let Some(batch) = batches.first() {
+        let header: Vec<String> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| field(f.name()))
+            .collect();
+        csv.push_str(&header.join(","));
+        csv.push_str("\r\n");
+    }
+    for row in to_rows(batches)? {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|value| value.as_deref().map(field).unwrap_or_default())
+            .collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    Ok(csv)
+}
+
+/// Serialize the batches as an array with an object for each row. Numbers and booleans are
+/// kept as JSON numbers and booleans while other values are formatted as strings.
+fn to_json(batches: &[RecordBatch]) -> Result<Vec<Map<String, Value>>> {
+    let mut objects = vec![];
+    for batch in batches {
+        let schema = batch.schema();
+        let rows = to_rows(std::slice::from_ref(batch))?;
+        for row in rows {
+            let object = schema
+                .fields()
+                .iter()
+                .zip(row)
+                .map(|(field, value)| {
+                    let value = match value {
+                        None => Value::Null,
+                        Some(value) if is_json_primitive(field.data_type()) => {
+                            serde_json::from_str(&value).unwrap_or(Value::String(value))
+                        }
+                        Some(value) => Value::String(value),
+                    };
+                    (field.name().to_string(), value)
+                })
+                .collect();
+            objects.push(object);
+        }
+    }
+    Ok(objects)
+}
+
+/// Whether values of the `data_type` are formatted as valid JSON numbers or booleans
+fn is_json_primitive(data_type: &DataType) -> bool {
+    data_type.is_numeric() || *data_type == DataType::Boolean
+}
+
+/// A query saved in `wallowa_saved_query`
+#[derive(Debug, Serialize)]
+pub struct SavedQuery {
+    pub name: String,
+    pub sql: String,
+}
+
+/// The query parameters of the `/query` page
+#[derive(Debug, Deserialize)]
+pub struct QueryPageParams {
+    /// The name of the saved query to open
+    pub name: Option<String>,
+}
+
+/// The `/query` page, with the saved query named in the parameters opened if given
+pub async fn query_page(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<QueryPageParams>,
+) -> AppResult<Response> {
+    if let Some(response) = check_authorization(&headers).await? {
+        return Ok(response);
+    }
+    let saved_queries = select_saved_queries(&state.read_pool)?;
+    let current = params
+        .name
+        .and_then(|name| saved_queries.iter().find(|query| query.name == name));
+    let (name, sql) = match current {
+        Some(query) => (query.name.as_str(), query.sql.as_str()),
+        None => ("", "SELECT data_source, data_type, count(*) AS row_count\nFROM wallowa_raw_data\nGROUP BY ALL\nORDER BY ALL"),
+    };
+
+    Ok(Html(render(
+        state.clone(),
+        "query/index.html",
+        context! {
+            current_nav => "/query",
+            name,
+            sql,
+            saved_queries,
+        },
+    )?)
+    .into_response())
+}
+
+/// The form fields for saving a query
+#[derive(Debug, Deserialize)]
+pub struct SaveQueryRequest {
+    pub name: String,
+    pub sql: String,
+}
+
+/// Save the query, replacing any saved query with the same name, and render the updated
+/// list of saved queries
+pub async fn save_query(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(request): Form<SaveQueryRequest>,
+) -> AppResult<Response> {
+    if let Some(response) = check_authorization(&headers).await? {
+        return Ok(response);
+    }
+    if let Some(response) = check_same_origin(&headers) {
+        return Ok(response);
+    }
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Saved queries need a name").into());
+    }
+    // The write waits for the write connection, which a fetch can hold for a while, so it runs
+    // off of the async workers
+    let (pool, name) = (state.pool.clone(), name.to_string());
+    task::spawn_blocking(move || -> Result<usize> {
+        Ok(pool.get()?.execute(
+            r#"
+INSERT OR REPLACE INTO wallowa_saved_query ("name", "sql", updated_at)
+VALUES (?, ?, now())
+"#,
+            [&name, &request.sql],
+        )?)
+    })
+    .await??;
+    render_saved_queries(state)
+}
+
+/// The query parameters for deleting a saved query
+#[derive(Debug, Deserialize)]
+pub struct DeleteSavedQueryParams {
+    pub name: String,
+}
+
+/// Delete the saved query with the `name` and render the updated list of saved queries
+pub async fn delete_saved_query(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<DeleteSavedQueryParams>,
+) -> AppResult<Response> {
+    if let Some(response) = check_authorization(&headers).await? {
+        return Ok(response);
+    }
+    if let Some(response) = check_same_origin(&headers) {
+        return Ok(response);
+    }
+    let pool = state.pool.clone();
+    task::spawn_blocking(move || -> Result<usize> {
+        Ok(pool.get()?.execute(
+            r#"DELETE FROM wallowa_saved_query WHERE "name" = ?"#,
+            [&params.name],
+        )?)
+    })
+    .await??;
+    render_saved_queries(state)
+}
+
+fn render_saved_queries(state: Arc<AppState>) -> AppResult<Response> {
+    let saved_queries = select_saved_queries(&state.read_pool)?;
+    Ok(Html(render(
+        state,
+        "query/saved_queries.html",
+        context! { saved_queries },
+    )?)
+    .into_response())
+}
+
+/// Check that the request is authorized to use the query page and API, returning the response
+/// to send instead when it isn't.
+///
+/// Requests must have an `Authorization: Bearer {query.auth.token}` header. Browsers can send
+/// the token as the password of HTTP Basic auth instead, which they prompt for, since a page
+/// can't be opened with a bearer token. The query page and API are disabled until
+/// `query.auth.token` is configured.
+async fn check_authorization(headers: &HeaderMap) -> AppResult<Option<Response>> {
+    let Some(token) = config_value::<Option<String>>("query.auth.token").await? else {
+        return Ok(Some(
+            (
+                StatusCode::NOT_FOUND,
+                "Queries are disabled. Configure `query.auth.token` to enable them.",
+            )
+                .into_response(),
+        ));
+    };
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let provided = if let Some(bearer) = authorization.strip_prefix("Bearer ") {
+        Some(bearer.to_string())
+    } else if let Some(basic) = authorization.strip_prefix("Basic ") {
+        STANDARD
+            .decode(basic)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|credentials| {
+                let (_, password) = credentials.split_once(':')?;
+                Some(password.to_string())
+            })
+    } else {
+        None
+    };
+    if provided.is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes())) {
+        return Ok(None);
+    }
+    Ok(Some(
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="wallowa query""#)],
+            "Invalid or missing token",
+        )
+            .into_response(),
+    ))
+}
+
+/// Check that a request to change the saved queries came from a page of this server,
+/// returning the response to send instead when it didn't.
+///
+/// Browsers send the HTTP Basic auth credentials of the query page along with requests that
+/// other sites make to it, so a request authorized with Basic auth is rejected when its
+/// `Sec-Fetch-Site` or `Origin` header shows that it came from another site. Requests with a
+/// bearer token can't be forged that way and are always allowed.
+fn check_same_origin(headers: &HeaderMap) -> Option<Response> {
+    let header_value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if header_value(header::AUTHORIZATION.as_str())
+        .is_some_and(|value| value.starts_with("Bearer "))
+    {
+        return None;
+    }
+
+    let same_origin = if let Some(site) = header_value("sec-fetch-site") {
+        matches!(site, "same-origin" | "none")
+    } else if let Some(origin) = header_value(header::ORIGIN.as_str()) {
+        let origin_host = Url::parse(origin).ok().and_then(|url| {
+            let host = url.host_str()?.to_string();
+            Some(match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host,
+            })
+        });
+        origin_host.is_some() && origin_host.as_deref() == header_value(header::HOST.as_str())
+    } else {
+        true
+    };
+    if same_origin {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::FORBIDDEN,
+            "Saved queries can only be changed from the query page",
+        )
+            .into_response(),
+    )
+}
+
+/// Select all of the saved queries ordered by name
+pub fn select_saved_queries(pool: &Pool) -> Result<Vec<SavedQuery>> {
+    let conn = pool.get()?;
+    let mut stmt =
+        conn.prepare(r#"SELECT "name", "sql" FROM wallowa_saved_query ORDER BY "name""#)?;
+    let saved_queries = stmt
+        .query_map([], |row| {
+            Ok(SavedQuery {
+                name: row.get(0)?,
+                sql: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(saved_queries)
+}
@@ -0,0 +1,107 @@
+//! The data sources that Wallowa fetches from, and the registry of them
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{body::Body, Router};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use wallowa_duckdb::Pool;
+
+use crate::{
+    git::GitSource, gitea::GiteaSource, github::GitHubSource, gitlab::GitLabSource,
+    incidents::IncidentSource, jira::JiraSource, web::AppState,
+};
+
+/// Every source, in the order they are fetched and listed in the web UI.
+///
+/// Add a new source here to have it fetched, routed, and shown in the nav and on the sources
+/// page.
+static SOURCES: &[&dyn Source] = &[
+    &GitHubSource,
+    &GitLabSource,
+    &GiteaSource,
+    &GitSource,
+    &JiraSource,
+    &IncidentSource,
+];
+
+/// A data source that is fetched into `wallowa_raw_data` and has its own pages and data
+/// routes in the web UI
+pub trait Source: Send + Sync {
+    /// The unique name of the source, used as the prefix of its routes (e.g. `/github` and
+    /// `/data/github`)
+    fn name(&self) -> &'static str;
+
+    /// The human-readable name of the source
+    fn label(&self) -> &'static str;
+
+    /// A short HTML description of the source for the sources page
+    fn description(&self) -> &'static str;
+
+    /// The path of the logo of the source, if it has one
+    fn logo(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// The `data_source` of the rows the source adds to `wallowa_raw_data` and
+    /// `wallowa_fetch_run`
+    fn data_source(&self) -> &'static str;
+
+    /// Check the configuration of the source, returning whether the source is configured to
+    /// be fetched. Returns an error when the source is configured incorrectly.
+    fn validate_config(&self) -> BoxFuture<'_, Result<bool>>;
+
+    /// Fetch the latest data from the source
+    fn fetch<'a>(&'a self, pool: &'a Pool) -> BoxFuture<'a, Result<()>>;
+
+    /// The page routes of the source, nested under `/{name}`
+    fn page_routes(&self) -> Router<Arc<AppState>, Body>;
+
+    /// The data routes of the source, nested under `/data/{name}`
+    fn data_routes(&self) -> Router<Arc<AppState>, Body>;
+
+    /// The entries of the source in the nav menu
+    fn nav_entries(&self) -> Vec<NavEntry>;
+}
+
+/// An entry in the nav menu
+#[derive(Debug, Clone, Serialize)]
+pub struct NavEntry {
+    pub href: String,
+    pub label: String,
+    pub logo: Option<String>,
+    pub children: Vec<NavEntry>,
+}
+
+impl NavEntry {
+    /// An entry without a logo or children
+    pub fn new(href: &str, label: &str) -> Self {
+        Self {
+            href: href.to_string(),
+            label: label.to_string(),
+            logo: None,
+            children: vec![],
+        }
+    }
+}
+
+/// All of the registered sources
+pub fn sources() -> &'static [&'static dyn Source] {
+    SOURCES
+}
+
+/// Find the registered source with the given `name`
+pub fn find_source(name: &str) -> Option<&'static dyn Source> {
+    sources()
+        .iter()
+        .find(|source| source.name() == name)
+        .copied()
+}
+
+/// The nav entries of all of the registered sources
+pub fn nav_entries() -> Vec<NavEntry> {
+    sources()
+        .iter()
+        .flat_map(|source| source.nav_entries())
+        .collect()
+}
@@ -1,45 +1,122 @@
+use anyhow::anyhow;
+use arrow::record_batch::RecordBatch;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::{StatusCode, Uri},
     response::{Html, IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
-use minijinja::{context, path_loader, Environment};
+use chrono::{DateTime, Datelike, Days, FixedOffset, TimeZone, Utc};
+use minijinja::{context, path_loader, value::Value, Environment};
 use minijinja_autoreload::AutoReloader;
 use reqwest::header;
 use rust_embed::RustEmbed;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::signal;
+use serde::{Deserialize, Serialize};
+use std::{io::BufWriter, net::SocketAddr, sync::Arc};
+use tokio::{signal, sync::Semaphore, task};
 use tower_http::trace::TraceLayer;
 use tower_http::{compression::CompressionLayer, CompressionLevel};
-use tracing::{debug, info};
-use wallowa_duckdb::Pool;
+use tracing::{debug, error, info};
+use wallowa_duckdb::{
+    duckdb::arrow::{datatypes::Schema, ipc::writer::FileWriter},
+    Pool,
+};
 
 use crate::{
     config_value,
-    github::{
-        fetch::latest_fetch_all,
-        web::{data_routes, page_routes},
-    },
+    fetch_run::{latest_fetch_run, select_repo_fetch_statuses, RepoFetchStatus},
+    github::load::load_raw_data,
+    ingest::ingest,
+    query::{delete_saved_query, query_data, query_page, save_query},
+    source::{self, find_source, nav_entries},
     AppError, AppResult,
 };
 
+/// The status of a source for the sources page
+#[derive(Debug, Serialize)]
+struct SourceStatus {
+    name: &'static str,
+    label: &'static str,
+    description: &'static str,
+    logo: Option<&'static str>,
+    last_fetched: String,
+    repo_statuses: Vec<RepoFetchStatus>,
+}
+
 pub async fn sources(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
-    let github_last_fetched = latest_fetch_all(&state.pool)?
-        .format("%Y-%m-%dT%H:%M:%SZ")
-        .to_string();
+    let mut source_statuses = vec![];
+    for source in source::sources() {
+        source_statuses.push(SourceStatus {
+            name: source.name(),
+            label: source.label(),
+            description: source.description(),
+            logo: source.logo(),
+            last_fetched: last_fetched(&state.read_pool, source.data_source())?,
+            repo_statuses: select_repo_fetch_statuses(&state.read_pool, source.data_source())?,
+        });
+    }
 
     Ok(Html(render(
         state,
         "sources/index.html",
         context! {
             current_nav => "/sources",
-            github_last_fetched,
+            sources => source_statuses,
         },
     )?))
 }
 
+/// Fetch the source with the given `name` and render the time it was last fetched, or the
+/// error if the fetch failed
+pub async fn fetch_source(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> AppResult<Response> {
+    let Some(source) = find_source(&name) else {
+        return Ok((StatusCode::NOT_FOUND, format!("Unknown source `{name}`")).into_response());
+    };
+
+    let result = async {
+        if !source.validate_config().await? {
+            return Err(anyhow!("{} is not configured", source.label()));
+        }
+        source.fetch(&state.pool).await?;
+        // Load what was fetched so that the charts, which read the normalized tables, are up
+        // to date once the fetch is reported
+        let pool = state.pool.clone();
+        task::spawn_blocking(move || load_raw_data(&pool)).await??;
+        last_fetched(&state.read_pool, source.data_source())
+    }
+    .await;
+
+    let message = match result {
+        Ok(last_fetched) => last_fetched,
+        Err(err) => {
+            let msg = format!("{err:#}");
+            error!(msg);
+            msg
+        }
+    };
+    Ok(Html(render(
+        state,
+        "sources/fetch_source.html",
+        context! {
+            message,
+        },
+    )?)
+    .into_response())
+}
+
+/// The time the most recent successful fetch from the `data_source` finished, formatted for
+/// display
+fn last_fetched(pool: &Pool, data_source: &str) -> anyhow::Result<String> {
+    Ok(match latest_fetch_run(pool, data_source)? {
+        Some(finished_at) => finished_at.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        None => "Never".to_string(),
+    })
+}
+
 pub async fn dashboard(State(state): State<Arc<AppState>>) -> AppResult<Html<String>> {
     Ok(Html(render(
         state,
@@ -56,6 +133,61 @@ pub async fn bookmark(State(state): State<Arc<AppState>>) -> AppResult<Html<Stri
     )?))
 }
 
+/// The query parameters of the data routes for charts
+#[derive(Deserialize, Debug)]
+pub struct ChartParams {
+    pub start_date: Option<DateTime<FixedOffset>>,
+    pub end_date: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub repo: Vec<String>,
+}
+
+/// The start and end dates from the query parameters, defaulting to the 30 days
+/// before the beginning of today
+pub fn date_range(params: &ChartParams) -> (DateTime<FixedOffset>, DateTime<FixedOffset>) {
+    // TODO better error handling for invalid or missing parameters
+    let end_date = if let Some(end) = params.end_date {
+        end
+    } else {
+        let now = chrono::offset::Utc::now();
+        let beginning_of_today = Utc
+            .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
+            .unwrap();
+        beginning_of_today.fixed_offset()
+    };
+    let start_date = if let Some(start) = params.start_date {
+        start
+    } else {
+        end_date.checked_sub_days(Days::new(30)).unwrap()
+    };
+    (start_date, end_date)
+}
+
+/// Serialize query results into the Arrow IPC file format
+pub fn to_arrow_ipc(results: Vec<RecordBatch>) -> AppResult<Vec<u8>> {
+    let mut ipc_data: Vec<u8> = Vec::new();
+    if !results.is_empty() {
+        // Use the schema from the first RecordBatch as the IPC schema
+        let schema = results[0].schema();
+        let metadata = schema.metadata.clone();
+        let fields: Vec<Arc<wallowa_duckdb::duckdb::arrow::datatypes::Field>> = schema
+            .all_fields()
+            .iter()
+            .map(|field| Arc::new((*field).clone()))
+            .collect();
+        let ipc_schema = Schema::new_with_metadata(fields, metadata);
+
+        let buf = BufWriter::new(&mut ipc_data);
+        let mut writer = FileWriter::try_new(buf, &ipc_schema)?;
+        for batch in results {
+            writer.write(&batch)?;
+        }
+        writer.finish()?;
+    }
+
+    Ok(ipc_data)
+}
+
 pub async fn static_file(uri: Uri) -> impl IntoResponse {
     let mut path = uri.path().trim_start_matches('/').to_string();
     if path.starts_with("static/") {
@@ -64,12 +196,15 @@ pub async fn static_file(uri: Uri) -> impl IntoResponse {
     StaticFile(path)
 }
 
-pub async fn serve(host: &str, port: &str, pool: Pool) -> AppResult<()> {
+/// Serve the web app on `host` and `port`. Fetches and other writes use `pool` while the
+/// queries behind the charts and pages use `read_pool` (see [`wallowa_duckdb::open_read_pool`]).
+pub async fn serve(host: &str, port: &str, pool: Pool, read_pool: Pool) -> AppResult<()> {
     let (env, reloader) = if cfg!(debug_assertions) {
         (
             None,
             Some(AutoReloader::new(|notifier| {
                 let mut env = Environment::new();
+                add_globals(&mut env);
                 let template_path = "templates";
                 env.set_loader(path_loader(&template_path));
                 notifier.set_fast_reload(true);
@@ -79,6 +214,7 @@ pub async fn serve(host: &str, port: &str, pool: Pool) -> AppResult<()> {
         )
     } else {
         let mut env: Environment<'static> = Environment::new();
+        add_globals(&mut env);
         for template_name in TemplateSrc::iter() {
             if let Some(template) = TemplateSrc::get(&template_name) {
                 env.add_template_owned(
@@ -90,10 +226,13 @@ pub async fn serve(host: &str, port: &str, pool: Pool) -> AppResult<()> {
         (Some(env), None)
     };
 
+    let max_running_queries: usize = config_value("query.max_running").await?;
     let state = Arc::new(AppState {
         template_loader: reloader,
         template_env: env,
         pool,
+        read_pool,
+        query_permits: Arc::new(Semaphore::new(max_running_queries)),
     });
 
     let compression_level_cfg: String = config_value("server.response.compression.level").await?;
@@ -110,10 +249,21 @@ pub async fn serve(host: &str, port: &str, pool: Pool) -> AppResult<()> {
         .deflate(config_value("server.response.compression.deflate").await?)
         .quality(compression_level);
 
-    let app = Router::new()
-        .nest("/github", page_routes())
-        .nest("/data", Router::new().nest("/github", data_routes()))
+    let mut app = Router::new();
+    let mut data_routes = Router::new();
+    for source in source::sources() {
+        app = app.nest(&format!("/{}", source.name()), source.page_routes());
+        data_routes = data_routes.nest(&format!("/{}", source.name()), source.data_routes());
+    }
+
+    let data_routes = data_routes.route("/query", post(query_data));
+    let app = app
+        .nest("/data", data_routes)
+        .route("/query", get(query_page))
+        .route("/query/saved", post(save_query).delete(delete_saved_query))
         .route("/sources", get(sources))
+        .route("/sources/:name/fetch", post(fetch_source))
+        .route("/ingest/:source/:data_type", post(ingest))
         .route("/bookmark", get(bookmark))
         .route("/", get(dashboard))
         .route("/static/*file", get(static_file))
@@ -134,6 +284,11 @@ pub async fn serve(host: &str, port: &str, pool: Pool) -> AppResult<()> {
     Ok(())
 }
 
+/// Add the values that every template uses to the template `env`
+fn add_globals(env: &mut Environment) {
+    env.add_global("nav_entries", Value::from_serialize(nav_entries()));
+}
+
 pub fn render(
     state: Arc<AppState>,
     template: &str,
@@ -192,7 +347,13 @@ async fn shutdown_signal() {
 pub struct AppState {
     template_loader: Option<AutoReloader>,
     template_env: Option<Environment<'static>>,
+    /// The pool for fetches and other writes
     pub pool: Pool,
+    /// The pool for queries that only read from the database
+    pub read_pool: Pool,
+    /// The permits for running ad-hoc queries from the query page, which limit how many
+    /// connections of `read_pool` they can use at once
+    pub query_permits: Arc<Semaphore>,
 }
 
 /// Tell axum how to convert `AppError` into a response.
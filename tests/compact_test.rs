@@ -0,0 +1,82 @@
+use wallowa::compact::compact;
+use wallowa_duckdb::{open_db_pool, Pool};
+
+fn insert_raw_data(pool: &Pool, data_type: &str, data: &str) {
+    pool.get()
+        .unwrap()
+        .execute(
+            r#"
+INSERT INTO wallowa_raw_data ("data_source", data_type, metadata, "data")
+VALUES ('github_rest_api', ?, to_json({owner: 'o', repo: 'r', etag: ''}), ?)
+"#,
+            [data_type, data],
+        )
+        .unwrap();
+}
+
+fn raw_data(pool: &Pool) -> Vec<String> {
+    let conn = pool.get().unwrap();
+    let mut stmt = conn
+        .prepare(r#"SELECT "data" FROM wallowa_raw_data ORDER BY id"#)
+        .unwrap();
+    let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+    rows.map(|row| row.unwrap()).collect()
+}
+
+#[test]
+fn compact_keeps_newest_items() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    insert_raw_data(
+        &pool,
+        "releases",
+        r#"[{"id":1,"name":"old"},{"id":2,"name":"two"}]"#,
+    );
+    insert_raw_data(&pool, "releases", r#"[{"id":1,"name":"new"}]"#);
+
+    let stats = compact(&pool, None).unwrap();
+
+    assert_eq!(stats.rows_deleted, 0);
+    assert_eq!(stats.rows_rewritten, 1);
+    assert_eq!(
+        raw_data(&pool),
+        vec![
+            r#"[{"id":2,"name":"two"}]"#.to_string(),
+            r#"[{"id":1,"name":"new"}]"#.to_string(),
+        ]
+    );
+}
+
+#[test]
+fn compact_keeps_items_without_a_key() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    insert_raw_data(&pool, "releases", r#"[{"name":"one"}]"#);
+    insert_raw_data(&pool, "releases", r#"[{"name":"two"}]"#);
+
+    let stats = compact(&pool, None).unwrap();
+
+    assert_eq!(stats.rows_deleted, 0);
+    assert_eq!(stats.rows_rewritten, 0);
+    assert_eq!(raw_data(&pool).len(), 2);
+}
+
+#[test]
+fn compact_keeps_the_same_id_from_each_host() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    insert_raw_data(&pool, "releases", r#"[{"id":1,"name":"github.com"}]"#);
+    pool.get()
+        .unwrap()
+        .execute(
+            r#"
+INSERT INTO wallowa_raw_data ("data_source", data_type, metadata, "data")
+VALUES ('github_rest_api', 'releases', to_json({host: 'ghe.example.com', owner: 'o', repo: 'r', etag: ''}), '[{"id":1,"name":"ghe"}]')
+"#,
+            [],
+        )
+        .unwrap();
+
+    let stats = compact(&pool, None).unwrap();
+
+    assert_eq!(stats.rows_deleted, 0);
+    assert_eq!(stats.rows_rewritten, 0);
+    assert_eq!(raw_data(&pool).len(), 2);
+}
@@ -0,0 +1,25 @@
+use wallowa::{config_value, init_config};
+
+#[tokio::test]
+async fn legacy_environment_variables_are_still_read() {
+    let config_path = std::env::temp_dir().join("wallowa_config_legacy_env_test.config.toml");
+    std::fs::write(&config_path, "[fetch]\ninterval = 60\n").unwrap();
+    std::env::set_var("WALLOWA_GITHUB_AUTH_TOKEN", "a legacy token");
+    std::env::set_var("WALLOWA_GITHUB_REPOS", "octocat/hello,octocat/world");
+    std::env::set_var("WALLOWA_FETCH_INTERVAL", "120");
+    // The current name wins over the legacy name
+    std::env::set_var("WALLOWA_SERVER_PORT", "1234");
+    std::env::set_var("WALLOWA_SERVER__PORT", "5678");
+    init_config(config_path.to_str().unwrap()).unwrap();
+
+    assert_eq!(
+        config_value::<String>("github.auth.token").await.unwrap(),
+        "a legacy token"
+    );
+    assert_eq!(
+        config_value::<Vec<String>>("github.repos").await.unwrap(),
+        vec!["octocat/hello", "octocat/world"]
+    );
+    assert_eq!(config_value::<u64>("fetch.interval").await.unwrap(), 120);
+    assert_eq!(config_value::<u16>("server.port").await.unwrap(), 5678);
+}
@@ -0,0 +1,28 @@
+use wallowa::{config_value, init_config};
+
+#[tokio::test]
+async fn environment_variables_separate_sections_with_double_underscores() {
+    let config_path = std::env::temp_dir().join("wallowa_config_test.config.toml");
+    std::fs::write(&config_path, "").unwrap();
+    std::env::set_var("WALLOWA_DATABASE", "env.db");
+    std::env::set_var("WALLOWA_GITHUB__MAX_CONCURRENCY", "7");
+    std::env::set_var("WALLOWA_GITHUB__AUTH__TOKEN", "a token");
+    std::env::set_var("WALLOWA_GITHUB__REPOS", "octocat/hello,octocat/world");
+    init_config(config_path.to_str().unwrap()).unwrap();
+
+    assert_eq!(config_value::<String>("database").await.unwrap(), "env.db");
+    assert_eq!(
+        config_value::<usize>("github.max_concurrency")
+            .await
+            .unwrap(),
+        7
+    );
+    assert_eq!(
+        config_value::<String>("github.auth.token").await.unwrap(),
+        "a token"
+    );
+    assert_eq!(
+        config_value::<Vec<String>>("github.repos").await.unwrap(),
+        vec!["octocat/hello", "octocat/world"]
+    );
+}
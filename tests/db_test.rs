@@ -0,0 +1,62 @@
+use wallowa_duckdb::{
+    migration_status, open_db_pool, open_read_pool, pending_migrations, MigrationState, MIGRATIONS,
+};
+
+#[test]
+fn read_pool_rolls_back_writes() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let read_pool = open_read_pool(&pool, 1).unwrap();
+
+    read_pool
+        .get()
+        .unwrap()
+        .execute_batch("CREATE TABLE written (id INTEGER); INSERT INTO written VALUES (1);")
+        .unwrap();
+    // A connection whose transaction was committed is closed instead of being reused
+    read_pool.get().unwrap().execute_batch("COMMIT").unwrap();
+
+    let tables: usize = pool
+        .get()
+        .unwrap()
+        .query_row(
+            "SELECT COUNT(*) FROM duckdb_tables() WHERE table_name = 'written'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(tables, 0);
+    assert!(read_pool.get().unwrap().execute_batch("SELECT 1").is_ok());
+}
+
+#[test]
+fn migration_status_detects_changed_migrations() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let conn = pool.get().unwrap();
+    assert!(migration_status(&conn)
+        .unwrap()
+        .iter()
+        .all(|status| status.state == MigrationState::Applied));
+
+    conn.execute(
+        "UPDATE wallowa_migration SET checksum = 'changed' WHERE \"name\" = ?",
+        [MIGRATIONS[1].name],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO wallowa_migration (\"name\", checksum, applied_at) VALUES ('9999_from_the_future', '', now())",
+        [],
+    )
+    .unwrap();
+
+    let statuses = migration_status(&conn).unwrap();
+    let state = |name: &str| {
+        statuses
+            .iter()
+            .find(|status| status.name == name)
+            .map(|status| status.state)
+    };
+    assert_eq!(state(MIGRATIONS[0].name), Some(MigrationState::Applied));
+    assert_eq!(state(MIGRATIONS[1].name), Some(MigrationState::Changed));
+    assert_eq!(state("9999_from_the_future"), Some(MigrationState::Unknown));
+    assert!(pending_migrations(&conn).is_err());
+}
@@ -0,0 +1,85 @@
+use arrow::array::{Array, Float64Array};
+use chrono::DateTime;
+use wallowa::github::{load::load_raw_data, queries::lead_time_for_changes};
+use wallowa_duckdb::{open_db_pool, Pool};
+
+fn insert_raw_data(pool: &Pool, data_type: &str, data: &str) {
+    pool.get()
+        .unwrap()
+        .execute(
+            r#"
+INSERT INTO wallowa_raw_data ("data_source", data_type, metadata, "data")
+VALUES ('github_rest_api', ?, to_json({owner: 'o', repo: 'r', etag: ''}), ?)
+"#,
+            [data_type, data],
+        )
+        .unwrap();
+}
+
+fn commit(sha: &str, committed_at: &str, parents: &[&str]) -> String {
+    let parents: Vec<String> = parents
+        .iter()
+        .map(|parent| format!(r#"{{"sha":"{parent}"}}"#))
+        .collect();
+    format!(
+        r#"{{"sha":"{sha}","commit":{{"committer":{{"date":"{committed_at}"}}}},"parents":[{}]}}"#,
+        parents.join(",")
+    )
+}
+
+#[test]
+fn lead_time_counts_the_commits_shipped_by_each_deployment() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    // `feature` branches off `c1` and is merged into `main` by `merge` after the first
+    // deployment. `c0` is older than the first deployment and is only reachable through it.
+    let commits = [
+        commit("c0", "2023-09-01T00:00:00Z", &[]),
+        commit("c1", "2023-10-02T00:00:00Z", &["c0"]),
+        commit("c2", "2023-10-02T12:00:00Z", &["c1"]),
+        commit("feature", "2023-10-03T00:00:00Z", &["c1"]),
+        commit("c3", "2023-10-04T00:00:00Z", &["c2"]),
+        commit("merge", "2023-10-10T00:00:00Z", &["c3", "feature"]),
+        commit("unshipped", "2023-10-11T00:00:00Z", &["merge"]),
+    ];
+    insert_raw_data(&pool, "commits", &format!("[{}]", commits.join(",")));
+    insert_raw_data(
+        &pool,
+        "deployments",
+        r#"[
+            {"url":"d1","sha":"c2","environment":"production","created_at":"2023-10-03T00:00:00Z","updated_at":"2023-10-03T00:00:00Z"},
+            {"url":"d2","sha":"merge","environment":"production","created_at":"2023-10-12T00:00:00Z","updated_at":"2023-10-12T00:00:00Z"}
+        ]"#,
+    );
+    insert_raw_data(
+        &pool,
+        "deployment_statuses",
+        r#"[
+            {"id":1,"deployment_url":"d1","state":"success","created_at":"2023-10-03T00:00:00Z"},
+            {"id":2,"deployment_url":"d2","state":"success","created_at":"2023-10-12T00:00:00Z"}
+        ]"#,
+    );
+    load_raw_data(&pool).unwrap();
+
+    let batches = lead_time_for_changes(
+        &pool,
+        DateTime::parse_from_rfc3339("2023-10-01T00:00:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2023-10-31T00:00:00Z").unwrap(),
+        &vec![],
+        "production",
+    )
+    .unwrap();
+
+    let mut lead_times = vec![];
+    for batch in &batches {
+        let column = batch
+            .column_by_name("lead_time")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        lead_times.extend((0..column.len()).map(|i| column.value(i)));
+    }
+    // The first deployment ships `c0` (768 hours), `c1` (24 hours), and `c2` (12 hours). The
+    // second ships `c3` (192 hours), `feature` (216 hours), and `merge` (48 hours).
+    assert_eq!(lead_times, vec![24.0, 192.0]);
+}
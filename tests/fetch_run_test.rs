@@ -0,0 +1,41 @@
+use wallowa::fetch_run::{count_items, record_fetch_run, record_response, record_rows_inserted};
+use wallowa_duckdb::open_db_pool;
+
+#[tokio::test]
+async fn fetch_run_counts_successful_pages_and_items() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+
+    record_fetch_run(&pool, "github_rest_api", "octocat/hello", async {
+        for status in [429, 200, 304, 502, 200] {
+            record_response(status);
+        }
+        record_rows_inserted(count_items(r#"[{"id":1},{"id":2}]"#));
+        record_rows_inserted(count_items(
+            r#"{"total_count":3,"workflow_runs":[{"id":1},{"id":2},{"id":3}]}"#,
+        ));
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let (pages_fetched, rows_inserted, http_statuses): (u64, u64, String) = pool
+        .get()
+        .unwrap()
+        .query_row(
+            "SELECT pages_fetched, rows_inserted, CAST(http_statuses AS VARCHAR) FROM wallowa_fetch_run",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(pages_fetched, 2);
+    assert_eq!(rows_inserted, 5);
+    assert_eq!(http_statuses, r#"{"200":2,"304":1,"429":1,"502":1}"#);
+}
+
+#[test]
+fn count_items_counts_arrays() {
+    assert_eq!(count_items("[]"), 0);
+    assert_eq!(count_items(r#"[{"id":1}]"#), 1);
+    assert_eq!(count_items(r#"{"total_count":0,"workflow_runs":[]}"#), 0);
+    assert_eq!(count_items("not json"), 0);
+}
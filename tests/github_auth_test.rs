@@ -0,0 +1,107 @@
+use std::{
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    routing::post,
+    Json, Router,
+};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use wallowa::github::auth::authorization_header;
+
+/// How long each installation token issued by the mock API is valid for, in minutes
+const EXPIRES_IN_MINUTES: [i64; 3] = [5, 60, 60];
+
+/// Serve a mock of the GitHub App installation token endpoint on the `listener`, returning the
+/// count of tokens issued
+fn serve_token_api(listener: TcpListener) -> Arc<AtomicUsize> {
+    let issued = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route(
+            "/app/installations/:installation_id/access_tokens",
+            post(issue_token),
+        )
+        .with_state(issued.clone());
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+    });
+    issued
+}
+
+async fn issue_token(
+    State(issued): State<Arc<AtomicUsize>>,
+    Path(installation_id): Path<String>,
+    headers: HeaderMap,
+) -> Json<Value> {
+    assert_eq!(installation_id, "42");
+    let jwt = headers["authorization"].to_str().unwrap();
+    assert!(jwt.starts_with("Bearer ey"), "{jwt}");
+
+    let count = issued.fetch_add(1, Ordering::SeqCst);
+    Json(json!({
+        "token": format!("installation token {count}"),
+        "expires_at": Utc::now() + Duration::minutes(EXPIRES_IN_MINUTES[count]),
+    }))
+}
+
+async fn authorization(url: &str) -> Option<String> {
+    authorization_header(url)
+        .await
+        .unwrap()
+        .map(|value| value.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn app_installation_tokens_are_cached_until_they_near_expiry() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let api_url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let private_key_path =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/github_app.private-key.pem");
+    let config_path = std::env::temp_dir().join("wallowa_github_auth_test.config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[github]\napi_url = {api_url:?}\n\n[github.auth]\ntoken = \"a token\"\napp_id = \"1\"\ninstallation_id = \"42\"\nprivate_key_path = {:?}\n",
+            private_key_path.to_str().unwrap()
+        ),
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+    let issued = serve_token_api(listener);
+    let repo_url = format!("{api_url}/repos/owner/repo");
+
+    // The first token expires within the refresh window, so it's replaced on the next request
+    assert_eq!(
+        authorization(&repo_url).await.unwrap(),
+        "Bearer installation token 0"
+    );
+    assert_eq!(
+        authorization(&repo_url).await.unwrap(),
+        "Bearer installation token 1"
+    );
+    // The second token is reused until it nears expiry
+    assert_eq!(
+        authorization(&repo_url).await.unwrap(),
+        "Bearer installation token 1"
+    );
+    assert_eq!(issued.load(Ordering::SeqCst), 2);
+
+    // The installation token is never sent to another host
+    assert_eq!(
+        authorization("https://ghe.example.com/api/v3/repos/owner/repo")
+            .await
+            .unwrap(),
+        "Bearer a token"
+    );
+    assert_eq!(issued.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,18 @@
+use wallowa::github::auth::authorization_header;
+
+#[tokio::test]
+async fn app_requests_to_other_hosts_are_unauthenticated_without_a_token() {
+    let config_path =
+        std::env::temp_dir().join("wallowa_github_auth_without_token_test.config.toml");
+    std::fs::write(
+        &config_path,
+        "[github.auth]\napp_id = \"1\"\ninstallation_id = \"42\"\nprivate_key_path = \"missing.pem\"\n",
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let header = authorization_header("https://ghe.example.com/api/v3/repos/owner/repo")
+        .await
+        .unwrap();
+    assert!(header.is_none());
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{TimeZone, Utc};
+use wallowa::github::fetch::{fetch_releases, Api, Backfill, GitHubRepo};
+use wallowa_duckdb::{open_db_pool, Pool};
+
+/// The state of the mock releases API
+struct MockApi {
+    url: String,
+    /// The `page` of each request received
+    requested_pages: Mutex<Vec<String>>,
+    /// Whether requests for the second page fail
+    fail_second_page: AtomicBool,
+}
+
+/// Serve two pages of releases, returning the state of the mock API
+fn serve() -> Arc<MockApi> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let state = Arc::new(MockApi {
+        url: format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port()),
+        requested_pages: Mutex::new(vec![]),
+        fail_second_page: AtomicBool::new(true),
+    });
+    let app = Router::new()
+        .route("/repos/owner/repo/releases", get(releases))
+        .with_state(state.clone());
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+    });
+    state
+}
+
+async fn releases(
+    State(state): State<Arc<MockApi>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let page = query.get("page").cloned().unwrap_or("1".to_string());
+    state.requested_pages.lock().unwrap().push(page.clone());
+    if page == "1" {
+        let link = format!(
+            "<{}/repos/owner/repo/releases?per_page=1&page=2>; rel=\"next\"",
+            state.url
+        );
+        (
+            [("link", link)],
+            r#"[{"id":2,"created_at":"2023-10-05T00:00:00Z"}]"#,
+        )
+            .into_response()
+    } else if state.fail_second_page.load(Ordering::SeqCst) {
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    } else {
+        r#"[{"id":1,"created_at":"2023-10-03T00:00:00Z"}]"#.into_response()
+    }
+}
+
+/// The `next_url` and whether the backfill of releases has been completed
+fn backfill_progress(pool: &Pool) -> (Option<String>, bool) {
+    pool.get()
+        .unwrap()
+        .query_row(
+            "SELECT next_url, completed_at IS NOT NULL FROM wallowa_backfill WHERE data_type = 'releases'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+}
+
+fn stored_pages(pool: &Pool) -> usize {
+    pool.get()
+        .unwrap()
+        .query_row(
+            "SELECT COUNT(*) FROM wallowa_raw_data WHERE data_type = 'releases'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+}
+
+#[tokio::test]
+async fn interrupted_backfill_resumes_at_the_recorded_page() {
+    let config_path = std::env::temp_dir().join("wallowa_github_backfill_test.config.toml");
+    std::fs::write(
+        &config_path,
+        "[github]\nmax_retries = 0\nper_page = 1\n\n[github.auth]\ntoken = \"a token\"\n",
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let api = serve();
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let repo = GitHubRepo {
+        api: Api::GitHub,
+        api_url: api.url.clone(),
+        host: api.url.trim_start_matches("http://").to_string(),
+        owner: "owner".to_string(),
+        name: "repo".to_string(),
+    };
+    let window = Backfill {
+        since: Utc.with_ymd_and_hms(2023, 10, 1, 0, 0, 0).unwrap(),
+        until: Some(Utc.with_ymd_and_hms(2023, 11, 1, 0, 0, 0).unwrap()),
+    };
+
+    // The second page fails, leaving it recorded as the next page to fetch
+    assert!(fetch_releases(&pool, &repo, Some(&window)).await.is_err());
+    assert_eq!(
+        backfill_progress(&pool),
+        (
+            Some(format!(
+                "{}/repos/owner/repo/releases?per_page=1&page=2",
+                api.url
+            )),
+            false
+        )
+    );
+    assert_eq!(stored_pages(&pool), 1);
+
+    // Running the backfill again starts at the second page instead of the first
+    api.fail_second_page.store(false, Ordering::SeqCst);
+    fetch_releases(&pool, &repo, Some(&window)).await.unwrap();
+    assert_eq!(backfill_progress(&pool), (None, true));
+    assert_eq!(stored_pages(&pool), 2);
+    assert_eq!(*api.requested_pages.lock().unwrap(), vec!["1", "2", "2"]);
+
+    // A completed backfill isn't fetched again
+    fetch_releases(&pool, &repo, Some(&window)).await.unwrap();
+    assert_eq!(api.requested_pages.lock().unwrap().len(), 3);
+}
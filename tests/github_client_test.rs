@@ -0,0 +1,179 @@
+use std::{
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Once,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use reqwest::header::HeaderMap as RequestHeaderMap;
+use wallowa::github::{client::GitHubClient, fetch::Api};
+
+static CONFIG: Once = Once::new();
+
+fn init_config() {
+    CONFIG.call_once(|| {
+        let config_path = std::env::temp_dir().join("wallowa_github_client_test.config.toml");
+        std::fs::write(
+            &config_path,
+            "[github]\nmax_retries = 2\n\n[github.auth]\ntoken = \"a token\"\n",
+        )
+        .unwrap();
+        wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+    });
+}
+
+/// A response for each request to the mock API, the last of which is repeated
+type Responses = Arc<Vec<fn() -> Response>>;
+
+/// Serve the `responses` in order on `/`, returning the base URL on `host` and the count of
+/// requests received
+async fn serve(host: &str, responses: Vec<fn() -> Response>) -> (String, Arc<AtomicUsize>) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let state = (Arc::new(responses), requests.clone());
+    let app = Router::new().route("/", get(respond)).with_state(state);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+    });
+
+    (format!("http://{host}:{port}/"), requests)
+}
+
+async fn respond(
+    State((responses, requests)): State<(Responses, Arc<AtomicUsize>)>,
+    headers: HeaderMap,
+) -> Response {
+    assert_eq!(headers["authorization"], "Bearer a token");
+    let request = requests.fetch_add(1, Ordering::SeqCst);
+    responses[request.min(responses.len() - 1)]()
+}
+
+fn ok() -> Response {
+    (StatusCode::OK, "[]").into_response()
+}
+
+async fn get_status(url: &str) -> StatusCode {
+    let client = GitHubClient::new(Api::GitHub).await.unwrap();
+    client
+        .get(url, RequestHeaderMap::new())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn client_retries_server_errors() {
+    init_config();
+    let (url, requests) = serve(
+        "127.0.0.1",
+        vec![|| StatusCode::BAD_GATEWAY.into_response(), ok],
+    )
+    .await;
+
+    assert_eq!(get_status(&url).await, StatusCode::OK);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn client_returns_the_last_response_once_the_retries_run_out() {
+    init_config();
+    let (url, requests) = serve(
+        "127.0.0.1",
+        vec![|| StatusCode::INTERNAL_SERVER_ERROR.into_response()],
+    )
+    .await;
+
+    assert_eq!(get_status(&url).await, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn client_waits_for_retry_after() {
+    init_config();
+    let (url, requests) = serve(
+        "127.0.0.1",
+        vec![
+            || (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "1")]).into_response(),
+            ok,
+        ],
+    )
+    .await;
+
+    let started = Instant::now();
+    assert_eq!(get_status(&url).await, StatusCode::OK);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn client_retries_secondary_rate_limits() {
+    init_config();
+    let (url, requests) = serve(
+        "127.0.0.1",
+        vec![
+            || (StatusCode::FORBIDDEN, [("retry-after", "0")]).into_response(),
+            ok,
+        ],
+    )
+    .await;
+
+    assert_eq!(get_status(&url).await, StatusCode::OK);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn client_does_not_retry_other_forbidden_responses() {
+    init_config();
+    let (url, requests) = serve("127.0.0.1", vec![|| StatusCode::FORBIDDEN.into_response()]).await;
+
+    assert_eq!(get_status(&url).await, StatusCode::FORBIDDEN);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn clients_share_the_rate_limit_reset_of_a_host() {
+    init_config();
+    // `localhost` keeps the reset from holding up the other tests, which use `127.0.0.1`. The
+    // reset is two seconds out since it's rounded down to the second.
+    let (url, requests) = serve(
+        "localhost",
+        vec![
+            || {
+                let reset = (Utc::now().timestamp() + 2).to_string();
+                (
+                    StatusCode::OK,
+                    [
+                        ("x-ratelimit-remaining", "0".to_string()),
+                        ("x-ratelimit-reset", reset),
+                    ],
+                    "[]",
+                )
+                    .into_response()
+            },
+            ok,
+        ],
+    )
+    .await;
+
+    assert_eq!(get_status(&url).await, StatusCode::OK);
+    // A new client waits until the budget resets before making its request
+    let started = Instant::now();
+    assert_eq!(get_status(&url).await, StatusCode::OK);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
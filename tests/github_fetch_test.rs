@@ -0,0 +1,114 @@
+use std::{net::TcpListener, time::Duration};
+
+use axum::{routing::get, Json, Router};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use wallowa_duckdb::{open_db_pool, open_read_pool, Pool};
+
+/// Serve a mock of the GitHub REST API with a single pull request in `owner/repo` and nothing
+/// else, returning its base URL
+fn serve_github_api() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let pull_url = format!("{url}/repos/owner/repo/pulls/1");
+    let app = Router::new()
+        .route(
+            "/repos/owner/repo/pulls",
+            get(move || async move {
+                Json(json!([{
+                    "url": pull_url,
+                    "number": 1,
+                    "user": {"login": "a"},
+                    "base": {"repo": {"name": "repo", "owner": {"login": "owner"}}},
+                    "state": "open",
+                    "created_at": "2023-10-01T00:00:00Z",
+                    "closed_at": null,
+                    "merged_at": null,
+                    "updated_at": "2023-10-02T00:00:00Z"
+                }]))
+            }),
+        )
+        .route(
+            "/repos/owner/repo/actions/runs",
+            get(|| async { Json(json!({"total_count": 0, "workflow_runs": []})) }),
+        )
+        .fallback(|| async { Json(Value::Array(vec![])) });
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+    });
+    url
+}
+
+/// Serve the web app with GitHub fetched from a mock API, returning the base URLs of the app
+/// and the API and the pool the app writes to
+async fn serve() -> (String, String, Pool) {
+    let api_url = serve_github_api();
+    let config_path = std::env::temp_dir().join("wallowa_github_fetch_test.config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[github]\napi_url = {api_url:?}\nrepos = [\"owner/repo\"]\nmax_retries = 0\n\n[github.auth]\ntoken = \"a token\"\n"
+        ),
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let read_pool = open_read_pool(&pool, 1).unwrap();
+    let server_pool = pool.clone();
+    tokio::spawn(async move {
+        wallowa::web::serve("127.0.0.1", &port.to_string(), server_pool, read_pool).await
+    });
+
+    let url = format!("http://127.0.0.1:{port}");
+    for _ in 0..50 {
+        if reqwest::get(&url).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    (url, api_url, pool)
+}
+
+#[tokio::test]
+async fn fetch_now_loads_the_fetched_pull_requests() {
+    let (url, api_url, pool) = serve().await;
+
+    let resp = reqwest::Client::new()
+        .post(format!("{url}/sources/github/fetch"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let last_fetched = resp.text().await.unwrap();
+    assert!(
+        chrono::NaiveDateTime::parse_from_str(last_fetched.trim(), "%Y-%m-%dT%H:%M:%SZ").is_ok(),
+        "{last_fetched}"
+    );
+
+    let pulls: Vec<(String, String)> = {
+        let conn = pool.get().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT repo, state FROM github_pull_request")
+            .unwrap();
+        let pulls = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        pulls
+    };
+    let host = api_url.trim_start_matches("http://");
+    assert_eq!(
+        pulls,
+        vec![(format!("{host}/owner/repo"), "open".to_string())]
+    );
+}
@@ -0,0 +1,86 @@
+use std::{net::TcpListener, time::Duration};
+
+use reqwest::StatusCode;
+use serde_json::Value;
+use wallowa_duckdb::{open_db_pool, open_read_pool};
+
+/// Serve the web app with `ingest.auth.token` set to `token`, returning its base URL
+async fn serve_with_token(token: &str) -> String {
+    let config_path = std::env::temp_dir().join("wallowa_ingest_test.config.toml");
+    std::fs::write(
+        &config_path,
+        format!("[ingest.auth]\ntoken = \"{token}\"\n"),
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let read_pool = open_read_pool(&pool, 1).unwrap();
+    tokio::spawn(async move {
+        wallowa::web::serve("127.0.0.1", &port.to_string(), pool, read_pool).await
+    });
+
+    let url = format!("http://127.0.0.1:{port}");
+    for _ in 0..50 {
+        if reqwest::get(&url).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    url
+}
+
+#[tokio::test]
+async fn ingest_validates_requests() {
+    let url = serve_with_token("token").await;
+    let client = reqwest::Client::new();
+    let body = r#"{"status": "success"}"#;
+
+    let post = |path: &str, bearer: Option<&str>, body: &'static str| {
+        let mut request = client.post(format!("{url}/ingest/{path}")).body(body);
+        if let Some(bearer) = bearer {
+            request = request.header("Authorization", format!("Bearer {bearer}"));
+        }
+        request.send()
+    };
+
+    let resp = post("ci/deploys", None, body).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = post("ci/deploys", Some("other token"), body).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    for path in ["CI/deploys", "ci/deploy-events", "ci/deploys%20all"] {
+        let resp = post(path, Some("token"), body).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+    let long_name = "a".repeat(65);
+    let resp = post(&format!("{long_name}/deploys"), Some("token"), body)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Pushed events can't be mixed in with the rows of a registered source
+    let resp = post("github_rest_api/pulls", Some("token"), body)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        resp.text().await.unwrap(),
+        "`github_rest_api` is fetched by a source and can't be pushed to"
+    );
+
+    let resp = post("ci/deploys", Some("token"), "not json").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = post("ci/deploys?env=production", Some("token"), body)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = resp.json().await.unwrap();
+    assert!(created["id"].is_i64());
+}
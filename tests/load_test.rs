@@ -0,0 +1,196 @@
+use wallowa::github::load::{load_raw_data, rebuild};
+use wallowa_duckdb::{open_db_pool, Pool};
+
+fn insert_raw_data(pool: &Pool, host: &str, data_type: &str, data: &str) {
+    pool.get()
+        .unwrap()
+        .execute(
+            r#"
+INSERT INTO wallowa_raw_data ("data_source", data_type, metadata, "data")
+VALUES ('github_rest_api', ?, to_json({host: ?, owner: 'o', repo: 'r', etag: ''}), ?)
+"#,
+            [data_type, host, data],
+        )
+        .unwrap();
+}
+
+#[test]
+fn load_keeps_the_same_id_from_each_host() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    for host in ["api.github.com", "ghe.example.com"] {
+        insert_raw_data(
+            &pool,
+            host,
+            "releases",
+            r#"[{"id":1,"draft":false,"prerelease":false,"published_at":"2023-10-01T00:00:00Z"}]"#,
+        );
+        insert_raw_data(
+            &pool,
+            host,
+            "pull_reviews",
+            &format!(
+                r#"[{{"id":1,"pull_request_url":"https://{host}/pulls/1","user":{{"login":"a"}},"state":"APPROVED","submitted_at":"2023-10-01T00:00:00Z"}}]"#
+            ),
+        );
+    }
+
+    assert_eq!(load_raw_data(&pool).unwrap(), 4);
+
+    let conn = pool.get().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT host, repo FROM github_release ORDER BY host")
+        .unwrap();
+    let releases: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    assert_eq!(
+        releases,
+        vec![
+            ("api.github.com".to_string(), "o/r".to_string()),
+            (
+                "ghe.example.com".to_string(),
+                "ghe.example.com/o/r".to_string()
+            ),
+        ]
+    );
+    let reviews: usize = conn
+        .query_row("SELECT COUNT(*) FROM github_pull_review", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(reviews, 2);
+}
+
+fn pull(state: &str, updated_at: &str) -> String {
+    format!(
+        r#"[{{"url":"https://api.github.com/repos/o/r/pulls/1","user":{{"login":"a"}},"base":{{"repo":{{"name":"r","owner":{{"login":"o"}}}}}},"state":"{state}","created_at":"2023-10-01T00:00:00Z","closed_at":null,"merged_at":null,"updated_at":"{updated_at}"}}]"#
+    )
+}
+
+/// The state of each loaded pull request
+fn pull_states(pool: &Pool) -> Vec<String> {
+    let conn = pool.get().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT state FROM github_pull_request ORDER BY url")
+        .unwrap();
+    let states = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    states
+}
+
+#[test]
+fn load_keeps_the_latest_version_of_each_pull_request() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    insert_raw_data(
+        &pool,
+        "api.github.com",
+        "pulls",
+        &pull("open", "2023-10-01T00:00:00Z"),
+    );
+    insert_raw_data(
+        &pool,
+        "api.github.com",
+        "pulls",
+        &pull("closed", "2023-10-05T00:00:00Z"),
+    );
+
+    assert_eq!(load_raw_data(&pool).unwrap(), 2);
+    assert_eq!(pull_states(&pool), vec!["closed"]);
+
+    // Loading again doesn't change anything since every raw row has been loaded
+    assert_eq!(load_raw_data(&pool).unwrap(), 0);
+    assert_eq!(pull_states(&pool), vec!["closed"]);
+
+    // A newer raw row with an older version of the pull request doesn't replace the latest
+    insert_raw_data(
+        &pool,
+        "api.github.com",
+        "pulls",
+        &pull("open", "2023-10-01T00:00:00Z"),
+    );
+    assert_eq!(load_raw_data(&pool).unwrap(), 1);
+    assert_eq!(pull_states(&pool), vec!["closed"]);
+
+    assert_eq!(rebuild(&pool).unwrap(), 3);
+    assert_eq!(pull_states(&pool), vec!["closed"]);
+}
+
+#[test]
+fn load_keeps_the_newest_row_of_items_without_an_updated_at() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    for draft in [true, false] {
+        insert_raw_data(
+            &pool,
+            "api.github.com",
+            "releases",
+            &format!(
+                r#"[{{"id":1,"draft":{draft},"prerelease":false,"published_at":"2023-10-01T00:00:00Z"}}]"#
+            ),
+        );
+    }
+
+    for loaded in [load_raw_data(&pool).unwrap(), rebuild(&pool).unwrap()] {
+        assert_eq!(loaded, 2);
+        let draft: bool = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT draft FROM github_release", [], |row| row.get(0))
+            .unwrap();
+        assert!(!draft);
+    }
+}
+
+#[test]
+fn load_keeps_gitea_pull_requests_apart_from_github() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    insert_raw_data(
+        &pool,
+        "api.github.com",
+        "pulls",
+        &pull("open", "2023-10-01T00:00:00Z"),
+    );
+    pool.get()
+        .unwrap()
+        .execute(
+            r#"
+INSERT INTO wallowa_raw_data ("data_source", data_type, metadata, "data")
+VALUES ('gitea_rest_api', 'pulls', to_json({host: 'codeberg.org', owner: 'o', repo: 'r', etag: ''}), ?)
+"#,
+            [pull("closed", "2023-10-05T00:00:00Z")],
+        )
+        .unwrap();
+
+    assert_eq!(load_raw_data(&pool).unwrap(), 2);
+
+    let conn = pool.get().unwrap();
+    let mut stmt = conn
+        .prepare(
+            r#"SELECT "data_source", repo, state FROM github_pull_request ORDER BY "data_source""#,
+        )
+        .unwrap();
+    let pulls: Vec<(String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    assert_eq!(
+        pulls,
+        vec![
+            (
+                "gitea_rest_api".to_string(),
+                "codeberg.org/o/r".to_string(),
+                "closed".to_string()
+            ),
+            (
+                "github_rest_api".to_string(),
+                "o/r".to_string(),
+                "open".to_string()
+            ),
+        ]
+    );
+}
@@ -0,0 +1,116 @@
+use wallowa::query::run_query;
+use wallowa_duckdb::{open_db_pool, open_read_pool, Pool};
+
+fn read_pool() -> Pool {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    open_read_pool(&pool, 1).unwrap()
+}
+
+fn query_error(pool: &Pool, sql: &str) -> String {
+    match run_query(pool, sql, 10) {
+        Ok(_) => panic!("Expected `{sql}` to be rejected"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn run_query_limits_rows() {
+    let pool = read_pool();
+
+    let results = run_query(&pool, "SELECT * FROM range(5);", 3).unwrap();
+    let rows: usize = results.batches.iter().map(|batch| batch.num_rows()).sum();
+    assert_eq!(rows, 3);
+    assert!(results.truncated);
+
+    let results = run_query(
+        &pool,
+        "WITH r AS (SELECT * FROM generate_series(1, 2)) FROM r",
+        3,
+    )
+    .unwrap();
+    let rows: usize = results.batches.iter().map(|batch| batch.num_rows()).sum();
+    assert_eq!(rows, 2);
+    assert!(!results.truncated);
+}
+
+#[test]
+fn run_query_rejects_other_statements() {
+    let pool = read_pool();
+
+    assert_eq!(
+        query_error(&pool, "DELETE FROM wallowa_raw_data"),
+        "Only SELECT statements are allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "COPY wallowa_raw_data TO 'raw.csv'"),
+        "Only SELECT statements are allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "SELECT 1; SELECT 2"),
+        "Only a single statement is allowed"
+    );
+}
+
+#[test]
+fn run_query_rejects_reading_files() {
+    let pool = read_pool();
+
+    assert_eq!(
+        query_error(&pool, "SELECT * FROM read_csv_auto('/etc/passwd')"),
+        "The `read_csv_auto` table function isn't allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "SELECT 1 WHERE 1 IN (SELECT 1 FROM glob('*'))"),
+        "The `glob` table function isn't allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "FROM 'wallowa.config.toml'"),
+        "Reading from files or URLs isn't allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "SELECT * FROM 'https://example.com/data.parquet'"),
+        "Reading from files or URLs isn't allowed"
+    );
+}
+
+#[test]
+fn run_query_rejects_reading_settings_and_the_environment() {
+    let pool = read_pool();
+
+    assert_eq!(
+        query_error(&pool, "SELECT current_setting('s3_secret_access_key')"),
+        "The `current_setting` function isn't allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "SELECT 1 WHERE CURRENT_SETTING('threads') > 0"),
+        "The `current_setting` function isn't allowed"
+    );
+    assert_eq!(
+        query_error(&pool, "SELECT getenv('HOME')"),
+        "The `getenv` function isn't allowed"
+    );
+    for function in [
+        "duckdb_settings",
+        "duckdb_secrets",
+        "duckdb_extensions",
+        "duckdb_databases",
+        "duckdb_temporary_files",
+    ] {
+        assert_eq!(
+            query_error(&pool, &format!("SELECT * FROM {function}()")),
+            format!("The `{function}` table function isn't allowed")
+        );
+    }
+
+    // The tables can still be described
+    for function in [
+        "duckdb_tables",
+        "duckdb_columns",
+        "duckdb_views",
+        "duckdb_types",
+        "duckdb_functions",
+    ] {
+        let sql = format!("SELECT * FROM {function}()");
+        assert!(run_query(&pool, &sql, 10).is_ok(), "{sql}");
+    }
+}
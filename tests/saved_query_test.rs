@@ -0,0 +1,89 @@
+use std::{net::TcpListener, time::Duration};
+
+use reqwest::StatusCode;
+use wallowa_duckdb::{open_db_pool, open_read_pool};
+
+/// Serve the web app with `query.auth.token` set to `token`, returning its base URL
+async fn serve_with_token(token: &str) -> String {
+    let config_path = std::env::temp_dir().join("wallowa_saved_query_test.config.toml");
+    std::fs::write(&config_path, format!("[query.auth]\ntoken = \"{token}\"\n")).unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let read_pool = open_read_pool(&pool, 1).unwrap();
+    tokio::spawn(async move {
+        wallowa::web::serve("127.0.0.1", &port.to_string(), pool, read_pool).await
+    });
+
+    let url = format!("http://127.0.0.1:{port}");
+    for _ in 0..50 {
+        if reqwest::get(&url).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    url
+}
+
+#[tokio::test]
+async fn saved_queries_can_only_be_changed_from_the_same_site() {
+    let url = serve_with_token("token").await;
+    let client = reqwest::Client::new();
+    let saved_url = format!("{url}/query/saved");
+    let save = || {
+        client
+            .post(&saved_url)
+            .basic_auth("", Some("token"))
+            .form(&[("name", "runs"), ("sql", "SELECT 1")])
+    };
+
+    // Browsers send the cached Basic auth credentials with requests from other sites too
+    let resp = save()
+        .header("Origin", "https://attacker.example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = save()
+        .header("Sec-Fetch-Site", "cross-site")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = save()
+        .header("Origin", &url)
+        .header("Sec-Fetch-Site", "same-origin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.text().await.unwrap().contains("runs"));
+
+    let resp = client
+        .delete(&saved_url)
+        .query(&[("name", "runs")])
+        .basic_auth("", Some("token"))
+        .header("Origin", "https://attacker.example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // A bearer token can't be sent by another site, so it's allowed from anywhere
+    let resp = client
+        .delete(&saved_url)
+        .query(&[("name", "runs")])
+        .bearer_auth("token")
+        .header("Origin", "https://other.example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!resp.text().await.unwrap().contains("runs"));
+}
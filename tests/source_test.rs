@@ -0,0 +1,90 @@
+use std::{net::TcpListener, path::Path, process::Command, time::Duration};
+
+use reqwest::StatusCode;
+use wallowa_duckdb::{open_db_pool, open_read_pool};
+
+/// Create a git repo with a single commit at `path`
+fn create_repo(path: &Path) {
+    let _ = std::fs::remove_dir_all(path);
+    std::fs::create_dir_all(path).unwrap();
+    for args in [
+        vec!["init", "--quiet"],
+        vec![
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "--message",
+            "Initial commit",
+        ],
+    ] {
+        let status = Command::new("git")
+            .args(&args)
+            .current_dir(path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
+
+/// Serve the web app with only the git source configured, returning its base URL
+async fn serve() -> String {
+    let repo_path = std::env::temp_dir().join("wallowa_source_test_repo");
+    create_repo(&repo_path);
+    let config_path = std::env::temp_dir().join("wallowa_source_test.config.toml");
+    std::fs::write(
+        &config_path,
+        format!("[git]\nrepos = [{:?}]\n", repo_path.to_str().unwrap()),
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let read_pool = open_read_pool(&pool, 1).unwrap();
+    tokio::spawn(async move {
+        wallowa::web::serve("127.0.0.1", &port.to_string(), pool, read_pool).await
+    });
+
+    let url = format!("http://127.0.0.1:{port}");
+    for _ in 0..50 {
+        if reqwest::get(&url).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    url
+}
+
+#[tokio::test]
+async fn fetch_source_dispatches_by_name() {
+    let url = serve().await;
+    let client = reqwest::Client::new();
+    let fetch = |name: &str| client.post(format!("{url}/sources/{name}/fetch")).send();
+
+    let resp = fetch("unknown").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // Only the configured git source is fetched, so it is the only one with a fetch time
+    let resp = fetch("git").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let last_fetched = resp.text().await.unwrap();
+    assert!(
+        chrono::NaiveDateTime::parse_from_str(last_fetched.trim(), "%Y-%m-%dT%H:%M:%SZ").is_ok(),
+        "{last_fetched}"
+    );
+
+    let resp = fetch("github").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.text().await.unwrap().trim(),
+        "GitHub is not configured"
+    );
+}
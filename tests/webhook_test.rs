@@ -0,0 +1,75 @@
+use std::{net::TcpListener, time::Duration};
+
+use reqwest::StatusCode;
+use ring::hmac;
+use wallowa_duckdb::{open_db_pool, open_read_pool};
+
+/// Serve the web app with `github.webhook.secret` set to `secret`, returning its base URL
+async fn serve_with_secret(secret: &str) -> String {
+    let config_path = std::env::temp_dir().join("wallowa_webhook_test.config.toml");
+    std::fs::write(
+        &config_path,
+        format!("[github.webhook]\nsecret = \"{secret}\"\n"),
+    )
+    .unwrap();
+    wallowa::init_config(config_path.to_str().unwrap()).unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    let read_pool = open_read_pool(&pool, 1).unwrap();
+    tokio::spawn(async move {
+        wallowa::web::serve("127.0.0.1", &port.to_string(), pool, read_pool).await
+    });
+
+    let url = format!("http://127.0.0.1:{port}");
+    for _ in 0..50 {
+        if reqwest::get(&url).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    url
+}
+
+fn signature(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    format!("sha256={}", hex::encode(hmac::sign(&key, body).as_ref()))
+}
+
+#[tokio::test]
+async fn webhook_rejects_invalid_signatures() {
+    let url = format!("{}/github/webhook", serve_with_secret("secret").await);
+    let client = reqwest::Client::new();
+    let body = br#"{"zen": "Keep it logically awesome."}"#;
+
+    let post = |signature: Option<String>| {
+        let mut request = client
+            .post(&url)
+            .header("X-GitHub-Event", "push")
+            .header("X-GitHub-Delivery", "1")
+            .body(body.to_vec());
+        if let Some(signature) = signature {
+            request = request.header("X-Hub-Signature-256", signature);
+        }
+        request.send()
+    };
+
+    let resp = post(None).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = post(Some(signature("other secret", body))).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = post(Some(signature("secret", b"another body")))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = post(Some("sha256=not hex".to_string())).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = post(Some(signature("secret", body))).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text().await.unwrap(), "Ignored `push` event");
+}
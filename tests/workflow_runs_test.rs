@@ -0,0 +1,63 @@
+use arrow::array::{Array, StringArray};
+use chrono::DateTime;
+use wallowa::github::{
+    load::load_raw_data,
+    queries::{select_distinct_workflow_run_repos, workflow_run_durations},
+};
+use wallowa_duckdb::{open_db_pool, Pool};
+
+fn insert_raw_data(pool: &Pool, repo: &str, data_type: &str, data: &str) {
+    pool.get()
+        .unwrap()
+        .execute(
+            r#"
+INSERT INTO wallowa_raw_data ("data_source", data_type, metadata, "data")
+VALUES ('github_rest_api', ?, to_json({owner: 'o', repo: ?, etag: ''}), ?)
+"#,
+            [data_type, repo, data],
+        )
+        .unwrap();
+}
+
+#[test]
+fn workflow_runs_default_to_the_repos_with_runs() {
+    let pool = open_db_pool(":memory:", 1).unwrap();
+    // `o/ci` only has workflow runs and `o/app` only has pull requests
+    insert_raw_data(
+        &pool,
+        "ci",
+        "workflow_runs",
+        r#"{"workflow_runs":[{"id":1,"url":"https://api.github.com/repos/o/ci/actions/runs/1","name":"CI","head_branch":"main","status":"completed","conclusion":"success","run_attempt":1,"created_at":"2023-10-02T00:00:00Z","run_started_at":"2023-10-02T00:00:00Z","updated_at":"2023-10-02T00:10:00Z","repository":{"full_name":"o/ci"}}]}"#,
+    );
+    insert_raw_data(
+        &pool,
+        "app",
+        "pulls",
+        r#"[{"url":"https://api.github.com/repos/o/app/pulls/1","user":{"login":"a"},"base":{"repo":{"name":"app","owner":{"login":"o"}}},"state":"open","created_at":"2023-10-01T00:00:00Z","closed_at":null,"merged_at":null,"updated_at":"2023-10-01T00:00:00Z"}]"#,
+    );
+    load_raw_data(&pool).unwrap();
+
+    assert_eq!(
+        select_distinct_workflow_run_repos(&pool).unwrap(),
+        vec!["o/ci".to_string()]
+    );
+
+    let batches = workflow_run_durations(
+        &pool,
+        DateTime::parse_from_rfc3339("2023-10-01T00:00:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2023-10-31T00:00:00Z").unwrap(),
+        &vec![],
+    )
+    .unwrap();
+    let mut repos = vec![];
+    for batch in &batches {
+        let column = batch
+            .column_by_name("repo")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        repos.extend((0..column.len()).map(|i| column.value(i).to_string()));
+    }
+    assert_eq!(repos, vec!["o/ci"]);
+}
@@ -1,23 +1,89 @@
-use anyhow::Result;
-use duckdb::{Connection, DuckdbConnectionManager};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use chrono::NaiveDateTime;
 pub use duckdb;
-use tracing::{debug, error};
+use duckdb::Connection;
+use ring::digest;
+use tracing::{debug, error, info};
+
+pub type Pool = r2d2::Pool<ConnectionManager>;
+
+/// An `r2d2::ManageConnection` for connections to an open DuckDB database.
+///
+/// Each connection is a clone of the same `Connection`, so every pool created from it,
+/// including the pools from [`open_read_pool`], shares one instance of the database.
+pub struct ConnectionManager {
+    connection: Arc<Mutex<Connection>>,
+    /// Whether each connection is checked out in a transaction that is rolled back when it's
+    /// returned to the pool, so that nothing written with it is ever committed
+    read_only: bool,
+}
+
+impl r2d2::ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = duckdb::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let conn = self.connection.lock().unwrap();
+        conn.try_clone()
+    }
 
-pub type Pool = r2d2::Pool<DuckdbConnectionManager>;
+    // The pools are built with the default `test_on_check_out`, so this runs each time a
+    // connection is checked out
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        if self.read_only {
+            conn.execute_batch("BEGIN TRANSACTION")
+        } else {
+            conn.execute_batch("")
+        }
+    }
+
+    // Runs each time a connection is returned to the pool. A read-only connection that can't
+    // be rolled back, such as when its transaction was committed, is closed instead.
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.read_only && conn.execute_batch("ROLLBACK").is_err()
+    }
+}
 
 /// Create a pool of connections to the database at the given `connection_string`
-/// with the given `max_size`.
+/// with the given `max_size` and run the migrations that haven't been run yet.
+///
+/// # Errors
+///
+/// Returns an error without running any migrations if the database has migrations that
+/// aren't in [`MIGRATIONS`] (it was migrated by a newer version) or if any applied
+/// migration has changed since it was applied.
 ///
 /// # Panics
 ///
 /// Panics if `max_size` is set to 0.
 pub fn open_db_pool(connection_string: &str, max_size: u32) -> Result<Pool> {
+    let pool = open_db_pool_without_migrations(connection_string, max_size)?;
+
+    let mut conn = pool.get()?;
+    run_migrations(&mut conn)?;
+
+    Ok(pool)
+}
+
+/// Create a pool of connections to the database at the given `connection_string`
+/// with the given `max_size` without running any migrations, such as to check the
+/// [`migration_status`] of the database.
+///
+/// # Panics
+///
+/// Panics if `max_size` is set to 0.
+pub fn open_db_pool_without_migrations(connection_string: &str, max_size: u32) -> Result<Pool> {
     debug!("Opening database at '{}'", connection_string);
 
-    let manager = DuckdbConnectionManager::file(connection_string)?;
+    let manager = ConnectionManager {
+        connection: Arc::new(Mutex::new(Connection::open(connection_string)?)),
+        read_only: false,
+    };
     let pool = r2d2::Pool::builder().max_size(max_size).build(manager)?;
 
-    let mut conn = pool.get()?;
+    let conn = pool.get()?;
 
     conn.execute_batch(
         r#"
@@ -26,23 +92,55 @@ LOAD 'json';
 "#,
     )?;
 
-    run_migrations(&mut conn)?;
-
     Ok(pool)
 }
 
-const MIGRATION_INDEX_NAME: &str = "migration_index";
-const SETTING_TABLE_NAME: &str = "wallowa_setting";
+/// Create a separate pool of connections with the given `max_size` to the same database
+/// as `pool`, for queries that only read from the database.
+///
+/// Queries use their own connections from this pool so that they don't wait for the
+/// connections of `pool` to be free while it's used for writes, such as during a fetch.
+/// Reads see the data committed by the other pool since both share the same instance of
+/// the database. DuckDB can't open a read-only connection to a database that's already
+/// open for writing, so instead each connection is checked out in a transaction that is
+/// always rolled back when the connection is returned.
+///
+/// # Panics
+///
+/// Panics if `max_size` is set to 0.
+pub fn open_read_pool(pool: &Pool, max_size: u32) -> Result<Pool> {
+    let manager = ConnectionManager {
+        connection: Arc::new(Mutex::new(pool.get()?.try_clone()?)),
+        read_only: true,
+    };
+    Ok(r2d2::Pool::builder().max_size(max_size).build(manager)?)
+}
 
-/// Run all migrations that have not yet been run on the given database
-fn run_migrations(conn: &mut Connection) -> Result<()> {
-    debug!("Running migrations");
+/// A named migration of the database schema
+#[derive(Debug)]
+pub struct Migration {
+    /// The unique name of the migration, prefixed with its number so that names sort in
+    /// the order the migrations run
+    pub name: &'static str,
+    pub sql: &'static str,
+}
 
-    // The full list of migrations to run.
-    // Add new migrations to the tail of the vector.
-    let migrations = vec![
-        // Create the `wallowa_setting` table and initialize the `migration_index`
-        r#"
+impl Migration {
+    /// The SHA-256 checksum of the `sql`, which is recorded when the migration is applied
+    /// so that later changes to an applied migration can be detected
+    pub fn checksum(&self) -> String {
+        hex::encode(digest::digest(&digest::SHA256, self.sql.as_bytes()))
+    }
+}
+
+/// The full list of migrations in the order they run.
+/// Add new migrations to the tail of the list with the next number. Migrations must not be
+/// changed once released since the checksum of each applied migration is checked.
+pub const MIGRATIONS: &[Migration] = &[
+    // Create the `wallowa_setting` table and initialize the `migration_index`
+    Migration {
+        name: "0001_create_wallowa_setting",
+        sql: r#"
 CREATE TABLE IF NOT EXISTS wallowa_setting (
     "name" VARCHAR NOT NULL,
     "value" JSON
@@ -51,8 +149,11 @@ CREATE TABLE IF NOT EXISTS wallowa_setting (
 -- Inserting this row here so that there is no need to upsert as part of `run_migrations`
 INSERT INTO wallowa_setting ("name", "value") VALUES ('migration_index', 0);
         "#,
-        // Create the `wallowa_raw_data` table
-        r#"
+    },
+    // Create the `wallowa_raw_data` table
+    Migration {
+        name: "0002_create_wallowa_raw_data",
+        sql: r#"
 CREATE SEQUENCE seq_wallowa_raw_data;
 CREATE TABLE IF NOT EXISTS wallowa_raw_data (
     id INTEGER PRIMARY KEY DEFAULT NEXTVAL('seq_wallowa_raw_data'),
@@ -63,65 +164,487 @@ CREATE TABLE IF NOT EXISTS wallowa_raw_data (
     metadata JSON,
     "data" VARCHAR
 );"#,
-    ];
+    },
+    // Create the `github_repo_name` macro to name GitHub repos consistently across hosts.
+    // Repos on github.com (including rows fetched before the host was stored) keep their
+    // `owner/repo` name while repos on other hosts are prefixed with the host.
+    Migration {
+        name: "0003_create_github_repo_name_macro",
+        sql: r#"
+CREATE MACRO github_repo_name(metadata, full_name) AS
+    CASE
+        WHEN coalesce(metadata->>'$.host', 'api.github.com') = 'api.github.com' THEN full_name
+        ELSE (metadata->>'$.host') || '/' || full_name
+    END;"#,
+    },
+    // Create the `wallowa_backfill` table to track the progress of backfills
+    Migration {
+        name: "0004_create_wallowa_backfill",
+        sql: r#"
+CREATE TABLE IF NOT EXISTS wallowa_backfill (
+    "data_source" VARCHAR NOT NULL,
+    data_type VARCHAR NOT NULL,
+    repo VARCHAR NOT NULL,
+    since TIMESTAMP NOT NULL,
+    "until" TIMESTAMP,
+    next_url VARCHAR,
+    completed_at TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT now() NOT NULL
+);"#,
+    },
+    // Create the `wallowa_fetch_run` table to record the history of fetches
+    Migration {
+        name: "0005_create_wallowa_fetch_run",
+        sql: r#"
+CREATE SEQUENCE seq_wallowa_fetch_run;
+CREATE TABLE IF NOT EXISTS wallowa_fetch_run (
+    id INTEGER PRIMARY KEY DEFAULT NEXTVAL('seq_wallowa_fetch_run'),
+    "data_source" VARCHAR NOT NULL,
+    repo VARCHAR,
+    started_at TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP,
+    pages_fetched UBIGINT DEFAULT 0 NOT NULL,
+    rows_inserted UBIGINT DEFAULT 0 NOT NULL,
+    http_statuses JSON,
+    error VARCHAR
+);"#,
+    },
+    // Create the normalized GitHub tables that are loaded from `wallowa_raw_data`.
+    // Each table holds the latest version of each item and can be rebuilt from the raw data.
+    Migration {
+        name: "0006_create_github_normalized_tables",
+        sql: r#"
+CREATE TABLE IF NOT EXISTS github_pull_request (
+    "data_source" VARCHAR NOT NULL,
+    "url" VARCHAR NOT NULL,
+    repo VARCHAR,
+    author VARCHAR,
+    state VARCHAR,
+    created_at TIMESTAMP,
+    closed_at TIMESTAMP,
+    merged_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY ("data_source", "url")
+);
+CREATE TABLE IF NOT EXISTS github_pull_review (
+    id BIGINT PRIMARY KEY,
+    pull_request_url VARCHAR,
+    reviewer VARCHAR,
+    state VARCHAR,
+    submitted_at TIMESTAMP,
+    raw_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS github_pull_review_comment (
+    id BIGINT PRIMARY KEY,
+    pull_request_url VARCHAR,
+    reviewer VARCHAR,
+    created_at TIMESTAMP,
+    raw_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS github_workflow_run (
+    "url" VARCHAR NOT NULL,
+    run_attempt BIGINT NOT NULL,
+    id BIGINT,
+    repo VARCHAR,
+    workflow VARCHAR,
+    branch VARCHAR,
+    status VARCHAR,
+    conclusion VARCHAR,
+    created_at TIMESTAMP,
+    run_started_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY ("url", run_attempt)
+);
+CREATE TABLE IF NOT EXISTS github_deployment (
+    "url" VARCHAR PRIMARY KEY,
+    repo VARCHAR,
+    sha VARCHAR,
+    environment VARCHAR,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    raw_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS github_deployment_status (
+    id BIGINT PRIMARY KEY,
+    deployment_url VARCHAR,
+    state VARCHAR,
+    created_at TIMESTAMP,
+    raw_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS github_release (
+    id BIGINT PRIMARY KEY,
+    repo VARCHAR,
+    draft BOOLEAN,
+    prerelease BOOLEAN,
+    published_at TIMESTAMP,
+    raw_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS github_commit (
+    repo VARCHAR NOT NULL,
+    sha VARCHAR NOT NULL,
+    committed_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY (repo, sha)
+);
+CREATE TABLE IF NOT EXISTS github_issue (
+    "url" VARCHAR PRIMARY KEY,
+    repo VARCHAR,
+    labels VARCHAR[],
+    state VARCHAR,
+    created_at TIMESTAMP,
+    closed_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    raw_id INTEGER NOT NULL
+);"#,
+    },
+    // Create the table of queries saved from the `/query` page
+    Migration {
+        name: "0007_create_wallowa_saved_query",
+        sql: r#"
+CREATE TABLE IF NOT EXISTS wallowa_saved_query (
+    "name" VARCHAR PRIMARY KEY,
+    "sql" VARCHAR NOT NULL,
+    updated_at TIMESTAMP DEFAULT now() NOT NULL
+);"#,
+    },
+    // Add the parents of each commit to `github_commit` so that the commits shipped by a
+    // deployment can be found from its sha. The commits are loaded again to fill them in.
+    Migration {
+        name: "0008_add_github_commit_parents",
+        sql: r#"
+ALTER TABLE github_commit ADD COLUMN parents VARCHAR[];
+UPDATE wallowa_raw_data
+SET loaded_at = NULL
+WHERE "data_source" = 'github_rest_api'
+AND data_type = 'commits';"#,
+    },
+    // Key the GitHub tables of items that only have a numeric `id` by the host as well, since
+    // ids are only unique within one GitHub host. The items are loaded again to fill them in.
+    Migration {
+        name: "0009_key_github_ids_by_host",
+        sql: r#"
+DROP TABLE github_pull_review;
+DROP TABLE github_pull_review_comment;
+DROP TABLE github_deployment_status;
+DROP TABLE github_release;
+CREATE TABLE IF NOT EXISTS github_pull_review (
+    host VARCHAR NOT NULL,
+    id BIGINT NOT NULL,
+    pull_request_url VARCHAR,
+    reviewer VARCHAR,
+    state VARCHAR,
+    submitted_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY (host, id)
+);
+CREATE TABLE IF NOT EXISTS github_pull_review_comment (
+    host VARCHAR NOT NULL,
+    id BIGINT NOT NULL,
+    pull_request_url VARCHAR,
+    reviewer VARCHAR,
+    created_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY (host, id)
+);
+CREATE TABLE IF NOT EXISTS github_deployment_status (
+    host VARCHAR NOT NULL,
+    id BIGINT NOT NULL,
+    deployment_url VARCHAR,
+    state VARCHAR,
+    created_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY (host, id)
+);
+CREATE TABLE IF NOT EXISTS github_release (
+    host VARCHAR NOT NULL,
+    id BIGINT NOT NULL,
+    repo VARCHAR,
+    draft BOOLEAN,
+    prerelease BOOLEAN,
+    published_at TIMESTAMP,
+    raw_id INTEGER NOT NULL,
+    PRIMARY KEY (host, id)
+);
+UPDATE wallowa_raw_data
+SET loaded_at = NULL
+WHERE "data_source" = 'github_rest_api'
+AND data_type IN ('pull_reviews', 'pull_review_comments', 'deployment_statuses', 'releases');"#,
+    },
+];
 
-    // Start a transaction to wrap all of the migrations
-    let tx = conn.transaction()?;
+const MIGRATION_INDEX_NAME: &str = "migration_index";
+const SETTING_TABLE_NAME: &str = "wallowa_setting";
+const MIGRATION_TABLE_NAME: &str = "wallowa_migration";
+
+/// The state of a migration in a database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    /// The migration has been applied
+    Applied,
+    /// The migration hasn't been applied yet
+    Pending,
+    /// The migration has been applied, but it has changed since
+    Changed,
+    /// The migration has been applied, but it isn't in [`MIGRATIONS`]. The database was
+    /// migrated by a newer version.
+    Unknown,
+}
+
+impl std::fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Changed => "changed",
+            MigrationState::Unknown => "unknown",
+        };
+        f.pad(state)
+    }
+}
+
+/// The status of a migration in a database
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub state: MigrationState,
+    /// When the migration was applied. This is `None` for pending migrations and for
+    /// migrations applied before the history of migrations was recorded.
+    pub applied_at: Option<NaiveDateTime>,
+}
+
+/// A migration recorded as applied to a database
+struct AppliedMigration {
+    name: String,
+    /// The checksum isn't known for migrations applied before the history was recorded
+    checksum: Option<String>,
+    applied_at: Option<NaiveDateTime>,
+}
+
+/// Select the migrations that have been applied to the database from the
+/// `wallowa_migration` table, or from the `migration_index` setting if the database
+/// was migrated before the history of migrations was recorded
+fn applied_migrations(conn: &Connection) -> Result<Vec<AppliedMigration>> {
+    if table_exists(conn, MIGRATION_TABLE_NAME)? {
+        let mut stmt = conn.prepare(&format!(
+            r#"SELECT "name", checksum, applied_at FROM {} ORDER BY "name""#,
+            MIGRATION_TABLE_NAME
+        ))?;
+        let applied = stmt
+            .query_map([], |row| {
+                Ok(AppliedMigration {
+                    name: row.get(0)?,
+                    checksum: Some(row.get(1)?),
+                    applied_at: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(applied);
+    }
+
+    // If the settings table doesn't exist, this is a new database
+    if !table_exists(conn, SETTING_TABLE_NAME)? {
+        return Ok(vec![]);
+    }
+
+    // The settings table exists so lookup the migration index.
+    // If that setting doesn't exist, start with the first migration index.
+    let index_res = conn.query_row(
+        &format!(
+            "SELECT CAST(value as INTEGER) FROM {} WHERE name = ?",
+            SETTING_TABLE_NAME
+        ),
+        [MIGRATION_INDEX_NAME],
+        |row| row.get::<_, usize>(0),
+    );
+    let index = match index_res {
+        Ok(i) => i,
+        Err(e) => {
+            // On any error, log it and default to the first migration index
+            let message = format!("Error loading migration index: {}", e);
+            error!(message);
+            0
+        }
+    };
+
+    // An index past the end of `MIGRATIONS` means the database was migrated by a newer
+    // version, so the extra migrations are named by their number
+    let applied = (0..index)
+        .map(|i| AppliedMigration {
+            name: MIGRATIONS
+                .get(i)
+                .map(|migration| migration.name.to_string())
+                .unwrap_or_else(|| format!("{:04}", i + 1)),
+            checksum: None,
+            applied_at: None,
+        })
+        .collect();
+    Ok(applied)
+}
 
-    // First, check whether the `wallowa_setting` table exists.
-    // If it doesn't exist, default to the first migration index.
-    let settings_exists = tx.query_row(
+/// Whether the table named `table_name` exists
+fn table_exists(conn: &Connection, table_name: &str) -> Result<bool> {
+    let count = conn.query_row(
         r#"
 SELECT COUNT(table_name)
 FROM information_schema.tables
 WHERE table_name = ?"#,
-        [SETTING_TABLE_NAME],
+        [table_name],
         |row| row.get::<_, usize>(0),
     )?;
+    Ok(count > 0)
+}
 
-    let index = if settings_exists == 0 {
-        // The settings table doesn't exist so start with the first migration index
-        0
-    } else {
-        // The settings table exists so lookup the migration index.
-        // If that setting doesn't exist, start with the first migration index.
-        let index_res = tx.query_row(
-            &format!(
-                "SELECT CAST(value as INTEGER) FROM {} WHERE name = ?",
-                SETTING_TABLE_NAME
-            ),
-            [MIGRATION_INDEX_NAME],
-            |row| row.get::<_, usize>(0),
-        );
-        match index_res {
-            Ok(i) => i,
-            Err(e) => {
-                // On any error, log it and default to the first migration index
-                let message = format!("Error loading migration index: {}", e);
-                error!(message);
-                0
+/// The status of each migration in [`MIGRATIONS`] in the database, followed by any
+/// migrations in the database that aren't in [`MIGRATIONS`]
+pub fn migration_status(conn: &Connection) -> Result<Vec<MigrationStatus>> {
+    let applied = applied_migrations(conn)?;
+
+    let mut statuses: Vec<MigrationStatus> = MIGRATIONS
+        .iter()
+        .map(|migration| {
+            match applied
+                .iter()
+                .find(|applied| applied.name == migration.name)
+            {
+                Some(applied) => MigrationStatus {
+                    name: migration.name.to_string(),
+                    state: match &applied.checksum {
+                        Some(checksum) if *checksum != migration.checksum() => {
+                            MigrationState::Changed
+                        }
+                        _ => MigrationState::Applied,
+                    },
+                    applied_at: applied.applied_at,
+                },
+                None => MigrationStatus {
+                    name: migration.name.to_string(),
+                    state: MigrationState::Pending,
+                    applied_at: None,
+                },
             }
-        }
+        })
+        .collect();
+    statuses.extend(
+        applied
+            .into_iter()
+            .filter(|applied| !MIGRATIONS.iter().any(|m| m.name == applied.name))
+            .map(|applied| MigrationStatus {
+                name: applied.name,
+                state: MigrationState::Unknown,
+                applied_at: applied.applied_at,
+            }),
+    );
+    Ok(statuses)
+}
+
+/// The migrations that haven't been run on the given database yet, in the order they run
+///
+/// # Errors
+///
+/// Returns an error if the database has migrations that aren't in [`MIGRATIONS`] or if any
+/// applied migration has changed since it was applied.
+pub fn pending_migrations(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let statuses = migration_status(conn)?;
+
+    let names_in_state = |state| {
+        statuses
+            .iter()
+            .filter(|status| status.state == state)
+            .map(|status| format!("`{}`", status.name))
+            .collect::<Vec<_>>()
     };
+    let unknown = names_in_state(MigrationState::Unknown);
+    if !unknown.is_empty() {
+        bail!(
+            "The database has migrations that this version of wallowa doesn't know about ({}). \
+             It was migrated by a newer version of wallowa. Upgrade wallowa to use this database.",
+            unknown.join(", ")
+        );
+    }
+    let changed = names_in_state(MigrationState::Changed);
+    if !changed.is_empty() {
+        bail!(
+            "The migrations {} have changed since they were applied to the database. \
+             Use the version of wallowa that applied them.",
+            changed.join(", ")
+        );
+    }
 
-    if index == migrations.len() {
-        debug!("No migrations to run");
-        return Ok(());
+    Ok(MIGRATIONS
+        .iter()
+        .zip(&statuses)
+        .filter(|(_, status)| status.state == MigrationState::Pending)
+        .map(|(migration, _)| migration)
+        .collect())
+}
+
+/// Run all migrations that have not yet been run on the given database, returning the
+/// names of the migrations that were run. Each migration is recorded in the
+/// `wallowa_migration` table along with its checksum.
+///
+/// # Errors
+///
+/// Returns an error without running any migrations if the database has migrations that
+/// aren't in [`MIGRATIONS`] or if any applied migration has changed since it was applied.
+pub fn run_migrations(conn: &mut Connection) -> Result<Vec<&'static str>> {
+    debug!("Running migrations");
+
+    // Start a transaction to wrap all of the migrations
+    let tx = conn.transaction()?;
+
+    let pending = pending_migrations(&tx)?;
+
+    if !table_exists(&tx, MIGRATION_TABLE_NAME)? {
+        // Record the migrations applied before the history of migrations was recorded
+        let applied = applied_migrations(&tx)?;
+        tx.execute_batch(&format!(
+            r#"
+CREATE TABLE IF NOT EXISTS {} (
+    "name" VARCHAR PRIMARY KEY,
+    checksum VARCHAR NOT NULL,
+    applied_at TIMESTAMP
+);"#,
+            MIGRATION_TABLE_NAME
+        ))?;
+        for migration in MIGRATIONS
+            .iter()
+            .filter(|migration| applied.iter().any(|applied| applied.name == migration.name))
+        {
+            tx.execute(
+                &format!(
+                    r#"INSERT INTO {} ("name", checksum) VALUES (?, ?)"#,
+                    MIGRATION_TABLE_NAME
+                ),
+                [migration.name, &migration.checksum()],
+            )?;
+        }
     }
 
-    if index > migrations.len() {
-        error!("Unexpected state: `migration_index` of {} is greater than the count of migrations in this version of {}. Possible reasons are running an older version than this database has been used with, a manual update to the `migration_index` value in `setting` table, or something else.",
-            index, migrations.len());
+    if pending.is_empty() {
+        debug!("No migrations to run");
+        tx.commit()?;
+        return Ok(vec![]);
     }
 
-    // If 'wallowa_setting' table exists, then run the migrations based on the latest migration version value parsed as a u64.
-    // If 'wallawa_setting' table doesn't exist, then assume this is a new database, create the 'wallowa_setting' table, insert the migration version row, and then run migrations.
-    for migration in &migrations[index..] {
-        debug!(migration = migration, "Running migration `{}`", migration);
-        tx.execute_batch(migration)?;
+    for migration in &pending {
+        info!("Running migration `{}`", migration.name);
+        debug!(
+            migration = migration.sql,
+            "Running migration `{}`", migration.name
+        );
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            &format!(
+                r#"INSERT INTO {} ("name", checksum, applied_at) VALUES (?, ?, now())"#,
+                MIGRATION_TABLE_NAME
+            ),
+            [migration.name, &migration.checksum()],
+        )?;
     }
 
-    // Update the `migration_index` to the latest value
+    // Keep the `migration_index` up to date for older versions, which only read it
     let update_res = tx.execute(
         &format!(
             r#"
@@ -131,21 +654,21 @@ WHERE "name" = ?;
 "#,
             SETTING_TABLE_NAME
         ),
-        [&migrations.len().to_string(), MIGRATION_INDEX_NAME],
+        [&MIGRATIONS.len().to_string(), MIGRATION_INDEX_NAME],
     );
     match update_res {
         Ok(updated) if updated > 0 => debug!(
             "Updated `{}.{}` to {}",
             SETTING_TABLE_NAME,
             MIGRATION_INDEX_NAME,
-            migrations.len()
+            MIGRATIONS.len()
         ),
         Ok(_) => {
             let msg = format!(
                 "Failed to update `{}.{}` to value of {}",
                 SETTING_TABLE_NAME,
                 MIGRATION_INDEX_NAME,
-                migrations.len()
+                MIGRATIONS.len()
             );
             error!(msg);
         }
@@ -154,7 +677,7 @@ WHERE "name" = ?;
                 "Failed to update `{}.{}` to value of {} with error {}",
                 SETTING_TABLE_NAME,
                 MIGRATION_INDEX_NAME,
-                migrations.len(),
+                MIGRATIONS.len(),
                 err
             );
             error!(msg);
@@ -162,9 +685,10 @@ WHERE "name" = ?;
     }
 
     // Commit the transaction
-    Ok(tx.commit()?)
-}
+    tx.commit()?;
 
+    Ok(pending.iter().map(|migration| migration.name).collect())
+}
 
 pub fn add(left: usize, right: usize) -> usize {
     left + right
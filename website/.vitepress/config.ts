@@ -49,7 +49,13 @@ export default defineConfig({
                 text: 'Data sources', link: '/docs/sources/',
                 collapsed: false,
                 items: [
-                  { text: 'GitHub', link: '/docs/sources/github' }
+                  { text: 'GitHub', link: '/docs/sources/github' },
+                  { text: 'GitLab', link: '/docs/sources/gitlab' },
+                  { text: 'Gitea', link: '/docs/sources/gitea' },
+                  { text: 'Local git', link: '/docs/sources/git' },
+                  { text: 'Jira', link: '/docs/sources/jira' },
+                  { text: 'Incidents', link: '/docs/sources/incidents' },
+                  { text: 'Pushed events', link: '/docs/sources/ingest' }
                 ]
               },
               { text: 'CLI', link: '/docs/cli' },